@@ -0,0 +1,125 @@
+/*
+ * Minimal stand-in for Chart.js, implementing the subset of its API the report uses:
+ * `new Chart(canvas, {type, data: {labels, datasets}, options})` with `line`, `bar` and
+ * `scatter` charts, `{x, y}` points or `labels`, and `options.scales.x.ticks.callback`.
+ * Replace this file with `chart.umd.min.js` from Chart.js to get the full library.
+ */
+(function (global) {
+  "use strict";
+
+  var PALETTE = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b"];
+  var PAD = { left: 60, right: 16, top: 28, bottom: 40 };
+
+  function points(dataset) {
+    return (dataset.data || []).map(function (p, i) {
+      return typeof p === "object" ? p : { x: i, y: p };
+    });
+  }
+
+  function extent(values) {
+    var min = Infinity, max = -Infinity;
+    values.forEach(function (v) {
+      if (v < min) min = v;
+      if (v > max) max = v;
+    });
+    if (min === Infinity) return [0, 1];
+    if (min === max) return [min - 1, max + 1];
+    return [min, max];
+  }
+
+  function Chart(canvas, config) {
+    this.canvas = canvas;
+    this.type = config.type;
+    this.data = config.data || { datasets: [] };
+    this.options = config.options || {};
+    this.draw();
+  }
+
+  Chart.prototype.draw = function () {
+    var self = this;
+    var canvas = this.canvas;
+    var ctx = canvas.getContext("2d");
+    var ratio = global.devicePixelRatio || 1;
+    var width = canvas.clientWidth || 800, height = canvas.clientHeight || 300;
+    canvas.width = width * ratio;
+    canvas.height = height * ratio;
+    ctx.setTransform(ratio, 0, 0, ratio, 0, 0);
+    ctx.clearRect(0, 0, width, height);
+    ctx.font = "12px sans-serif";
+
+    var scales = this.options.scales || {};
+    var xTicks = (scales.x && scales.x.ticks) || {};
+    var bar = this.type === "bar";
+    var labels = this.data.labels || [];
+    var series = this.data.datasets.map(points);
+    var all = [].concat.apply([], series);
+    var xs = bar ? [-0.5, labels.length - 0.5] : extent(all.map(function (p) { return p.x; }));
+    var ys = extent(all.map(function (p) { return p.y; }).concat(bar || (scales.y && scales.y.beginAtZero) ? [0] : []));
+
+    var plotW = width - PAD.left - PAD.right, plotH = height - PAD.top - PAD.bottom;
+    function px(x) { return PAD.left + (x - xs[0]) / (xs[1] - xs[0]) * plotW; }
+    function py(y) { return PAD.top + plotH - (y - ys[0]) / (ys[1] - ys[0]) * plotH; }
+
+    // Axes with five ticks each
+    ctx.strokeStyle = "#ccc";
+    ctx.fillStyle = "#555";
+    for (var i = 0; i <= 4; i++) {
+      var y = ys[0] + (ys[1] - ys[0]) * i / 4;
+      ctx.beginPath();
+      ctx.moveTo(PAD.left, py(y));
+      ctx.lineTo(PAD.left + plotW, py(y));
+      ctx.stroke();
+      ctx.textAlign = "right";
+      ctx.fillText(+y.toFixed(2), PAD.left - 6, py(y) + 4);
+    }
+    ctx.textAlign = "center";
+    var xCount = bar ? Math.min(labels.length, 8) : 5;
+    for (var j = 0; j < xCount; j++) {
+      var x, label;
+      if (bar) {
+        var index = Math.round(j * (labels.length - 1) / Math.max(xCount - 1, 1));
+        x = index;
+        label = labels[index];
+      } else {
+        x = xs[0] + (xs[1] - xs[0]) * j / (xCount - 1);
+        label = xTicks.callback ? xTicks.callback(x) : +x.toFixed(2);
+      }
+      ctx.fillText(label, px(x), PAD.top + plotH + 18);
+    }
+
+    // Data
+    var barWidth = plotW / Math.max(labels.length, 1) / (series.length + 1);
+    series.forEach(function (pts, n) {
+      var dataset = self.data.datasets[n];
+      var color = dataset.borderColor || dataset.backgroundColor || PALETTE[n % PALETTE.length];
+      ctx.strokeStyle = ctx.fillStyle = color;
+      if (bar) {
+        pts.forEach(function (p) {
+          var left = px(p.x) - barWidth * series.length / 2 + barWidth * n;
+          ctx.fillRect(left, py(p.y), barWidth, py(ys[0] < 0 ? 0 : ys[0]) - py(p.y));
+        });
+      } else if (self.type === "scatter") {
+        pts.forEach(function (p) {
+          ctx.beginPath();
+          ctx.arc(px(p.x), py(p.y), 3, 0, 2 * Math.PI);
+          ctx.fill();
+        });
+      } else {
+        ctx.beginPath();
+        pts.forEach(function (p, k) {
+          if (k === 0) ctx.moveTo(px(p.x), py(p.y));
+          else ctx.lineTo(px(p.x), py(p.y));
+        });
+        ctx.stroke();
+      }
+      // Legend
+      var lx = PAD.left + n * 160;
+      ctx.fillRect(lx, 8, 12, 12);
+      ctx.fillStyle = "#333";
+      ctx.textAlign = "left";
+      ctx.fillText(dataset.label || "", lx + 16, 18);
+    });
+  };
+
+  global.Chart = global.Chart || Chart;
+})(globalThis);
@@ -0,0 +1,70 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use super::{Alert, AlertKind, Alerter};
+use crate::config::Config;
+
+/// Sends alerts as email over SMTP with TLS
+pub struct EmailAlerter {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Mailbox,
+}
+
+impl EmailAlerter {
+    pub fn new(config: &Config, to: &str) -> Result<Self> {
+        let to: Mailbox = to.parse()?;
+        let from = match &config.alert_from {
+            Some(from) => from.parse()?,
+            None => to.clone(),
+        };
+        let mut transport =
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?.port(config.smtp_port);
+        if let Some(user) = &config.smtp_user {
+            transport = transport.credentials(Credentials::new(
+                user.clone(),
+                config.smtp_password.clone().unwrap_or_default(),
+            ));
+        }
+        Ok(Self {
+            transport: transport.build(),
+            from,
+            to,
+        })
+    }
+}
+
+#[async_trait]
+impl Alerter for EmailAlerter {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(match alert.kind {
+                AlertKind::Outage => format!("con-mon: packet loss to {}", alert.target),
+                AlertKind::Recovered => format!("con-mon: {} recovered", alert.target),
+                AlertKind::PoorQuality { .. } => {
+                    format!("con-mon: poor connection quality to {}", alert.target)
+                }
+            })
+            .body(format!("{}.", alert))?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    async fn verify(&self) -> Result<()> {
+        // Connects and logs in, then quits
+        if self.transport.test_connection().await? {
+            Ok(())
+        } else {
+            Err(anyhow!("The SMTP server didn't accept the connection"))
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+
+mod email;
+mod pagerduty;
+mod slack;
+mod telegram;
+
+pub use email::EmailAlerter;
+pub use pagerduty::PagerDutyAlerter;
+pub use slack::SlackAlerter;
+pub use telegram::TelegramAlerter;
+
+/// Whether an outage started or ended, or the connection got poor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    /// The packet loss went above the threshold
+    Outage,
+    /// The packet loss is back below the threshold
+    Recovered,
+    /// The quality score dropped below the threshold
+    PoorQuality { score: u8 },
+}
+
+/// Packet loss to a target crossing the alert threshold
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub target: String,
+    pub loss_pct: f64,
+    pub threshold: f64,
+    pub avg_latency_ms: f64,
+    pub timestamp: String,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            AlertKind::Outage => write!(
+                f,
+                "Packet loss to {} is {:.2}%, above {}%, with an average latency of {:.1} ms at {}",
+                self.target, self.loss_pct, self.threshold, self.avg_latency_ms, self.timestamp
+            ),
+            AlertKind::Recovered => write!(
+                f,
+                "Packet loss to {} is back to {:.2}%, with an average latency of {:.1} ms at {}",
+                self.target, self.loss_pct, self.avg_latency_ms, self.timestamp
+            ),
+            AlertKind::PoorQuality { score } => write!(
+                f,
+                "Connection quality to {} is {}, below {}, with {:.2}% packet loss \
+                 and an average latency of {:.1} ms at {}",
+                self.target,
+                score,
+                self.threshold,
+                self.loss_pct,
+                self.avg_latency_ms,
+                self.timestamp
+            ),
+        }
+    }
+}
+
+/// A channel alerts are delivered through
+#[async_trait]
+pub trait Alerter: Send + Sync {
+    /// Name used in log messages
+    fn name(&self) -> &'static str;
+
+    async fn send(&self, alert: &Alert) -> Result<()>;
+
+    /// Checks the credentials without sending an alert
+    async fn verify(&self) -> Result<()>;
+}
+
+/// Sends an alert through every configured channel when the packet loss is too high
+pub struct Alerts {
+    alerters: Vec<Arc<dyn Alerter>>,
+    threshold: f64,
+    cooldown: Duration,
+    dry_run: bool,
+    /// When the last outage alert went out for every target
+    last_sent: Mutex<HashMap<String, Instant>>,
+    /// Targets an outage alert was sent for that haven't recovered yet
+    outages: Mutex<HashSet<String>>,
+    /// Quality score below which an alert is sent
+    quality_threshold: u8,
+    /// Targets whose quality is below the threshold
+    poor_quality: Mutex<HashSet<String>>,
+}
+
+impl Alerts {
+    /// Sets up the alerters given on the command line, `None` if there are none
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let mut alerters: Vec<Arc<dyn Alerter>> = Vec::new();
+        if let Some(to) = &config.alert_email {
+            alerters.push(Arc::new(EmailAlerter::new(config, to)?));
+        }
+        if let Some(url) = &config.alert_slack_webhook {
+            alerters.push(Arc::new(SlackAlerter::new(url)));
+        }
+        if let Some(key) = &config.pagerduty_routing_key {
+            alerters.push(Arc::new(PagerDutyAlerter::new(
+                key,
+                Duration::from_secs(config.pagerduty_trigger_minutes * 60),
+            )));
+        }
+        match (&config.telegram_bot_token, config.telegram_chat_id) {
+            (Some(token), Some(chat_id)) => {
+                alerters.push(Arc::new(TelegramAlerter::new(token, chat_id)))
+            }
+            (None, None) => {}
+            _ => {
+                return Err(anyhow!(
+                    "Telegram alerts need both `telegram_bot_token` and `telegram_chat_id`"
+                ))
+            }
+        }
+        if alerters.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            alerters,
+            threshold: config.alert_threshold,
+            cooldown: Duration::from_secs(config.alert_cooldown),
+            dry_run: config.dry_run,
+            last_sent: Mutex::default(),
+            outages: Mutex::default(),
+            quality_threshold: config.quality_threshold,
+            poor_quality: Mutex::default(),
+        }))
+    }
+
+    /// The configured channels
+    pub fn alerters(&self) -> &[Arc<dyn Alerter>] {
+        &self.alerters
+    }
+
+    /// Sends alerts in the background when the loss over the recent pings is too high and the
+    /// cooldown of the target has passed, or when a target an alert was sent for recovers
+    pub fn check(&self, target: &str, loss_pct: f64, avg_latency_ms: f64) {
+        let kind = if loss_pct > self.threshold {
+            // Per target, an outage elsewhere during the cooldown is still alerted about
+            let mut last_sent = self.last_sent.lock().unwrap();
+            if last_sent
+                .get(target)
+                .is_some_and(|sent| sent.elapsed() < self.cooldown)
+            {
+                return;
+            }
+            last_sent.insert(target.to_string(), Instant::now());
+            self.outages.lock().unwrap().insert(target.to_string());
+            AlertKind::Outage
+        } else if self.outages.lock().unwrap().remove(target) {
+            AlertKind::Recovered
+        } else {
+            return;
+        };
+
+        self.send(Alert {
+            kind,
+            target: target.to_string(),
+            loss_pct,
+            threshold: self.threshold,
+            avg_latency_ms,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        });
+    }
+
+    /// Sends an alert in the background when the quality score of a target drops below the
+    /// threshold, once until it is back above
+    pub fn check_quality(&self, target: &str, score: u8, loss_pct: f64, avg_latency_ms: f64) {
+        let mut poor_quality = self.poor_quality.lock().unwrap();
+        if score >= self.quality_threshold {
+            poor_quality.remove(target);
+            return;
+        }
+        if !poor_quality.insert(target.to_string()) {
+            return;
+        }
+        self.send(Alert {
+            kind: AlertKind::PoorQuality { score },
+            target: target.to_string(),
+            loss_pct,
+            threshold: f64::from(self.quality_threshold),
+            avg_latency_ms,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        });
+    }
+
+    /// Sends `alert` through every alerter in the background
+    fn send(&self, alert: Alert) {
+        for alerter in &self.alerters {
+            if self.dry_run {
+                info!("Dry run, would send {} alert: {}", alerter.name(), alert);
+                continue;
+            }
+            let alerter = alerter.clone();
+            let alert = alert.clone();
+            tokio::spawn(async move {
+                match alerter.send(&alert).await {
+                    Ok(()) => debug!("Sent {} alert for {}", alerter.name(), alert.target),
+                    Err(err) => warn!("Couldn't send {} alert: {}", alerter.name(), err),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps the alerts instead of sending them
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Alert>>);
+
+    #[async_trait]
+    impl Alerter for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        async fn send(&self, alert: &Alert) -> Result<()> {
+            self.0.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+
+        async fn verify(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn cooldown_is_per_target() {
+        let recorder = Arc::new(Recorder::default());
+        let alerts = Alerts {
+            alerters: vec![recorder.clone()],
+            threshold: 5.0,
+            cooldown: Duration::from_secs(600),
+            dry_run: false,
+            last_sent: Mutex::default(),
+            outages: Mutex::default(),
+            quality_threshold: 0,
+            poor_quality: Mutex::default(),
+        };
+        alerts.check("1.1.1.1", 50.0, 20.0);
+        alerts.check("1.1.1.1", 60.0, 20.0);
+        alerts.check("8.8.8.8", 50.0, 20.0);
+        alerts.check("8.8.8.8", 0.0, 20.0);
+        // Let the spawned sends run
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let sent: Vec<_> = recorder
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|alert| (alert.target.clone(), alert.kind))
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                ("1.1.1.1".to_string(), AlertKind::Outage),
+                ("8.8.8.8".to_string(), AlertKind::Outage),
+                ("8.8.8.8".to_string(), AlertKind::Recovered),
+            ]
+        );
+    }
+}
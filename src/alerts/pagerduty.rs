@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::{Alert, AlertKind, Alerter};
+
+/// Where PagerDuty events are sent
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+/// State of the PagerDuty incident for a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Incident {
+    /// Waiting for the outage to last long enough, identified by when it started
+    Pending(Instant),
+    Triggered,
+}
+
+/// Opens PagerDuty incidents for outages that last longer than `trigger_after`
+pub struct PagerDutyAlerter {
+    pub routing_key: String,
+    trigger_after: Duration,
+    client: reqwest::Client,
+    incidents: Mutex<HashMap<String, Incident>>,
+}
+
+impl PagerDutyAlerter {
+    pub fn new(routing_key: &str, trigger_after: Duration) -> Self {
+        Self {
+            routing_key: routing_key.to_string(),
+            trigger_after,
+            client: reqwest::Client::new(),
+            incidents: Mutex::default(),
+        }
+    }
+
+    /// Sends an event, the dedup key is stable per target so flapping updates one incident
+    async fn enqueue(&self, action: &str, alert: &Alert) -> Result<()> {
+        self.client
+            .post(PAGERDUTY_EVENTS_URL)
+            .json(&json!({
+                "routing_key": self.routing_key,
+                "event_action": action,
+                "dedup_key": format!("con-mon-{}", alert.target),
+                "payload": {
+                    "summary": alert.to_string(),
+                    "source": alert.target,
+                    "severity": "critical",
+                    "timestamp": alert.timestamp,
+                },
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Alerter for PagerDutyAlerter {
+    fn name(&self) -> &'static str {
+        "PagerDuty"
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        match alert.kind {
+            AlertKind::Outage => {
+                let pending = Incident::Pending(Instant::now());
+                {
+                    let mut incidents = self.incidents.lock().unwrap();
+                    if incidents.contains_key(&alert.target) {
+                        return Ok(());
+                    }
+                    incidents.insert(alert.target.clone(), pending);
+                }
+                tokio::time::sleep(self.trigger_after).await;
+                {
+                    let mut incidents = self.incidents.lock().unwrap();
+                    // Recovered in the meantime, or a newer outage is pending
+                    if incidents.get(&alert.target) != Some(&pending) {
+                        return Ok(());
+                    }
+                    incidents.insert(alert.target.clone(), Incident::Triggered);
+                }
+                self.enqueue("trigger", alert).await
+            }
+            AlertKind::Recovered => {
+                let incident = self.incidents.lock().unwrap().remove(&alert.target);
+                if incident == Some(Incident::Triggered) {
+                    self.enqueue("resolve", alert).await?;
+                }
+                Ok(())
+            }
+            // Incidents are for outages, a slow connection isn't worth paging for
+            AlertKind::PoorQuality { .. } => Ok(()),
+        }
+    }
+
+    async fn verify(&self) -> Result<()> {
+        // The Events API can't check a key without opening an incident, only its format is
+        if self.routing_key.len() == 32
+            && self.routing_key.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            Ok(())
+        } else {
+            Err(anyhow!("The routing key isn't 32 letters and digits"))
+        }
+    }
+}
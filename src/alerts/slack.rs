@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use super::{Alert, AlertKind, Alerter};
+
+/// Posts alerts to a Slack incoming webhook
+pub struct SlackAlerter {
+    pub webhook_url: String,
+    client: reqwest::Client,
+}
+
+impl SlackAlerter {
+    pub fn new(webhook_url: &str) -> Self {
+        Self {
+            webhook_url: webhook_url.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Alerter for SlackAlerter {
+    fn name(&self) -> &'static str {
+        "Slack"
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        let icon = match alert.kind {
+            AlertKind::Outage => ":warning:",
+            AlertKind::Recovered => ":white_check_mark:",
+            AlertKind::PoorQuality { .. } => ":chart_with_downwards_trend:",
+        };
+        self.client
+            .post(&self.webhook_url)
+            .json(&json!({ "text": format!("{} {}", icon, alert) }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn verify(&self) -> Result<()> {
+        // A valid webhook rejects a message without text with 400, an invalid one with 403 or 404
+        let response = self
+            .client
+            .post(&self.webhook_url)
+            .json(&json!({}))
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::BAD_REQUEST => Ok(()),
+            status => Err(anyhow!(
+                "The webhook answered {}: {}",
+                status,
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+}
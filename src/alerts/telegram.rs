@@ -0,0 +1,64 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::{Alert, AlertKind, Alerter};
+
+/// Messages a Telegram chat through the Bot API
+pub struct TelegramAlerter {
+    pub bot_token: String,
+    pub chat_id: i64,
+    client: reqwest::Client,
+}
+
+impl TelegramAlerter {
+    pub fn new(bot_token: &str, chat_id: i64) -> Self {
+        Self {
+            bot_token: bot_token.to_string(),
+            chat_id,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Alerter for TelegramAlerter {
+    fn name(&self) -> &'static str {
+        "Telegram"
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        let title = match alert.kind {
+            AlertKind::Outage => "Outage started",
+            AlertKind::Recovered => "Outage recovered",
+            AlertKind::PoorQuality { .. } => "Poor connection quality",
+        };
+        self.client
+            .post(format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                self.bot_token
+            ))
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": format!("{}: {}", title, alert),
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn verify(&self) -> Result<()> {
+        // Fails for an invalid token and for a chat the bot isn't in
+        self.client
+            .get(format!(
+                "https://api.telegram.org/bot{}/getChat",
+                self.bot_token
+            ))
+            .query(&[("chat_id", self.chat_id)])
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::session::is_session_start;
+use crate::speedtest::SpeedtestResult;
+
+/// Data used by the speedtests of one day, appended after every speedtest
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BandwidthUsage {
+    pub date: String,
+    pub speedtests: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl BandwidthUsage {
+    /// Today's usage so far, taken from the last line of the log if it is from today
+    fn today(path: &Path, date: &str) -> Self {
+        let last = File::open(path).ok().and_then(|file| {
+            BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter(|line| !line.trim().is_empty() && !is_session_start(line))
+                .last()
+        });
+        last.and_then(|line| serde_json::from_str::<Self>(&line).ok())
+            .filter(|usage| usage.date == date)
+            .unwrap_or_else(|| Self {
+                date: date.to_string(),
+                ..Self::default()
+            })
+    }
+
+    pub fn total_mb(&self) -> f64 {
+        (self.bytes_sent + self.bytes_received) as f64 / 1e6
+    }
+}
+
+/// Data used by the speedtests of one calendar month
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MonthlyUsage {
+    /// e.g. `2024-01`
+    pub month: String,
+    pub bytes: u64,
+}
+
+impl MonthlyUsage {
+    /// This month's usage so far, starting over on the 1st
+    fn load(path: &Path) -> Self {
+        let month = Local::now().format("%Y-%m").to_string();
+        File::open(path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, Self>(file).ok())
+            .filter(|usage| usage.month == month)
+            .unwrap_or(Self { month, bytes: 0 })
+    }
+
+    pub fn total_mb(&self) -> f64 {
+        self.bytes as f64 / 1e6
+    }
+}
+
+/// Whether this month's speedtests used up `--monthly-speedtest-budget-mb`
+pub fn budget_exhausted(config: &Config) -> bool {
+    config
+        .monthly_speedtest_budget_mb
+        .is_some_and(|budget| MonthlyUsage::load(&config.monthly_data).total_mb() >= budget)
+}
+
+/// Adds the data used by `result` to this month's total
+fn record_monthly(config: &Config, budget_mb: f64, result: &SpeedtestResult) -> Result<()> {
+    let mut usage = MonthlyUsage::load(&config.monthly_data);
+    usage.bytes += result.bytes_sent + result.bytes_received;
+    config
+        .sinks()
+        .replace(&config.monthly_data, &serde_json::to_vec(&usage)?)?;
+    if usage.total_mb() >= budget_mb {
+        warn!(
+            used_mb = usage.total_mb(),
+            budget_mb,
+            "Speedtests used up this month's data budget, none are scheduled until the 1st"
+        );
+    }
+    Ok(())
+}
+
+/// Adds the data used by `result` to today's total and warns once it is above the cap
+pub fn record(config: &Config, result: &SpeedtestResult) -> Result<()> {
+    let date = Local::now().date_naive().to_string();
+    let mut usage = BandwidthUsage::today(&config.bandwidth_log, &date);
+    usage.speedtests += 1;
+    usage.bytes_sent += result.bytes_sent;
+    usage.bytes_received += result.bytes_received;
+
+    let mut outfile = config.sinks().append(&config.bandwidth_log)?;
+    serde_json::to_writer(&mut outfile, &usage)?;
+    outfile.write_all(b"\n")?;
+    outfile.flush()?;
+
+    if let Some(budget) = config.monthly_speedtest_budget_mb {
+        record_monthly(config, budget, result)?;
+    }
+
+    match config.speedtest_daily_data_cap_mb {
+        Some(cap) if usage.total_mb() > cap => warn!(
+            used_mb = usage.total_mb(),
+            cap_mb = cap,
+            "Speedtests used more data today than the daily cap"
+        ),
+        _ => info!(
+            used_mb = usage.total_mb(),
+            speedtests = usage.speedtests,
+            "Speedtest data used today"
+        ),
+    }
+    Ok(())
+}
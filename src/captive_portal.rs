@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use crate::metrics::SharedMetrics;
+use crate::ping::unix_timestamp;
+
+/// Maximum time to wait for the test URL
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Answer of the test URL
+#[derive(Debug, Serialize)]
+pub struct CaptivePortalResult {
+    pub url: String,
+    pub timestamp: String,
+    pub status_code: u16,
+    /// Where the request ended up if it was redirected
+    pub redirected_to: Option<String>,
+    /// Whether something other than `success` came back
+    pub active: bool,
+}
+
+/// Fetches `test_url`, following redirects, and checks that it answers exactly `success`
+pub async fn captive_portal_check(test_url: &str) -> Result<CaptivePortalResult> {
+    let client = reqwest::Client::builder().timeout(CHECK_TIMEOUT).build()?;
+    let response = client.get(test_url).send().await?;
+    let status_code = response.status().as_u16();
+    let redirected_to = Some(response.url().to_string()).filter(|url| url != test_url);
+    let body = response.text().await?;
+    Ok(CaptivePortalResult {
+        url: test_url.to_string(),
+        timestamp: unix_timestamp(),
+        status_code,
+        active: redirected_to.is_some() || status_code != 200 || body.trim_end() != "success",
+        redirected_to,
+    })
+}
+
+/// Checks for a captive portal every `period`, warning when one shows up
+pub async fn captive_portal_checker(
+    test_url: String,
+    period: Duration,
+    metrics: SharedMetrics,
+) -> Result<()> {
+    let mut iv = interval(period);
+    let mut active = false;
+    loop {
+        iv.tick().await;
+        let result = match captive_portal_check(&test_url).await {
+            Ok(result) => result,
+            // Without an answer there is no telling whether a portal is in the way
+            Err(err) => {
+                debug!("Captive portal check of {} failed: {}", test_url, err);
+                continue;
+            }
+        };
+        debug!("Captive portal check: {:?}", result);
+        if result.active && !active {
+            match &result.redirected_to {
+                Some(portal) => warn!(
+                    "Captive portal detected, {} was redirected to {}",
+                    test_url, portal
+                ),
+                None => warn!(
+                    "Captive portal detected, {} didn't answer `success` but {}",
+                    test_url, result.status_code
+                ),
+            }
+        } else if !result.active && active {
+            info!("Captive portal is gone, {} answers again", test_url);
+        }
+        active = result.active;
+        metrics.lock().unwrap().captive_portal_active = Some(if active { 1.0 } else { 0.0 });
+    }
+}
@@ -0,0 +1,1164 @@
+use std::io::IsTerminal;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::NaiveTime;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+
+use crate::sinks::{DryRunSinks, FileSinks, SinkFactory};
+
+/// Maximum time to wait for ping before restarting
+const PING_TIMEOUT: u64 = 10;
+
+/// Seconds between two batches of pings
+const PING_INTERVAL: u64 = 10;
+
+/// Seconds to wait for the replies to a batch of pings
+const BATCH_TIMEOUT: u64 = 2;
+
+/// Shortest wait in seconds before restarting a pinger
+const RESTART_MIN_DELAY: u64 = 1;
+
+/// Longest wait in seconds before restarting a pinger
+const RESTART_MAX_DELAY: u64 = 60;
+
+/// Seconds a pinger waits at least before it is restarted
+const PING_RESTART_COOLDOWN: u64 = 5;
+
+/// Speedtest interval in seconds
+const SPEEDTEST_INTERVAL: u64 = 30 * 60;
+
+/// Packet loss percentage above which a warning is logged
+const LOSS_THRESHOLD: f64 = 5.0;
+
+/// Recent pings the packet loss is calculated over
+const LOSS_WINDOW: usize = 100;
+
+/// Number of recent pings the jitter is calculated over
+const JITTER_WINDOW: usize = 60;
+
+/// Jitter in milliseconds above which a warning is logged
+const JITTER_THRESHOLD: f64 = 30.0;
+
+/// HTTP probe interval in seconds
+const HTTP_PROBE_INTERVAL: u64 = 60;
+
+/// URL fetched to detect captive portals, answers `success` when there is none
+const CAPTIVE_PORTAL_URL: &str = "http://detectportal.firefox.com/success.txt";
+
+/// Captive portal check interval in seconds
+const CAPTIVE_PORTAL_INTERVAL: u64 = 60;
+
+/// Address whose route is looked up to find the egress interface and gateway
+const ROUTE_CHECK_TARGET: &str = "1.1.1.1";
+
+/// Route check interval in seconds
+const ROUTE_CHECK_INTERVAL: u64 = 60;
+
+/// TCP probe interval in seconds
+const TCP_PROBE_INTERVAL: u64 = 60;
+
+/// DNS probe interval in seconds
+const DNS_PROBE_INTERVAL: u64 = 60;
+
+/// DNS resolution time in milliseconds above which a warning is logged
+const DNS_PROBE_THRESHOLD: f64 = 500.0;
+
+/// Interval between lookups of the ping target hostnames in seconds
+const DNS_CHECK_INTERVAL: u64 = 60;
+
+/// Seconds the latency alarm stays quiet after ringing
+const ALARM_COOLDOWN: u64 = 10;
+
+/// Pings kept in memory for the API
+const PING_BUFFER_SIZE: usize = 1000;
+
+/// WireGuard probe interval in seconds
+const WG_PROBE_INTERVAL: u64 = 60;
+
+/// WireGuard handshake age in seconds above which a warning is logged, three keepalive intervals
+const WG_HANDSHAKE_MAX_AGE: u64 = 180;
+
+/// Packet loss percentage above which an alert is sent
+const ALERT_THRESHOLD: f64 = 10.0;
+
+/// Minimum time in seconds between two alerts
+const ALERT_COOLDOWN: u64 = 3600;
+
+/// Minutes an outage has to last before PagerDuty is triggered
+const PAGERDUTY_TRIGGER_MINUTES: u64 = 5;
+
+/// Number of recent pings the loss during an outage is calculated over
+const OUTAGE_WINDOW: usize = 30;
+
+/// Connection quality score below which an alert is sent
+const QUALITY_THRESHOLD: u8 = 50;
+
+/// Number of recent speedtests the latest one is compared to
+const TREND_WINDOW: usize = 10;
+
+/// Percentage below the recent average at which a slow speedtest is warned about
+const TREND_THRESHOLD: f64 = 20.0;
+
+/// Rows buffered before they are sent to ClickHouse
+const CLICKHOUSE_BATCH_SIZE: usize = 1000;
+
+/// Maximum seconds a row is buffered before it is sent to ClickHouse
+const CLICKHOUSE_FLUSH_INTERVAL: u64 = 5;
+
+/// Pings buffered before they are written to the Parquet file
+const PARQUET_BATCH_SIZE: usize = 1000;
+
+/// Maximum minutes a ping is buffered before it is written to the Parquet file
+const PARQUET_FLUSH_MINUTES: u64 = 5;
+
+/// MQTT client id con-mon connects with
+const MQTT_CLIENT_ID: &str = "con-mon";
+
+/// Prefix of the MQTT topics published to
+const MQTT_TOPIC_PREFIX: &str = "con-mon";
+
+/// Times a failed speedtest is retried
+const SPEEDTEST_RETRIES: u32 = 2;
+
+/// Seconds between speedtest attempts
+const SPEEDTEST_RETRY_DELAY: u64 = 30;
+
+/// Maximum number of hops traced
+const TRACEROUTE_MAX_HOPS: u8 = 30;
+
+/// Kernel log lines added to an outage
+const DMESG_LINES: usize = 20;
+
+/// Percentage of the ping log's lines removed once it is too large
+const LOG_TRIM_PCT: f64 = 10.0;
+
+/// Speedup of replayed pings over their original rate
+const SIMULATE_SPEED: f64 = 1.0;
+
+/// Packet loss percentage over the outage window above which a target is degraded
+const OUTAGE_THRESHOLD: f64 = 50.0;
+
+/// Consecutive lost pings after which a target is degraded
+const DEGRADED_AFTER: u32 = 3;
+
+/// Consecutive lost pings after which a target is offline
+const OFFLINE_AFTER: u32 = 10;
+
+/// Consecutive replies after which a target is online again
+const RECOVER_AFTER: u32 = 3;
+
+/// Fields a config file has to set explicitly
+const REQUIRED_CONFIG_FIELDS: &[&str] = &["ping_target"];
+
+/// Monitors the connection by pinging a host and running periodic speedtests
+#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+#[command(version, about)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub action: Option<Action>,
+
+    /// TOML file to read settings from, command line arguments take precedence
+    #[arg(long)]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// Maximum time in seconds to wait for a ping before restarting
+    #[arg(long, default_value_t = PING_TIMEOUT)]
+    pub ping_timeout: u64,
+
+    /// Shortest wait in seconds before restarting a pinger, doubled after every restart without a reply
+    #[arg(long, default_value_t = RESTART_MIN_DELAY)]
+    pub restart_min_delay: u64,
+
+    /// Longest wait in seconds before restarting a pinger
+    #[arg(long, default_value_t = RESTART_MAX_DELAY)]
+    pub restart_max_delay: u64,
+
+    /// Seconds a pinger waits at least before it is restarted, so a dead link isn't hammered
+    #[arg(long, default_value_t = PING_RESTART_COOLDOWN)]
+    pub ping_restart_cooldown: u64,
+
+    /// Interval between speedtests in seconds
+    #[arg(long, default_value_t = SPEEDTEST_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub speedtest_interval: u64,
+
+    /// Local times of day to run the speedtests at instead of an interval, e.g. `06:00,18:00`
+    #[arg(long, value_delimiter = ',')]
+    pub speedtest_time: Vec<NaiveTime>,
+
+    /// Times a failed speedtest is retried before giving up until the next one
+    #[arg(long, default_value_t = SPEEDTEST_RETRIES)]
+    pub speedtest_retries: u32,
+
+    /// Seconds between speedtest attempts
+    #[arg(long, default_value_t = SPEEDTEST_RETRY_DELAY)]
+    pub speedtest_retry_delay: u64,
+
+    /// Number of recent speedtests the latest one is compared to
+    #[arg(long, default_value_t = TREND_WINDOW)]
+    pub trend_window: usize,
+
+    /// Percentage below the recent average at which a slow speedtest is warned about
+    #[arg(long, default_value_t = TREND_THRESHOLD)]
+    pub trend_threshold: f64,
+
+    /// File the latest speedtest's comparison to the recent ones is written to
+    #[arg(long, default_value = "speed_trend.json")]
+    pub speed_trend: PathBuf,
+
+    /// IP addresses or host names to ping, each one is pinged concurrently
+    #[arg(long, value_delimiter = ',', default_value = "1.1.1.1")]
+    #[serde(deserialize_with = "one_or_many")]
+    pub ping_target: Vec<String>,
+
+    /// File the parsed pings are appended to, rotated daily as e.g. `ping-2024-01-15.log`
+    #[arg(long, default_value = "ping.log")]
+    pub ping_log: PathBuf,
+
+    /// Size the ping log may grow to before its oldest lines are removed, e.g. `50MB`
+    #[arg(long)]
+    pub max_log_size: Option<ByteSize>,
+
+    /// How the ping logs of past days are compressed
+    #[arg(long, value_enum, default_value_t = crate::rotate::Compression::Gzip)]
+    pub log_compression: crate::rotate::Compression,
+
+    /// Percentage of the ping log's lines removed once it exceeds `--max-log-size`
+    #[arg(long, default_value_t = LOG_TRIM_PCT)]
+    pub log_trim_pct: f64,
+
+    /// Network interface pings and HTTP and TCP probes are sent through, e.g. `eth0`
+    #[arg(long)]
+    pub bind_interface: Option<String>,
+
+    /// Interface whose state is watched, pinging is paused while it is down, e.g. `wlan0`
+    #[arg(long)]
+    pub watch_interface: Option<String>,
+
+    /// Interfaces every target is pinged through in parallel, to detect failovers between them
+    #[arg(long, value_delimiter = ',')]
+    pub interfaces: Vec<String>,
+
+    /// How targets are pinged
+    #[arg(long, value_enum, default_value_t = PingBackend::Ping)]
+    pub ping_backend: PingBackend,
+
+    /// `ping` run by `--ping-backend ping`, looked up in the PATH unless it is a path
+    ///
+    /// Hidden, it is there for the tests to run a fake `ping` without changing the PATH.
+    #[arg(long, default_value = "ping", hide = true)]
+    pub ping_command: PathBuf,
+
+    /// Network namespace the ping and fping processes run in, e.g. `/var/run/netns/mynamespace`
+    #[arg(long)]
+    pub netns: Option<PathBuf>,
+
+    /// Same as `--ping-backend native`
+    #[arg(long)]
+    pub native_ping: bool,
+
+    /// Send this many pings at once every `--ping-interval` over a raw ICMP socket, and log
+    /// their statistics to `--batch-log`
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub batch_size: Option<u16>,
+
+    /// Seconds between two batches of pings
+    #[arg(long, default_value_t = PING_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub ping_interval: u64,
+
+    /// Seconds to wait for the replies to a batch of pings
+    #[arg(long, default_value_t = BATCH_TIMEOUT)]
+    pub batch_timeout: u64,
+
+    /// File the statistics of every batch of pings are appended to
+    #[arg(long, default_value = "ping_batches.jsonl")]
+    pub batch_log: PathBuf,
+
+    /// Also ping the gateway of the default route, to tell local problems from those upstream
+    #[arg(long)]
+    pub gateway_ping: bool,
+
+    /// Also ping the hosts advertising this DNS-SD service on the local network, e.g.
+    /// `_http._tcp.local`
+    #[arg(long)]
+    pub mdns_discover: Option<String>,
+
+    /// File the discovered hosts are kept in, so they are pinged again right after a restart
+    #[arg(long, default_value = "discovered_targets.json")]
+    pub discovered_targets: PathBuf,
+
+    /// Run as a container: ping the gateway of the container's network and write to `/data`
+    #[arg(long)]
+    pub docker: bool,
+
+    /// Probe the path MTU to every target on startup and whenever it recovers from an outage
+    #[arg(long)]
+    pub mtu_probe: bool,
+
+    /// Trace the route to a target whenever it becomes degraded or offline
+    #[arg(long)]
+    pub traceroute: bool,
+
+    /// Add the kernel log from when an outage started to its record, to spot OOM kills or driver resets
+    #[arg(long)]
+    pub correlate_dmesg: bool,
+
+    /// Kernel log lines added to an outage
+    #[arg(long, default_value_t = DMESG_LINES)]
+    pub dmesg_lines: usize,
+
+    /// Maximum number of hops traced
+    #[arg(long, default_value_t = TRACEROUTE_MAX_HOPS)]
+    pub traceroute_max_hops: u8,
+
+    /// File the traced routes are appended to, one JSON object per line
+    #[arg(long, default_value = "traceroutes.jsonl")]
+    pub traceroute_log: PathBuf,
+
+    /// Replays the pings of a ping log instead of pinging, e.g. for testing alerts offline
+    #[arg(long)]
+    pub simulate: Option<PathBuf>,
+
+    /// Speedup of the replayed pings over their original rate
+    #[arg(long, default_value_t = SIMULATE_SPEED)]
+    pub simulate_speed: f64,
+
+    /// Format of the lines written to the ping log
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Formats the pings and speedtests are also written in, besides the ping log
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub output_format: Vec<ExtraOutput>,
+
+    /// File the pings are appended to with `--output-format csv`
+    #[arg(long, default_value = "pings.csv")]
+    pub csv_ping_log: PathBuf,
+
+    /// File the speedtests are appended to with `--output-format csv`
+    #[arg(long, default_value = "speedtests.csv")]
+    pub csv_speedtest_log: PathBuf,
+
+    /// File the pings are written to with `--output-format parquet`, one per day as e.g. `ping-2024-01-15.parquet`
+    #[arg(long, default_value = "ping.parquet")]
+    pub parquet_log: PathBuf,
+
+    /// Pings buffered before they are written to the Parquet file
+    #[arg(long, default_value_t = PARQUET_BATCH_SIZE)]
+    pub parquet_batch_size: usize,
+
+    /// Maximum minutes a ping is buffered before it is written to the Parquet file
+    #[arg(long, default_value_t = PARQUET_FLUSH_MINUTES, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub parquet_flush_minutes: u64,
+
+    /// Laplace noise added to the latencies written to files and remote sinks, e.g. `epsilon=0.1,sensitivity=1`, the API and `tail` show the actual ones
+    #[arg(long)]
+    pub export_privacy: Option<crate::privacy::ExportPrivacy>,
+
+    /// Labels added to every record written and to the metrics, e.g. `site=home,isp=comcast`
+    #[arg(long, default_value = "")]
+    pub label: crate::labels::Labels,
+
+    /// Number of recent pings the jitter is calculated over
+    #[arg(long, default_value_t = JITTER_WINDOW)]
+    pub jitter_window: usize,
+
+    /// Jitter in milliseconds above which a warning is logged
+    #[arg(long, default_value_t = JITTER_THRESHOLD)]
+    pub jitter_threshold: f64,
+
+    /// SQLite database the parsed pings are also written to, e.g. `ping.db`
+    #[arg(long)]
+    pub ping_db: Option<PathBuf>,
+
+    /// Packet loss percentage above which a warning is logged
+    #[arg(long, default_value_t = LOSS_THRESHOLD)]
+    pub loss_threshold: f64,
+
+    /// Number of recent pings the packet loss is calculated over
+    #[arg(long, default_value_t = LOSS_WINDOW)]
+    pub loss_window: usize,
+
+    /// Sidecar file the ping counters are persisted to, one per target
+    #[arg(long, default_value = "ping_stats.json")]
+    pub ping_stats: PathBuf,
+
+    /// Number of recent pings the loss during an outage is calculated over
+    #[arg(long, default_value_t = OUTAGE_WINDOW)]
+    pub outage_window: usize,
+
+    /// Packet loss percentage over the outage window above which a target is degraded
+    #[arg(long, default_value_t = OUTAGE_THRESHOLD)]
+    pub outage_threshold: f64,
+
+    /// Consecutive lost pings after which a target is degraded
+    #[arg(long, default_value_t = DEGRADED_AFTER)]
+    pub degraded_after: u32,
+
+    /// Consecutive lost pings after which a target is offline
+    #[arg(long, default_value_t = OFFLINE_AFTER)]
+    pub offline_after: u32,
+
+    /// Consecutive replies after which a degraded or offline target is online again
+    #[arg(long, default_value_t = RECOVER_AFTER)]
+    pub recover_after: u32,
+
+    /// File a latency histogram of every target is appended to each minute, for Grafana's heatmap panel
+    #[arg(long)]
+    pub heatmap_log: Option<PathBuf>,
+
+    /// Upper bounds in milliseconds of the heatmap's latency buckets, the last one is open
+    #[arg(long, value_delimiter = ',', default_value = "5,10,25,50,100,500")]
+    pub heatmap_buckets: Vec<f64>,
+
+    /// File the hourly latency percentiles of every target are appended to
+    #[arg(long, default_value = "hourly_stats.jsonl")]
+    pub hourly_stats_log: PathBuf,
+
+    /// File the outages are appended to once they recovered, one JSON object per line
+    #[arg(long, default_value = "outages.jsonl")]
+    pub outage_log: PathBuf,
+
+    /// Where to measure speed: `speedtest-net`, `fast-com` or `iperf3=<host>[:<port>]`
+    #[arg(long, default_value = "speedtest-net")]
+    pub speedtest_backend: SpeedtestBackend,
+
+    /// Run a single speedtest, print the result as JSON and exit
+    #[arg(long)]
+    pub once: bool,
+
+    /// Check whether a newer release is available and exit
+    #[arg(long)]
+    #[serde(skip)]
+    pub check_update: bool,
+
+    /// Speedtest.net server `speedtest-cli` tests against instead of picking the closest one
+    #[arg(long)]
+    pub speedtest_server_id: Option<u32>,
+
+    /// List the Speedtest.net servers closest to this host and exit
+    #[arg(long)]
+    #[serde(skip)]
+    pub speedtest_list_servers: bool,
+
+    /// Measure speed with the built-in Speedtest.net client instead of `speedtest-cli`
+    #[arg(long)]
+    pub native_speedtest: bool,
+
+    /// Run `speedtest-cli` if the built-in speedtest fails
+    #[arg(long)]
+    pub native_speedtest_fallback: bool,
+
+    /// Add the public IP address, ISP and location from ipinfo.io to every speedtest
+    #[arg(long)]
+    pub geolocation: bool,
+
+    /// ipinfo.io API token, for more lookups than the free rate limit allows
+    #[arg(long)]
+    pub ipinfo_token: Option<String>,
+
+    /// File the speedtest results are appended to, one JSON object per line
+    #[arg(long, default_value = "speedtests.jsonl")]
+    pub speedtest_log: PathBuf,
+
+    /// File the data used by the speedtests is appended to, with a running total per day
+    #[arg(long, default_value = "bandwidth_usage.jsonl")]
+    pub bandwidth_log: PathBuf,
+
+    /// Script run on outage starts, recoveries and finished speedtests, given the details in
+    /// `CONMON_*` environment variables such as `CONMON_EVENT=outage_start`
+    #[arg(long)]
+    pub notify_script: Option<PathBuf>,
+
+    /// Megabytes the speedtests may use per day before a warning is logged
+    #[arg(long)]
+    pub speedtest_daily_data_cap_mb: Option<f64>,
+
+    /// Megabytes the speedtests may use per calendar month, no more are scheduled once used up
+    #[arg(long)]
+    pub monthly_speedtest_budget_mb: Option<f64>,
+
+    /// File the data used by this month's speedtests is kept in
+    #[arg(long, default_value = "monthly_data.json")]
+    pub monthly_data: PathBuf,
+
+    /// URLs to fetch periodically to check HTTP connectivity
+    #[arg(long, value_delimiter = ',')]
+    pub http_probe_url: Vec<String>,
+
+    /// Interval between HTTP probes in seconds
+    #[arg(long, default_value_t = HTTP_PROBE_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub http_probe_interval: u64,
+
+    /// Maximum time in seconds to wait for an HTTP probe
+    #[arg(long, default_value_t = 10)]
+    pub http_probe_timeout: u64,
+
+    /// File the HTTP probe results are appended to
+    #[arg(long, default_value = "http_probes.json")]
+    pub http_probe_log: PathBuf,
+
+    /// Periodically check whether a captive portal intercepts HTTP, which pings don't notice
+    #[arg(long)]
+    pub captive_portal_check: bool,
+
+    /// URL that answers exactly `success` when no captive portal is in the way
+    #[arg(long, default_value = CAPTIVE_PORTAL_URL)]
+    pub captive_portal_url: String,
+
+    /// Interval between captive portal checks in seconds
+    #[arg(long, default_value_t = CAPTIVE_PORTAL_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub captive_portal_interval: u64,
+
+    /// Periodically look up the egress interface and gateway, logging when DHCP or a VPN changes them
+    #[arg(long)]
+    pub route_check: bool,
+
+    /// Address whose route is looked up
+    #[arg(long, default_value = ROUTE_CHECK_TARGET)]
+    pub route_check_target: String,
+
+    /// Interval between route lookups in seconds
+    #[arg(long, default_value_t = ROUTE_CHECK_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub route_check_interval: u64,
+
+    /// File the route changes are appended to
+    #[arg(long, default_value = "route_changes.jsonl")]
+    pub route_change_log: PathBuf,
+
+    /// Addresses to connect to periodically, e.g. `1.1.1.1:443`, for networks that block ICMP
+    #[arg(long, value_delimiter = ',')]
+    pub tcp_probe_target: Vec<SocketAddr>,
+
+    /// Interval between TCP probes in seconds
+    #[arg(long, default_value_t = TCP_PROBE_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub tcp_probe_interval: u64,
+
+    /// Maximum time in seconds to wait for a TCP connection
+    #[arg(long, default_value_t = 10)]
+    pub tcp_probe_timeout: u64,
+
+    /// File the TCP probe results are appended to
+    #[arg(long, default_value = "tcp_probes.jsonl")]
+    pub tcp_probe_log: PathBuf,
+
+    /// Hostnames to resolve periodically to check DNS
+    #[arg(long, value_delimiter = ',')]
+    pub dns_probe_host: Vec<String>,
+
+    /// Resolvers each DNS probe hostname is resolved with
+    #[arg(long, value_delimiter = ',', default_value = "1.1.1.1")]
+    pub dns_resolver: Vec<IpAddr>,
+
+    /// Interval between DNS probes in seconds
+    #[arg(long, default_value_t = DNS_PROBE_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub dns_probe_interval: u64,
+
+    /// Resolution time in milliseconds above which a warning is logged
+    #[arg(long, default_value_t = DNS_PROBE_THRESHOLD)]
+    pub dns_probe_threshold: f64,
+
+    /// File the DNS probe results are appended to
+    #[arg(long, default_value = "dns_probes.jsonl")]
+    pub dns_probe_log: PathBuf,
+
+    /// Periodically resolve the ping targets that are hostnames with the system resolver, which caching could hide failures of
+    #[arg(long)]
+    pub dns_check_host: bool,
+
+    /// Interval between lookups of the ping target hostnames in seconds
+    #[arg(long, default_value_t = DNS_CHECK_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub dns_check_interval: u64,
+
+    /// File changes of what the ping targets resolve to are appended to
+    #[arg(long, default_value = "dns_changes.jsonl")]
+    pub dns_change_log: PathBuf,
+
+    /// WireGuard interfaces whose handshakes are checked periodically, e.g. `wg0`
+    #[arg(long, value_delimiter = ',')]
+    pub wg_interface: Vec<String>,
+
+    /// Interval between WireGuard probes in seconds
+    #[arg(long, default_value_t = WG_PROBE_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub wg_probe_interval: u64,
+
+    /// Handshake age in seconds above which a warning is logged
+    #[arg(long, default_value_t = WG_HANDSHAKE_MAX_AGE)]
+    pub wg_handshake_max_age: u64,
+
+    /// File the WireGuard probe results are appended to
+    #[arg(long, default_value = "wg_probes.jsonl")]
+    pub wg_probe_log: PathBuf,
+
+    /// Only log what would be written instead of touching any file
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// InfluxDB 2 server pings and speedtests are also written to, e.g. `http://localhost:8086`
+    #[arg(long)]
+    pub influx_url: Option<String>,
+
+    /// API token for the InfluxDB server
+    #[arg(long, default_value = "")]
+    pub influx_token: String,
+
+    /// InfluxDB organization to write to
+    #[arg(long, default_value = "")]
+    pub influx_org: String,
+
+    /// InfluxDB bucket to write to
+    #[arg(long, default_value = "con-mon")]
+    pub influx_bucket: String,
+
+    /// ClickHouse HTTP interface pings are also written to, e.g. `http://localhost:8123`
+    #[arg(long)]
+    pub clickhouse_url: Option<String>,
+
+    /// ClickHouse database the ping table is in
+    #[arg(long, default_value = "default")]
+    pub clickhouse_database: String,
+
+    /// ClickHouse table the pings are inserted into
+    #[arg(long, default_value = "pings")]
+    pub clickhouse_table: String,
+
+    /// ClickHouse user, the default user is used if unset
+    #[arg(long)]
+    pub clickhouse_user: Option<String>,
+
+    /// Password of the ClickHouse user
+    #[arg(long, default_value = "")]
+    pub clickhouse_password: String,
+
+    /// Rows buffered before they are sent to ClickHouse
+    #[arg(long, default_value_t = CLICKHOUSE_BATCH_SIZE)]
+    pub clickhouse_batch_size: usize,
+
+    /// Maximum seconds a row is buffered before it is sent to ClickHouse
+    #[arg(long, default_value_t = CLICKHOUSE_FLUSH_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(deserialize_with = "period")]
+    pub clickhouse_flush_interval: u64,
+
+    /// MQTT broker pings and speedtests are also published to, e.g. `127.0.0.1:1883`
+    #[arg(long)]
+    pub mqtt_broker: Option<SocketAddr>,
+
+    /// Client id used with the MQTT broker
+    #[arg(long, default_value = MQTT_CLIENT_ID)]
+    pub mqtt_client_id: String,
+
+    /// Prefix of the MQTT topics, pings are published to `{prefix}/ping/{target}`
+    #[arg(long, default_value = MQTT_TOPIC_PREFIX)]
+    pub mqtt_topic_prefix: String,
+
+    /// Connection quality score from 0 to 100 below which an alert is sent
+    #[arg(long, default_value_t = QUALITY_THRESHOLD, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub quality_threshold: u8,
+
+    /// Address to email alerts to
+    #[arg(long)]
+    pub alert_email: Option<String>,
+
+    /// Sender of the alert emails, defaults to `--alert-email`
+    #[arg(long)]
+    pub alert_from: Option<String>,
+
+    /// Packet loss percentage above which an alert is sent through every configured channel
+    #[arg(long, default_value_t = ALERT_THRESHOLD)]
+    pub alert_threshold: f64,
+
+    /// Minimum time in seconds between two alerts
+    #[arg(long, default_value_t = ALERT_COOLDOWN)]
+    pub alert_cooldown: u64,
+
+    /// Slack incoming webhook URL alerts are posted to
+    #[arg(long)]
+    pub alert_slack_webhook: Option<String>,
+
+    /// Token of the Telegram bot alerts are sent with
+    #[arg(long, requires = "telegram_chat_id")]
+    pub telegram_bot_token: Option<String>,
+
+    /// Telegram chat the bot sends alerts to
+    #[arg(long, requires = "telegram_bot_token")]
+    pub telegram_chat_id: Option<i64>,
+
+    /// PagerDuty Events API v2 routing key outages are reported with
+    #[arg(long)]
+    pub pagerduty_routing_key: Option<String>,
+
+    /// Minutes the packet loss has to stay above the threshold before PagerDuty is triggered
+    #[arg(long, default_value_t = PAGERDUTY_TRIGGER_MINUTES)]
+    pub pagerduty_trigger_minutes: u64,
+
+    /// SMTP server the alert emails are sent through over TLS
+    #[arg(long, default_value = "localhost")]
+    pub smtp_host: String,
+
+    /// Port of the SMTP server
+    #[arg(long, default_value_t = 465)]
+    pub smtp_port: u16,
+
+    /// User to log in to the SMTP server with
+    #[arg(long)]
+    pub smtp_user: Option<String>,
+
+    /// Password to log in to the SMTP server with
+    #[arg(long)]
+    pub smtp_password: Option<String>,
+
+    /// OTLP endpoint pings and speedtests are exported to as spans, e.g. `http://localhost:4317`
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Protocol the OTLP endpoint speaks
+    #[arg(long, value_enum, default_value_t = OtlpProtocol::Grpc)]
+    pub otlp_protocol: OtlpProtocol,
+
+    /// Address to serve Prometheus metrics and the live dashboard on, e.g. `0.0.0.0:9898`
+    #[arg(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Latest pings kept in memory for `/api/pings` on the metrics address and `tail`
+    #[arg(long, default_value_t = PING_BUFFER_SIZE)]
+    pub ping_buffer_size: usize,
+
+    /// Separate address to serve the live dashboard and its WebSocket on, e.g. `0.0.0.0:9899`
+    #[arg(long)]
+    pub ws_addr: Option<SocketAddr>,
+
+    /// Show a live table of the latencies and the loss of every target in the terminal instead of
+    /// the application log, which still goes to the log file
+    #[arg(long)]
+    pub console_display: bool,
+
+    /// Ring the terminal bell when a ping takes longer than this many milliseconds
+    #[arg(long)]
+    pub latency_alarm_ms: Option<f64>,
+
+    /// Seconds the latency alarm stays quiet after ringing
+    #[arg(long, default_value_t = ALARM_COOLDOWN)]
+    pub alarm_cooldown: u64,
+
+    /// Unix socket to stream every ping to as a JSON line, e.g. `/tmp/con-mon.sock`
+    #[arg(long)]
+    pub ping_socket: Option<PathBuf>,
+
+    /// Unix socket accepting the commands `status`, `speedtest now`, `reload`, `shutdown` and `tail <count>`
+    #[arg(long)]
+    pub control_socket: Option<PathBuf>,
+
+    /// File the PID is written to, con-mon refuses to start if the process in it still runs
+    #[arg(long)]
+    pub pid_file: Option<PathBuf>,
+
+    /// Format of the application log
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// How the time of the application log lines is written
+    #[arg(long, value_enum, default_value_t = LogTimestampFormat::Rfc3339)]
+    pub log_timestamp_format: LogTimestampFormat,
+
+    /// When to color the terminal output, `auto` colors a terminal unless `NO_COLOR` is set
+    #[arg(long, value_enum, default_value_t = ColorMode::Auto, global = true)]
+    pub color: ColorMode,
+
+    /// Same as `--color never`
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// File the application log is appended to
+    #[arg(long, default_value = "con_mon.log")]
+    pub log_file: PathBuf,
+}
+
+/// Program or socket the pings are sent with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PingBackend {
+    /// A `ping` process per target
+    Ping,
+    /// A single `fping` process for all targets, which interleaves the probes
+    Fping,
+    /// A raw ICMP socket, needs `CAP_NET_RAW` on Linux
+    Native,
+}
+
+/// Format of the ping log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// `target timestamp ms` per line
+    Text,
+    /// One JSON object per line, including the jitter
+    #[value(alias = "json")]
+    #[serde(alias = "json")]
+    Jsonl,
+}
+
+/// Files the pings and speedtests are written to besides the ping log and `--speedtest-log`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtraOutput {
+    /// `--csv-ping-log` and `--csv-speedtest-log`, each starting with a header row
+    Csv,
+    /// The pings to `--parquet-log`
+    Parquet,
+}
+
+/// Format of the application log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per event, with its fields
+    Json,
+}
+
+/// Time of the application log lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogTimestampFormat {
+    /// UTC with milliseconds, e.g. `2024-01-15T12:00:00.123Z`
+    Rfc3339,
+    /// Milliseconds since the epoch
+    #[value(name = "unix_ms")]
+    UnixMs,
+    /// None, for when journald or supervisord add their own
+    Off,
+}
+
+impl LogTimestampFormat {
+    /// Format of the time for `ChronoUtc`, `None` if the lines have no time
+    pub fn chrono_format(self) -> Option<&'static str> {
+        match self {
+            Self::Rfc3339 => Some("%Y-%m-%dT%H:%M:%S%.3fZ"),
+            Self::UnixMs => Some("%s%3f"),
+            Self::Off => None,
+        }
+    }
+}
+
+/// When the terminal output is colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    Always,
+    Auto,
+    Never,
+}
+
+/// Transport of the OTLP exporter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OtlpProtocol {
+    Grpc,
+    HttpProtobuf,
+}
+
+/// Service the speedtests are run against
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpeedtestBackend {
+    /// Speedtest.net, via `speedtest-cli` or the built-in client
+    SpeedtestNet,
+    /// An `iperf3` server
+    Iperf3 { host: String, port: u16 },
+    /// Netflix' fast.com
+    FastCom,
+}
+
+impl FromStr for SpeedtestBackend {
+    type Err = Error;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.split_once('=') {
+            Some(("iperf3", server)) => {
+                let (host, port) = iperf3_server(server)?;
+                Ok(Self::Iperf3 { host, port })
+            }
+            None if string == "speedtest-net" => Ok(Self::SpeedtestNet),
+            None if string == "fast-com" => Ok(Self::FastCom),
+            _ => Err(anyhow!(
+                "Unknown speedtest backend `{}`, expected `speedtest-net`, \
+                 `fast-com` or `iperf3=<host>[:<port>]`",
+                string
+            )),
+        }
+    }
+}
+
+/// Port `iperf3` servers listen on unless told otherwise
+const IPERF3_PORT: u16 = 5201;
+
+/// Splits `host[:port]` into host and port, IPv6 addresses need brackets to have a port
+fn iperf3_server(server: &str) -> Result<(String, u16)> {
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok((ip.to_string(), IPERF3_PORT));
+    }
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok((addr.ip().to_string(), addr.port()));
+    }
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .with_context(|| format!("Invalid port in iperf3 server `{}`", server))?,
+        ),
+        None => (server, IPERF3_PORT),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(anyhow!("No host in iperf3 server `{}`", server));
+    }
+    Ok((host.to_string(), port))
+}
+
+/// A number of bytes, optionally with a `KB`, `MB` or `GB` suffix in multiples of 1024
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ByteSize(u64);
+
+impl FromStr for ByteSize {
+    type Err = Error;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let string = string.trim();
+        let split = string
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(string.len());
+        let (number, unit) = string.split_at(split);
+        let factor: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" | "KB" => 1 << 10,
+            "M" | "MB" => 1 << 20,
+            "G" | "GB" => 1 << 30,
+            _ => return Err(anyhow!("Unknown size unit `{}` in `{}`", unit, string)),
+        };
+        let number: f64 = number
+            .parse()
+            .with_context(|| format!("`{}` is no size", string))?;
+        Ok(Self((number * factor as f64) as u64))
+    }
+}
+
+impl TryFrom<String> for ByteSize {
+    type Error = Error;
+    fn try_from(string: String) -> Result<Self> {
+        string.parse()
+    }
+}
+
+impl From<ByteSize> for String {
+    fn from(size: ByteSize) -> Self {
+        size.0.to_string()
+    }
+}
+
+/// Reads a list from the config file, where a single value can also be given on its own
+fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Reads a period from the config file, rejecting 0 like `value_parser!(u64).range(1..)` does
+/// on the command line
+fn period<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<u64, D::Error> {
+    match u64::deserialize(deserializer)? {
+        0 => Err(D::Error::custom(
+            "a period of 0 is not allowed, it must be at least 1",
+        )),
+        period => Ok(period),
+    }
+}
+
+/// Commands run instead of the monitor
+#[derive(Debug, Clone, Subcommand)]
+pub enum Action {
+    /// Print aggregated ping statistics from the SQLite database, or the recorded outages
+    Query(crate::query::QueryArgs),
+    /// Write an HTML report with charts of the recorded pings, speedtests and outages
+    Report(crate::report::ReportArgs),
+    /// Convert a ping log to CSV or Parquet
+    Export(crate::export::ExportArgs),
+    /// Print the latest pings from the running monitor or the ping log and follow new ones
+    Tail(crate::tail::TailArgs),
+    /// Write a systemd unit running con-mon with the options given before `install`
+    Install(crate::install::InstallArgs),
+    /// Check the output files, a ping to 127.0.0.1, the sinks and the alerters, exit 1 if any fails
+    SelfTest,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::parse_from(["con-mon"])
+    }
+}
+
+impl Config {
+    /// Parses the command line and layers it over the config file if one is given
+    pub fn load() -> Result<Self> {
+        let matches = Self::command().get_matches();
+        let cli = Self::from_arg_matches(&matches)?;
+        let path = match &cli.config {
+            Some(path) => path.clone(),
+            None => return Ok(cli),
+        };
+
+        let mut merged = serde_json::to_value(Self::from_file(&path)?)?;
+        let overrides = serde_json::to_value(&cli)?;
+        for (key, value) in overrides
+            .as_object()
+            .ok_or(anyhow!("Config is not an object"))?
+        {
+            if matches.value_source(key) == Some(ValueSource::CommandLine) {
+                merged[key] = value.clone();
+            }
+        }
+
+        let mut config: Self = serde_json::from_value(merged)?;
+        config.config = Some(path);
+        config.action = cli.action;
+        config.check_update = cli.check_update;
+        config.speedtest_list_servers = cli.speedtest_list_servers;
+        Ok(config)
+    }
+
+    /// Whether to color what's written to stdout
+    pub fn color(&self) -> bool {
+        match (self.no_color, self.color) {
+            (true, _) | (_, ColorMode::Never) => false,
+            (_, ColorMode::Always) => true,
+            (_, ColorMode::Auto) => {
+                // https://no-color.org: set to anything but empty
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+
+    /// Size the ping log is kept within, if limited
+    pub fn ping_log_limit(&self) -> Option<crate::rotate::SizeLimit> {
+        self.max_log_size.map(|size| crate::rotate::SizeLimit {
+            max_bytes: size.0,
+            trim_pct: self.log_trim_pct,
+        })
+    }
+
+    /// Where output goes, nowhere on a dry run
+    pub fn sinks(&self) -> &'static dyn SinkFactory {
+        if self.dry_run {
+            &DryRunSinks
+        } else {
+            &FileSinks
+        }
+    }
+
+    /// Reads a TOML config file, unset optional fields take their default value
+    fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read config file {}", path.display()))?;
+        let table: toml::Table = content
+            .parse()
+            .with_context(|| format!("Config file {} is not valid TOML", path.display()))?;
+        for field in REQUIRED_CONFIG_FIELDS {
+            if !table.contains_key(*field) {
+                return Err(anyhow!(
+                    "Config file {} is missing required field `{}`",
+                    path.display(),
+                    field
+                ));
+            }
+        }
+        let config: Self = table
+            .try_into()
+            .with_context(|| format!("Config file {} is invalid", path.display()))?;
+        if config
+            .ping_target
+            .iter()
+            .all(|target| target.trim().is_empty())
+        {
+            return Err(anyhow!(
+                "Config file {} has an empty `ping_target`",
+                path.display()
+            ));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_toml(content: &str) -> Result<Config> {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), content).unwrap();
+        Config::from_file(file.path())
+    }
+
+    #[test]
+    fn ping_target_is_one_or_many() {
+        let config = from_toml("ping_target = \"8.8.8.8\"").unwrap();
+        assert_eq!(config.ping_target, ["8.8.8.8"]);
+        let config = from_toml("ping_target = [\"8.8.8.8\", \"1.1.1.1\"]").unwrap();
+        assert_eq!(config.ping_target, ["8.8.8.8", "1.1.1.1"]);
+    }
+
+    #[test]
+    fn iperf3_server_is_host_and_optional_port() {
+        let iperf3 = |host: &str, port| SpeedtestBackend::Iperf3 {
+            host: host.to_string(),
+            port,
+        };
+        let parse = |string: &str| string.parse::<SpeedtestBackend>().unwrap();
+        assert_eq!(
+            parse("iperf3=iperf.example.com"),
+            iperf3("iperf.example.com", 5201)
+        );
+        assert_eq!(
+            parse("iperf3=iperf.example.com:5202"),
+            iperf3("iperf.example.com", 5202)
+        );
+        assert_eq!(parse("iperf3=192.0.2.1:5202"), iperf3("192.0.2.1", 5202));
+        assert_eq!(parse("iperf3=2001:db8::1"), iperf3("2001:db8::1", 5201));
+        assert_eq!(
+            parse("iperf3=[2001:db8::1]:5202"),
+            iperf3("2001:db8::1", 5202)
+        );
+        assert!("iperf3=host:port".parse::<SpeedtestBackend>().is_err());
+        assert!("iperf3=".parse::<SpeedtestBackend>().is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+
+use crate::{live, Shared, Stop};
+
+/// Runs a single command and returns the line answered
+fn execute(command: &str, shared: &Shared, stop: &mpsc::UnboundedSender<Stop>) -> Result<String> {
+    match command.split_whitespace().collect::<Vec<_>>()[..] {
+        ["status"] => Ok(serde_json::to_string(&*shared.metrics.lock().unwrap())?),
+        ["speedtest", "now"] => {
+            shared.speedtest_now.notify_one();
+            Ok("ok".to_string())
+        }
+        ["reload"] => {
+            stop.send(Stop::Reload)?;
+            Ok("ok".to_string())
+        }
+        ["shutdown"] => {
+            stop.send(Stop::Shutdown)?;
+            Ok("ok".to_string())
+        }
+        _ => Err(anyhow!(
+            "Unknown command `{}`, expected `status`, `speedtest now`, `reload`, `shutdown` or `tail <count>`",
+            command
+        )),
+    }
+}
+
+/// Writes the last `count` pings and then every new one as JSON lines until the client goes away
+async fn tail(mut write: OwnedWriteHalf, shared: &Shared, count: usize) -> Result<()> {
+    // The actual latencies, like the console shows
+    let pings = shared.raw_pings.as_ref().or(shared.pings.as_ref());
+    let live = shared.raw_live.as_ref().or(shared.live.as_ref());
+    let (Some(pings), Some(live)) = (pings, live) else {
+        return Err(anyhow!("No pings are kept"));
+    };
+    // Subscribed first so no ping is missed between the two
+    let rx = live.subscribe();
+    let last = pings.lock().unwrap().last(count);
+    for ping in last {
+        write
+            .write_all(format!("{}\n", serde_json::to_string(&ping)?).as_bytes())
+            .await?;
+    }
+    live::stream_lines(write, rx).await;
+    Ok(())
+}
+
+/// Answers the commands of a client, one per line, until it disconnects, or streams the
+/// pings if it asks for `tail`
+async fn handle(stream: UnixStream, shared: Shared, stop: mpsc::UnboundedSender<Stop>) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = line.trim();
+        if command.is_empty() {
+            continue;
+        }
+        info!("Control command `{}`", command);
+        if let ["tail", count] = command.split_whitespace().collect::<Vec<_>>()[..] {
+            match count.parse() {
+                Ok(count) => {
+                    if let Err(err) = tail(write, &shared, count).await {
+                        debug!("Stopped streaming pings: {}", err);
+                    }
+                    return;
+                }
+                Err(err) => {
+                    let answer = format!("error: `{}` is no count: {}\n", count, err);
+                    if write.write_all(answer.as_bytes()).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            }
+        }
+        let answer =
+            execute(command, &shared, &stop).unwrap_or_else(|err| format!("error: {}", err));
+        if write
+            .write_all(format!("{}\n", answer).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Accepts commands on the Unix socket at `path`
+pub async fn control_server(
+    path: &Path,
+    shared: Shared,
+    stop: mpsc::UnboundedSender<Stop>,
+) -> Result<()> {
+    let (listener, _socket) = live::bind_unix(path)?;
+    info!("Accepting commands on {}", path.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        debug!("Control client connected");
+        tokio::spawn(handle(stream, shared.clone(), stop.clone()));
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use futures_util::{pin_mut, StreamExt};
+use mdns::{RecordKind, Response};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use crate::sinks::SinkFactory;
+
+/// Time between two mDNS queries
+const QUERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time after which a service that stopped answering is removed
+const EXPIRY: Duration = Duration::from_secs(90);
+
+/// A host advertising the service, as persisted to the discovered targets file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveredTarget {
+    /// Instance the service is advertised as, e.g. `nas._http._tcp.local`
+    pub name: String,
+    /// Address the host is pinged at
+    pub target: String,
+}
+
+/// A ping target to start or stop pinging
+#[derive(Debug, PartialEq, Eq)]
+pub enum Discovery {
+    Added(String),
+    Removed(String),
+}
+
+/// Reads the targets discovered before the last restart, none if the file is missing
+pub fn load(path: &Path) -> Vec<DiscoveredTarget> {
+    let Ok(contents) = std::fs::read(path) else {
+        return Vec::new();
+    };
+    serde_json::from_slice(&contents).unwrap_or_else(|err| {
+        warn!("Ignoring invalid {}: {}", path.display(), err);
+        Vec::new()
+    })
+}
+
+/// Instance name, address and TTL of the service advertised in `response`
+fn service_of(response: &Response) -> Option<(String, IpAddr, u32)> {
+    let (name, ttl) = response.records().find_map(|record| match &record.kind {
+        RecordKind::PTR(name) => Some((name.clone(), record.ttl)),
+        _ => None,
+    })?;
+    // IPv6 link-local addresses can't be pinged without their interface
+    let v4 = response.records().find_map(|record| match record.kind {
+        RecordKind::A(addr) => Some(IpAddr::V4(addr)),
+        _ => None,
+    });
+    let v6 = response.records().find_map(|record| match record.kind {
+        RecordKind::AAAA(addr) => Some(IpAddr::V6(addr)),
+        _ => None,
+    });
+    Some((name, v4.or(v6)?, ttl))
+}
+
+/// Services seen recently, and when they last answered
+#[derive(Debug, Default)]
+struct Services {
+    seen: BTreeMap<String, (DiscoveredTarget, Instant)>,
+}
+
+impl Services {
+    fn new(known: Vec<DiscoveredTarget>, now: Instant) -> Self {
+        Self {
+            seen: known
+                .into_iter()
+                .map(|target| (target.name.clone(), (target, now)))
+                .collect(),
+        }
+    }
+
+    /// Records an answer of `name` at `now`, a TTL of 0 means the service is going away
+    fn answered(&mut self, name: String, addr: IpAddr, ttl: u32, now: Instant) {
+        if ttl == 0 {
+            if let Some((target, _)) = self.seen.remove(&name) {
+                info!("{} at {} went away", target.name, target.target);
+            }
+            return;
+        }
+        let target = addr.to_string();
+        match self.seen.get(&name) {
+            Some((previous, _)) if previous.target == target => {}
+            Some((previous, _)) => info!("{} moved from {} to {}", name, previous.target, target),
+            None => info!("Discovered {} at {}", name, target),
+        }
+        self.seen
+            .insert(name.clone(), (DiscoveredTarget { name, target }, now));
+    }
+
+    /// Removes the services that didn't answer within `EXPIRY` of `now`
+    fn expire(&mut self, now: Instant) {
+        self.seen.retain(|_, (target, seen)| {
+            let alive = now.duration_since(*seen) < EXPIRY;
+            if !alive {
+                info!("{} at {} stopped answering", target.name, target.target);
+            }
+            alive
+        });
+    }
+
+    /// Addresses to ping, several services may be on the same host
+    fn targets(&self) -> BTreeSet<String> {
+        self.seen
+            .values()
+            .map(|(target, _)| target.target.clone())
+            .collect()
+    }
+
+    fn persisted(&self) -> Vec<&DiscoveredTarget> {
+        self.seen.values().map(|(target, _)| target).collect()
+    }
+}
+
+/// Queries `service` over mDNS and tells `events` about hosts to start and stop pinging, keeping
+/// them in `path`
+///
+/// The targets in `path` are started right away and removed unless they answer again.
+pub async fn discover(
+    service: String,
+    path: PathBuf,
+    sinks: &'static dyn SinkFactory,
+    events: mpsc::UnboundedSender<Discovery>,
+) -> Result<()> {
+    let mut services = Services::new(load(&path), Instant::now());
+    let mut pinged = BTreeSet::new();
+    let responses = mdns::discover::all(&service, QUERY_INTERVAL)?.listen();
+    pin_mut!(responses);
+    let mut expiry = interval(QUERY_INTERVAL);
+    info!("Discovering {} over mDNS", service);
+    loop {
+        tokio::select! {
+            response = responses.next() => match response {
+                Some(Ok(response)) => match service_of(&response) {
+                    Some((name, addr, ttl)) => services.answered(name, addr, ttl, Instant::now()),
+                    None => debug!("Ignoring mDNS response without an address: {:?}", response),
+                },
+                Some(Err(err)) => warn!("Couldn't receive mDNS responses: {}", err),
+                None => return Err(anyhow!("mDNS discovery of {} stopped", service)),
+            },
+            _ = expiry.tick() => services.expire(Instant::now()),
+        }
+
+        let targets = services.targets();
+        if targets == pinged {
+            continue;
+        }
+        for target in pinged.difference(&targets) {
+            events.send(Discovery::Removed(target.clone()))?;
+        }
+        for target in targets.difference(&pinged) {
+            events.send(Discovery::Added(target.clone()))?;
+        }
+        pinged = targets;
+        if let Err(err) = sinks.replace(&path, &serde_json::to_vec_pretty(&services.persisted())?) {
+            warn!("Couldn't write {}: {}", path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn targets(services: &Services) -> Vec<String> {
+        services.targets().into_iter().collect()
+    }
+
+    #[test]
+    fn tracks_the_addresses_of_the_services() {
+        let now = Instant::now();
+        let mut services = Services::default();
+        services.answered(
+            "nas._http._tcp.local".into(),
+            addr("192.168.1.20"),
+            120,
+            now,
+        );
+        services.answered(
+            "nas-admin._http._tcp.local".into(),
+            addr("192.168.1.20"),
+            120,
+            now,
+        );
+        services.answered(
+            "printer._http._tcp.local".into(),
+            addr("192.168.1.30"),
+            120,
+            now,
+        );
+        assert_eq!(targets(&services), ["192.168.1.20", "192.168.1.30"]);
+
+        // The other service keeps the host pinged
+        services.answered(
+            "nas-admin._http._tcp.local".into(),
+            addr("192.168.1.20"),
+            0,
+            now,
+        );
+        assert_eq!(targets(&services), ["192.168.1.20", "192.168.1.30"]);
+
+        services.answered(
+            "printer._http._tcp.local".into(),
+            addr("192.168.1.31"),
+            120,
+            now,
+        );
+        assert_eq!(targets(&services), ["192.168.1.20", "192.168.1.31"]);
+        assert_eq!(services.persisted().len(), 2);
+    }
+
+    #[test]
+    fn expires_services_that_stop_answering() {
+        let start = Instant::now();
+        let known = vec![DiscoveredTarget {
+            name: "nas._http._tcp.local".to_string(),
+            target: "192.168.1.20".to_string(),
+        }];
+        let mut services = Services::new(known, start);
+        services.answered(
+            "printer._http._tcp.local".into(),
+            addr("192.168.1.30"),
+            120,
+            start + Duration::from_secs(60),
+        );
+
+        services.expire(start + Duration::from_secs(89));
+        assert_eq!(targets(&services), ["192.168.1.20", "192.168.1.30"]);
+        services.expire(start + EXPIRY);
+        assert_eq!(targets(&services), ["192.168.1.30"]);
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use nix::fcntl::OFlag;
+use nix::time::{clock_gettime, ClockId};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    /// `[   12.345678] message` as printed by `dmesg`
+    static ref DMESG_LINE: Regex = Regex::new(r"^\[\s*(\d+)\.(\d+)\]\s?(.*)$").unwrap();
+}
+
+/// A line of the kernel log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelMessage {
+    pub time: DateTime<Utc>,
+    pub message: String,
+}
+
+/// Converts a kernel timestamp, which counts from boot, to wall-clock time
+fn wall_clock(since_boot: Duration) -> Result<DateTime<Utc>> {
+    let boottime = Duration::from(clock_gettime(ClockId::CLOCK_BOOTTIME)?);
+    let ago = chrono::Duration::from_std(boottime.saturating_sub(since_boot))?;
+    Ok(Utc::now() - ago)
+}
+
+/// Parses a `/dev/kmsg` record like `6,1234,5678901,-;message`, the third field being
+/// microseconds since boot
+fn parse_kmsg(record: &str) -> Option<(Duration, String)> {
+    let (prefix, message) = record.split_once(';')?;
+    let micros = prefix.split(',').nth(2)?.parse().ok()?;
+    // Continuation lines of the record follow indented
+    let message = message.lines().next().unwrap_or_default().to_string();
+    Some((Duration::from_micros(micros), message))
+}
+
+/// Reads the kernel log records still buffered in `/dev/kmsg`, one per `read`
+fn read_kmsg(lines: usize) -> io::Result<VecDeque<(Duration, String)>> {
+    let mut kmsg = File::options()
+        .read(true)
+        .custom_flags(OFlag::O_NONBLOCK.bits())
+        .open("/dev/kmsg")?;
+    let mut records = VecDeque::with_capacity(lines + 1);
+    let mut buf = [0u8; 8192];
+    loop {
+        match kmsg.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => {
+                if let Some(record) = parse_kmsg(&String::from_utf8_lossy(&buf[..len])) {
+                    records.push_back(record);
+                    if records.len() > lines {
+                        records.pop_front();
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            // Records overwritten while reading
+            Err(err) if err.raw_os_error() == Some(nix::libc::EPIPE) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(records)
+}
+
+/// Runs `dmesg`, for when `/dev/kmsg` can't be read
+fn read_dmesg(lines: usize) -> Result<VecDeque<(Duration, String)>> {
+    let output = std::process::Command::new("dmesg").output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "dmesg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut records: VecDeque<_> = stdout
+        .lines()
+        .filter_map(|line| {
+            let captures = DMESG_LINE.captures(line)?;
+            let micros: String = captures[2]
+                .chars()
+                .chain("000000".chars())
+                .take(6)
+                .collect();
+            let since_boot = Duration::from_secs(captures[1].parse().ok()?)
+                + Duration::from_micros(micros.parse().ok()?);
+            Some((since_boot, captures[3].to_string()))
+        })
+        .collect();
+    let skip = records.len().saturating_sub(lines);
+    records.drain(..skip);
+    Ok(records)
+}
+
+/// The last `lines` messages of the kernel log
+pub fn recent(lines: usize) -> Result<Vec<KernelMessage>> {
+    let records = match read_kmsg(lines) {
+        Ok(records) => records,
+        Err(_) => read_dmesg(lines)?,
+    };
+    records
+        .into_iter()
+        .map(|(since_boot, message)| {
+            Ok(KernelMessage {
+                time: wall_clock(since_boot)?,
+                message,
+            })
+        })
+        .collect()
+}
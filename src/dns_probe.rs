@@ -0,0 +1,163 @@
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use hickory_resolver::config::{NameServerConfig, ResolverConfig};
+use hickory_resolver::net::runtime::TokioRuntimeProvider;
+use hickory_resolver::Resolver;
+use serde::Serialize;
+use serde_json::json;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use crate::ping::unix_timestamp;
+use crate::sinks::Writer;
+
+/// Outcome of resolving a hostname against a single resolver
+#[derive(Debug, Serialize)]
+pub struct DnsProbeResult {
+    pub hostname: String,
+    pub resolver: IpAddr,
+    pub timestamp: String,
+    pub latency_ms: f64,
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Resolves `hostname` with `resolver`, bypassing any cache
+pub async fn dns_probe(hostname: &str, resolver: IpAddr) -> Result<DnsProbeResult> {
+    let config = ResolverConfig::from_name_servers(vec![NameServerConfig::udp_and_tcp(resolver)]);
+    let mut builder = Resolver::builder_with_config(config, TokioRuntimeProvider::default());
+    builder.options_mut().cache_size = 0;
+    let dns = builder.build()?;
+
+    let timestamp = unix_timestamp();
+    let start = Instant::now();
+    let lookup = dns.lookup_ip(hostname).await?;
+    Ok(DnsProbeResult {
+        hostname: hostname.to_string(),
+        resolver,
+        timestamp,
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        addresses: lookup.iter().collect(),
+    })
+}
+
+/// Resolves every hostname against every resolver each `period` and appends the results to `outfile`
+pub async fn dns_prober(
+    hostnames: Vec<String>,
+    resolvers: Vec<IpAddr>,
+    period: Duration,
+    threshold_ms: f64,
+    mut outfile: Writer,
+) -> Result<()> {
+    let mut iv = interval(period);
+    loop {
+        iv.tick().await;
+        for hostname in &hostnames {
+            for resolver in &resolvers {
+                let record = match dns_probe(hostname, *resolver).await {
+                    Ok(result) => {
+                        debug!("DNS probe: {:?}", result);
+                        if result.latency_ms > threshold_ms {
+                            warn!(
+                                "Resolving {} with {} took {:.1} ms, above {} ms",
+                                hostname, resolver, result.latency_ms, threshold_ms
+                            );
+                        }
+                        serde_json::to_value(&result)?
+                    }
+                    Err(err) => {
+                        warn!("Resolving {} with {} failed: {}", hostname, resolver, err);
+                        json!({
+                            "hostname": hostname,
+                            "resolver": resolver,
+                            "timestamp": unix_timestamp(),
+                            "error": err.to_string(),
+                        })
+                    }
+                };
+                outfile.write_all(format!("{}\n", record).as_bytes())?;
+                outfile.flush()?;
+            }
+        }
+    }
+}
+
+/// A change of what a ping target resolves to with the system resolver
+#[derive(Debug, Serialize)]
+pub struct DnsChange<'a> {
+    pub hostname: &'a str,
+    pub timestamp: String,
+    /// Addresses of the first lookup
+    pub startup: &'a [IpAddr],
+    pub previous: Option<&'a [IpAddr]>,
+    /// `None` if the lookup failed
+    pub addresses: Option<&'a [IpAddr]>,
+    pub error: Option<String>,
+}
+
+/// Resolves `hostname` with the system resolver, sorted so the order doesn't count as a change
+async fn resolve(hostname: &str) -> Result<Vec<IpAddr>> {
+    let mut addresses: Vec<_> = tokio::net::lookup_host((hostname, 0))
+        .await?
+        .map(|addr| addr.ip())
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+    Ok(addresses)
+}
+
+/// Resolves `hostname` every `period`, warning when it fails or resolves to other addresses,
+/// and appends the changes to `outfile`
+pub async fn dns_checker(hostname: String, period: Duration, mut outfile: Writer) -> Result<()> {
+    let mut iv = interval(period);
+    let mut startup: Option<Vec<IpAddr>> = None;
+    // `None` while the lookups fail
+    let mut previous: Option<Vec<IpAddr>> = None;
+    loop {
+        iv.tick().await;
+        let result = resolve(&hostname).await;
+        let Some(first) = &startup else {
+            match result {
+                Ok(addresses) => {
+                    debug!("{} resolves to {:?}", hostname, addresses);
+                    previous = Some(addresses.clone());
+                    startup = Some(addresses);
+                }
+                Err(err) => warn!("Resolving {} failed: {}", hostname, err),
+            }
+            continue;
+        };
+        let (addresses, error) = match result {
+            Ok(addresses) if previous.as_ref() == Some(&addresses) => continue,
+            Ok(addresses) => {
+                warn!(
+                    "{} resolves to {:?} instead of {:?}",
+                    hostname,
+                    addresses,
+                    previous.as_ref().unwrap_or(first)
+                );
+                (Some(addresses), None)
+            }
+            Err(err) => {
+                warn!("Resolving {} failed: {}", hostname, err);
+                if previous.is_none() {
+                    continue;
+                }
+                (None, Some(err.to_string()))
+            }
+        };
+        let change = DnsChange {
+            hostname: &hostname,
+            timestamp: unix_timestamp(),
+            startup: first,
+            previous: previous.as_deref(),
+            addresses: addresses.as_deref(),
+            error,
+        };
+        outfile.write_all(format!("{}\n", serde_json::to_string(&change)?).as_bytes())?;
+        outfile.flush()?;
+        previous = addresses;
+    }
+}
@@ -0,0 +1,24 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Volume the logs are written to when running in a container
+pub const DATA_DIR: &str = "/data";
+
+lazy_static! {
+    static ref CONTAINER_ID: Regex = Regex::new(r"\b[0-9a-f]{64}\b").unwrap();
+}
+
+/// Short ID of the container con-mon runs in, if it can be told
+///
+/// Docker names the cgroup after the container with cgroups v1, with v2 the ID only shows
+/// up in the paths of the files it mounts, such as `/etc/hostname`.
+pub fn container_id() -> Option<String> {
+    ["/proc/self/cgroup", "/proc/self/mountinfo"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .find_map(|content| {
+            CONTAINER_ID
+                .find(&content)
+                .map(|id| id.as_str()[..12].to_string())
+        })
+}
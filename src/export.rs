@@ -0,0 +1,181 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, ValueEnum};
+use flate2::read::GzDecoder;
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+
+use crate::labels::Labels;
+use crate::ping::{epoch_micros, Ping};
+use crate::session::is_session_start;
+
+/// Columns of the exported Parquet file
+const PARQUET_COLUMNS: &str = "
+    REQUIRED INT64 timestamp (TIMESTAMP(MICROS, true));
+    REQUIRED BYTE_ARRAY target (STRING);
+    REQUIRED DOUBLE latency_ms;
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+/// Converts a ping log to CSV or Parquet
+#[derive(Debug, Clone, Args)]
+pub struct ExportArgs {
+    /// Ping log to convert, text or JSONL and optionally gzipped
+    #[arg(long)]
+    input: PathBuf,
+
+    /// File the pings are written to
+    #[arg(long)]
+    output: PathBuf,
+
+    /// Format the pings are written in
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    format: ExportFormat,
+}
+
+/// Reads the pings of a text or JSONL ping log, telling them apart by the first line,
+/// and counts the lines that aren't pings
+fn read_pings(path: &Path) -> Result<(Vec<Ping>, usize)> {
+    let file = File::open(path).with_context(|| format!("Couldn't open {}", path.display()))?;
+    let reader: Box<dyn Read> = if path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut pings = Vec::new();
+    let mut skipped = 0;
+    let mut jsonl = None;
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() || is_session_start(&line) {
+            continue;
+        }
+        let jsonl = *jsonl.get_or_insert_with(|| line.starts_with('{'));
+        let ping = if jsonl {
+            serde_json::from_str(&line).map_err(anyhow::Error::from)
+        } else {
+            line.parse()
+        };
+        match ping {
+            Ok(ping) => pings.push(ping),
+            Err(_) => skipped += 1,
+        }
+    }
+    Ok((pings, skipped))
+}
+
+/// Quotes a CSV field if it needs to be
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_csv(pings: &[Ping], path: &Path) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "timestamp,target,latency_ms")?;
+    for ping in pings {
+        writeln!(
+            out,
+            "{},{},{}",
+            ping.timestamp,
+            csv_field(&ping.target),
+            ping.ms
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes `pings` as a single row group with the columns of `PARQUET_COLUMNS` and one
+/// string column per label
+pub fn write_parquet(pings: &[Ping], labels: &Labels, out: impl Write + Send) -> Result<()> {
+    let timestamps = pings
+        .iter()
+        .map(|ping| epoch_micros(&ping.timestamp))
+        .collect::<Result<Vec<_>>>()?;
+    let targets: Vec<ByteArray> = pings
+        .iter()
+        .map(|ping| ping.target.as_str().into())
+        .collect();
+    let latencies: Vec<f64> = pings.iter().map(|ping| ping.ms).collect();
+
+    let label_columns: String = labels
+        .iter()
+        .map(|(name, _)| format!("    REQUIRED BYTE_ARRAY {} (STRING);\n", name))
+        .collect();
+    let label_values: Vec<Vec<ByteArray>> = labels
+        .iter()
+        .map(|(_, value)| vec![value.as_str().into(); pings.len()])
+        .collect();
+
+    let schema = format!("message ping {{{}{}}}", PARQUET_COLUMNS, label_columns);
+    let schema = Arc::new(parse_message_type(&schema)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(out, schema, properties)?;
+    let mut row_group = writer.next_row_group()?;
+    for column in 0.. {
+        let Some(mut writer) = row_group.next_column()? else {
+            break;
+        };
+        match column {
+            0 => writer
+                .typed::<Int64Type>()
+                .write_batch(&timestamps, None, None)?,
+            1 => writer
+                .typed::<ByteArrayType>()
+                .write_batch(&targets, None, None)?,
+            2 => writer
+                .typed::<DoubleType>()
+                .write_batch(&latencies, None, None)?,
+            _ if column - 3 < label_values.len() => writer.typed::<ByteArrayType>().write_batch(
+                &label_values[column - 3],
+                None,
+                None,
+            )?,
+            _ => {
+                return Err(anyhow!(
+                    "Unexpected column {} in the Parquet schema",
+                    column
+                ))
+            }
+        };
+        writer.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Writes the pings of `args.input` to `args.output` in `args.format`
+pub fn run(args: &ExportArgs) -> Result<()> {
+    let (pings, skipped) = read_pings(&args.input)?;
+    if skipped > 0 {
+        eprintln!("Skipped {} lines that aren't pings", skipped);
+    }
+    match args.format {
+        ExportFormat::Csv => write_csv(&pings, &args.output)?,
+        ExportFormat::Parquet => {
+            write_parquet(&pings, &Labels::default(), File::create(&args.output)?)?
+        }
+    }
+    println!(
+        "Exported {} pings to {}",
+        pings.len(),
+        args.output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use tracing::info;
+
+/// Failover detection shared by the pingers of all interfaces
+pub type SharedFailover = Arc<Mutex<FailoverDetector>>;
+
+/// Compares the packet loss of the monitored interfaces, to tell when traffic
+/// should have moved from one to another
+pub struct FailoverDetector {
+    /// Loss percentage above which an interface is degraded
+    threshold: f64,
+    /// Recent loss of every target, per interface
+    loss: BTreeMap<String, HashMap<String, f64>>,
+    /// Interfaces currently reported as degraded
+    degraded: Vec<String>,
+}
+
+impl FailoverDetector {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            loss: BTreeMap::new(),
+            degraded: Vec::new(),
+        }
+    }
+
+    /// Mean loss over all targets pinged through `interface`
+    fn loss_pct(&self, interface: &str) -> f64 {
+        let targets = &self.loss[interface];
+        targets.values().sum::<f64>() / targets.len() as f64
+    }
+
+    /// Updates the loss to `target` through `interface` and logs when it fails over
+    pub fn record(&mut self, interface: &str, target: &str, loss_pct: f64) {
+        self.loss
+            .entry(interface.to_string())
+            .or_default()
+            .insert(target.to_string(), loss_pct);
+
+        let loss = self.loss_pct(interface);
+        let was_degraded = self.degraded.iter().any(|degraded| degraded == interface);
+        if loss < self.threshold {
+            if was_degraded {
+                self.degraded.retain(|degraded| degraded != interface);
+                info!(
+                    interface,
+                    loss_pct = loss,
+                    "Failover ended: {} healthy again",
+                    interface
+                );
+            }
+            return;
+        }
+        if was_degraded {
+            return;
+        }
+        // Without a healthy interface to move to it is an outage, not a failover
+        let healthy = self
+            .loss
+            .keys()
+            .find(|other| *other != interface && self.loss_pct(other) < self.threshold);
+        if let Some(healthy) = healthy {
+            info!(
+                degraded = interface,
+                healthy = %healthy,
+                loss_pct = loss,
+                "Failover detected: {} degraded, {} healthy",
+                interface,
+                healthy
+            );
+            self.degraded.push(interface.to_string());
+        }
+    }
+}
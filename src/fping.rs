@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::ping::{unix_timestamp, Ping};
+
+lazy_static! {
+    /// `[1700000000.123456] host : [0], 64 bytes, 1.23 ms (1.23 avg, 0% loss)` or
+    /// `host : [1], timed out (1.23 avg, 50% loss)`
+    static ref FPING_LINE: Regex = Regex::new(
+        r"^(?:\[(?P<timestamp>\d+\.\d+)\] )?(?P<host>\S+)\s+: \[(?P<seq>\d+)\], (?:\d+ bytes, (?P<ms>\d+(?:\.\d+)?) ms|timed out)"
+    )
+    .unwrap();
+}
+
+/// A probe of one host reported by `fping`
+#[derive(Debug)]
+pub struct FpingRecord {
+    pub host: String,
+    /// Probes before this one that fping never reported on
+    pub missed: u64,
+    /// The reply, `None` if the probe timed out
+    pub ping: Option<Ping>,
+}
+
+/// Parses the per-probe lines of `fping -D -l`, which interleaves the probes of all hosts
+#[derive(Debug, Default)]
+pub struct FpingParser {
+    /// Sequence number of the last probe of every host
+    last_seq: HashMap<String, u64>,
+}
+
+impl FpingParser {
+    /// Parses a line of output, `None` for the summaries and anything else that isn't a probe
+    pub fn parse(&mut self, line: &str) -> Option<FpingRecord> {
+        let captures = FPING_LINE.captures(line)?;
+        let host = captures["host"].to_string();
+        let seq: u64 = captures["seq"].parse().ok()?;
+        let missed = match self.last_seq.insert(host.clone(), seq) {
+            Some(last) if seq > last => seq - last - 1,
+            _ => 0,
+        };
+        let ping = match captures.name("ms") {
+            Some(ms) => Some(Ping {
+                target: host.clone(),
+                timestamp: captures
+                    .name("timestamp")
+                    .map_or_else(unix_timestamp, |timestamp| timestamp.as_str().to_string()),
+                ms: ms.as_str().parse().ok()?,
+            }),
+            None => None,
+        };
+        Some(FpingRecord { host, missed, ping })
+    }
+}
@@ -0,0 +1,49 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use anyhow::{anyhow, Result};
+
+/// Gateway of the default route, as listed in `/proc/net/route`
+#[cfg(target_os = "linux")]
+pub fn default_gateway() -> Result<IpAddr> {
+    let routes = std::fs::read_to_string("/proc/net/route")?;
+    parse_proc_route(&routes)
+}
+
+/// Gateway of the default route, as listed by `netstat -rn`
+#[cfg(not(target_os = "linux"))]
+pub fn default_gateway() -> Result<IpAddr> {
+    let output = std::process::Command::new("netstat")
+        .args(["-rn", "-f", "inet"])
+        .output()?;
+    parse_netstat(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Finds the default route, whose destination and mask are zero, in the kernel routing table
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_proc_route(routes: &str) -> Result<IpAddr> {
+    for line in routes.lines().skip(1) {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        // Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+        if let [_, "00000000", gateway, _, _, _, _, "00000000", ..] = fields[..] {
+            // Stored in host byte order
+            let gateway = u32::from_str_radix(gateway, 16)?;
+            return Ok(Ipv4Addr::from(gateway.to_le_bytes()).into());
+        }
+    }
+    Err(anyhow!("No default route found"))
+}
+
+/// Finds the `default` line in the routing table printed by `netstat -rn`
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn parse_netstat(table: &str) -> Result<IpAddr> {
+    table
+        .lines()
+        .filter_map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["default", gateway, ..] => gateway.parse().ok(),
+                _ => None,
+            },
+        )
+        .next()
+        .ok_or(anyhow!("No default route found"))
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Describes the public IP address the request comes from
+const IPINFO_URL: &str = "https://ipinfo.io/json";
+
+/// Time the lookup may take, it must not hold up recording the speedtest for long
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a speedtest ran, as far as the public IP address tells
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Geolocation {
+    pub ip: Option<String>,
+    /// Autonomous system and name of the ISP, e.g. `AS3320 Deutsche Telekom AG`
+    pub org: Option<String>,
+    pub city: Option<String>,
+    pub region: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Looks up the public IP address on ipinfo.io, a `token` raises the rate limit
+pub async fn lookup(token: Option<&str>) -> Result<Geolocation> {
+    let mut request = reqwest::Client::new()
+        .get(IPINFO_URL)
+        .timeout(LOOKUP_TIMEOUT);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    Ok(request.send().await?.error_for_status()?.json().await?)
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use reqwest::tls::TlsInfo;
+use serde::Serialize;
+use serde_json::json;
+use tokio::time::interval;
+use tracing::{debug, error, info, warn};
+
+use crate::metrics::SharedMetrics;
+use crate::ping::unix_timestamp;
+use crate::sinks::Writer;
+
+/// Days of certificate validity left below which a warning is logged
+const CERT_WARN_DAYS: i64 = 14;
+
+/// Days of certificate validity left below which an error is logged
+const CERT_ERROR_DAYS: i64 = 3;
+
+/// Outcome of a single HTTP request
+#[derive(Debug, Serialize)]
+pub struct HttpProbeResult {
+    pub url: String,
+    pub timestamp: String,
+    pub status_code: u16,
+    pub latency_ms: f64,
+    pub content_length: Option<u64>,
+    /// When the server certificate expires, for HTTPS URLs
+    pub cert_expires_at: Option<DateTime<Utc>>,
+    pub cert_days_remaining: Option<i64>,
+}
+
+/// Expiry of a DER encoded certificate
+fn cert_expiry(der: &[u8]) -> Option<DateTime<Utc>> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+}
+
+/// Fetches `url`, through `interface` if given, and measures how long it takes until the
+/// whole body arrived
+pub async fn http_probe(
+    url: &str,
+    timeout: Duration,
+    interface: Option<&str>,
+) -> Result<HttpProbeResult> {
+    let mut client = reqwest::Client::builder().timeout(timeout).tls_info(true);
+    if let Some(interface) = interface {
+        client = client.interface(interface);
+    }
+    let client = client.build()?;
+    let timestamp = unix_timestamp();
+    let start = Instant::now();
+    let response = client.get(url).send().await?;
+    let status_code = response.status().as_u16();
+    let cert_expires_at = response
+        .extensions()
+        .get::<TlsInfo>()
+        .and_then(TlsInfo::peer_certificate)
+        .and_then(cert_expiry);
+    let body = response.bytes().await?;
+    Ok(HttpProbeResult {
+        url: url.to_string(),
+        timestamp,
+        status_code,
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        content_length: Some(body.len() as u64),
+        cert_expires_at,
+        cert_days_remaining: cert_expires_at.map(|expires| (expires - Utc::now()).num_days()),
+    })
+}
+
+/// How close a certificate is to expiring
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum CertLevel {
+    Ok,
+    Warn,
+    Error,
+}
+
+/// Logs when the certificate of `url` gets close to expiring, or when it was renewed
+fn check_cert(url: &str, days: i64, levels: &mut HashMap<String, CertLevel>) {
+    let level = if days < CERT_ERROR_DAYS {
+        CertLevel::Error
+    } else if days < CERT_WARN_DAYS {
+        CertLevel::Warn
+    } else {
+        CertLevel::Ok
+    };
+    let previous = levels
+        .insert(url.to_string(), level)
+        .unwrap_or(CertLevel::Ok);
+    if level == previous {
+        return;
+    }
+    match level {
+        CertLevel::Error => error!("Certificate of {} expires in {} days", url, days),
+        CertLevel::Warn => warn!("Certificate of {} expires in {} days", url, days),
+        CertLevel::Ok => info!("Certificate of {} is valid for {} more days", url, days),
+    }
+}
+
+/// Probes all `urls` every `period` and appends the results to `outfile` as JSON lines
+pub async fn http_prober(
+    urls: Vec<String>,
+    period: Duration,
+    timeout: Duration,
+    interface: Option<String>,
+    mut outfile: Writer,
+    metrics: SharedMetrics,
+) -> Result<()> {
+    let mut iv = interval(period);
+    let mut cert_levels = HashMap::new();
+    loop {
+        iv.tick().await;
+        for url in &urls {
+            let record = match http_probe(url, timeout, interface.as_deref()).await {
+                Ok(result) => {
+                    debug!("HTTP probe: {:?}", result);
+                    if let Some(days) = result.cert_days_remaining {
+                        check_cert(url, days, &mut cert_levels);
+                    }
+                    metrics
+                        .lock()
+                        .unwrap()
+                        .http_probe_latency_ms
+                        .insert(url.clone(), result.latency_ms);
+                    serde_json::to_value(&result)?
+                }
+                Err(err) => {
+                    warn!("HTTP probe of {} failed: {}", url, err);
+                    metrics.lock().unwrap().http_probe_latency_ms.remove(url);
+                    json!({
+                        "url": url,
+                        "timestamp": unix_timestamp(),
+                        "error": err.to_string(),
+                    })
+                }
+            };
+            outfile.write_all(format!("{}\n", record).as_bytes())?;
+            outfile.flush()?;
+        }
+    }
+}
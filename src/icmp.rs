@@ -0,0 +1,226 @@
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::interface::bind_to_interface;
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+
+/// Bytes of payload sent with every echo request
+const PAYLOAD_SIZE: usize = 56;
+
+/// Sequence number of the next echo request, shared by all targets
+static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+/// Internet checksum as used by ICMPv4
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|chunk| u32::from(u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Opens a raw ICMP socket, which needs `CAP_NET_RAW` on Linux
+fn open_socket(target: IpAddr) -> Result<Socket> {
+    let (domain, protocol) = match target {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+    Socket::new(domain, Type::RAW, Some(protocol)).context(
+        "Couldn't open raw ICMP socket, on Linux run as root or grant the binary \
+         CAP_NET_RAW with `setcap cap_net_raw+ep`",
+    )
+}
+
+/// Builds an echo request, the kernel fills in the checksum for ICMPv6
+fn echo_request(target: IpAddr, id: u16, seq: u16) -> Vec<u8> {
+    let kind = match target {
+        IpAddr::V4(_) => ECHO_REQUEST_V4,
+        IpAddr::V6(_) => ECHO_REQUEST_V6,
+    };
+    let mut packet = vec![kind, 0, 0, 0];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend((0..PAYLOAD_SIZE).map(|i| i as u8));
+    if target.is_ipv4() {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    packet
+}
+
+/// Sequence number of `packet` if it is a reply to one of our requests, raw IPv4 sockets
+/// include the IP header
+fn reply_seq(target: IpAddr, packet: &[u8], id: u16) -> Option<u16> {
+    let (icmp, kind) = match target {
+        IpAddr::V4(_) => {
+            let header_len = usize::from(packet.first().map_or(0, |b| b & 0x0f)) * 4;
+            (packet.get(header_len..).unwrap_or_default(), ECHO_REPLY_V4)
+        }
+        IpAddr::V6(_) => (packet, ECHO_REPLY_V6),
+    };
+    (icmp.len() >= 8 && icmp[0] == kind && icmp[4..6] == id.to_be_bytes())
+        .then(|| u16::from_be_bytes([icmp[6], icmp[7]]))
+}
+
+/// Opens the socket for pinging `target`, bound to `interface` if given
+fn open_bound_socket(target: IpAddr, interface: Option<&str>) -> Result<Socket> {
+    let socket = open_socket(target)?;
+    if let Some(interface) = interface {
+        bind_to_interface(&socket, interface, target)
+            .with_context(|| format!("Couldn't bind to interface {}", interface))?;
+    }
+    Ok(socket)
+}
+
+fn ping_blocking(target: IpAddr, timeout: Duration, interface: Option<&str>) -> Result<f64> {
+    let socket = open_bound_socket(target, interface)?;
+    let id = std::process::id() as u16;
+    let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+
+    let start = Instant::now();
+    socket.send_to(
+        &echo_request(target, id, seq),
+        &SocketAddr::new(target, 0).into(),
+    )?;
+
+    let mut buf = [0u8; 1500];
+    loop {
+        let remaining = timeout
+            .checked_sub(start.elapsed())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::TimedOut))?;
+        socket.set_read_timeout(Some(remaining))?;
+        let len = (&socket).read(&mut buf)?;
+        if reply_seq(target, &buf[..len], id) == Some(seq) {
+            return Ok(start.elapsed().as_secs_f64() * 1000.0);
+        }
+    }
+}
+
+/// Sends a single ICMP echo request, through `interface` if given, and returns the round trip
+/// time in milliseconds
+pub async fn native_ping(
+    target: IpAddr,
+    timeout: Duration,
+    interface: Option<String>,
+) -> Result<f64> {
+    tokio::task::spawn_blocking(move || ping_blocking(target, timeout, interface.as_deref()))
+        .await
+        .map_err(|err| anyhow!("Ping task failed: {}", err))?
+}
+
+/// Ping statistics of a batch of echo requests sent in quick succession
+#[derive(Debug, Serialize)]
+pub struct PingBatch {
+    pub target: String,
+    pub timestamp: String,
+    pub sent: usize,
+    pub lost_count: usize,
+    pub min_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+}
+
+impl PingBatch {
+    /// Sums up the round trip times of a batch, `None` for the requests that got no reply
+    pub fn new(target: &str, timestamp: String, rtts: &[Option<f64>]) -> Self {
+        let replies: Vec<f64> = rtts.iter().flatten().copied().collect();
+        Self {
+            target: target.to_string(),
+            timestamp,
+            sent: rtts.len(),
+            lost_count: rtts.len() - replies.len(),
+            min_ms: replies.iter().copied().reduce(f64::min),
+            max_ms: replies.iter().copied().reduce(f64::max),
+            avg_ms: (!replies.is_empty())
+                .then(|| replies.iter().sum::<f64>() / replies.len() as f64),
+        }
+    }
+}
+
+fn ping_batch_blocking(
+    target: IpAddr,
+    count: u16,
+    timeout: Duration,
+    interface: Option<&str>,
+) -> Result<Vec<Option<f64>>> {
+    let socket = open_bound_socket(target, interface)?;
+    let id = std::process::id() as u16;
+    let first = SEQUENCE.fetch_add(count, Ordering::Relaxed);
+
+    let mut sent_at = Vec::with_capacity(usize::from(count));
+    for i in 0..count {
+        sent_at.push(Instant::now());
+        socket.send_to(
+            &echo_request(target, id, first.wrapping_add(i)),
+            &SocketAddr::new(target, 0).into(),
+        )?;
+    }
+
+    let mut rtts = vec![None; usize::from(count)];
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; 1500];
+    while rtts.iter().any(Option::is_none) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let len = match (&socket).read(&mut buf) {
+            Ok(len) => len,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                break
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let Some(seq) = reply_seq(target, &buf[..len], id) else {
+            continue;
+        };
+        let index = usize::from(seq.wrapping_sub(first));
+        if let Some(rtt @ None) = rtts.get_mut(index) {
+            *rtt = Some(sent_at[index].elapsed().as_secs_f64() * 1000.0);
+        }
+    }
+    Ok(rtts)
+}
+
+/// Sends `count` echo requests right after each other and waits up to `timeout` for the
+/// replies, returning the round trip time of each request in milliseconds if it got one
+pub async fn native_ping_batch(
+    target: IpAddr,
+    count: u16,
+    timeout: Duration,
+    interface: Option<String>,
+) -> Result<Vec<Option<f64>>> {
+    tokio::task::spawn_blocking(move || {
+        ping_batch_blocking(target, count, timeout, interface.as_deref())
+    })
+    .await
+    .map_err(|err| anyhow!("Ping task failed: {}", err))?
+}
+
+/// Whether the error only means that no reply arrived in time
+pub fn is_timeout(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<io::Error>().map(io::Error::kind),
+        Some(io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+    )
+}
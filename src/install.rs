@@ -0,0 +1,109 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+
+/// Writes a systemd unit running con-mon with the options given before `install`
+#[derive(Debug, Clone, Args)]
+pub struct InstallArgs {
+    /// Unit file to write
+    #[arg(long, default_value = "/etc/systemd/system/con-mon.service")]
+    unit: PathBuf,
+
+    /// User the service runs as, the current one if not given
+    #[arg(long)]
+    user: Option<String>,
+
+    /// Seconds systemd waits before restarting con-mon
+    #[arg(long, default_value_t = 5)]
+    restart_sec: u64,
+
+    /// Seconds without a watchdog notification before systemd restarts con-mon
+    #[arg(long)]
+    watchdog: Option<u64>,
+}
+
+/// Quotes an `ExecStart` argument if systemd would otherwise split or expand it
+fn quote(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if escaped.is_empty() || escaped.contains(|c: char| c.is_whitespace() || "\"'\\;".contains(c)) {
+        format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        escaped
+    }
+}
+
+/// The unit running `exec_start` as `user` in `working_directory`
+fn unit_file(args: &InstallArgs, exec_start: &str, user: &str, working_directory: &str) -> String {
+    let mut service = String::new();
+    match args.watchdog {
+        Some(seconds) => {
+            service.push_str("Type=notify\n");
+            service.push_str(&format!("WatchdogSec={}\n", seconds));
+        }
+        None => service.push_str("Type=simple\n"),
+    }
+    format!(
+        "[Unit]
+Description=con-mon connection monitor
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+{service}ExecStart={exec_start}
+Restart=always
+RestartSec={restart_sec}
+User={user}
+WorkingDirectory={working_directory}
+StandardOutput=journal
+StandardError=journal
+
+[Install]
+WantedBy=multi-user.target
+",
+        restart_sec = args.restart_sec,
+    )
+}
+
+/// Writes the unit file, `options` are the command line arguments before `install`
+pub fn run(args: &InstallArgs, options: &[OsString]) -> Result<()> {
+    if args.watchdog.is_some() && cfg!(not(feature = "systemd")) {
+        return Err(anyhow!(
+            "--watchdog needs con-mon built with the `systemd` feature to notify systemd"
+        ));
+    }
+    let exe = std::env::current_exe()?;
+    let exec_start = std::iter::once(exe.into_os_string())
+        .chain(options.iter().cloned())
+        .map(|arg| quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let user = match &args.user {
+        Some(user) => user.clone(),
+        None => {
+            nix::unistd::User::from_uid(nix::unistd::getuid())?
+                .ok_or(anyhow!("The current user has no name"))?
+                .name
+        }
+    };
+    let working_directory = std::env::current_dir()?;
+    let unit = unit_file(
+        args,
+        &exec_start,
+        &user,
+        &quote(&working_directory.to_string_lossy()),
+    );
+    std::fs::write(&args.unit, unit)
+        .with_context(|| format!("Couldn't write {}", args.unit.display()))?;
+    println!("Wrote {}", args.unit.display());
+    let name = args
+        .unit
+        .file_name()
+        .map_or("con-mon.service".into(), |name| name.to_string_lossy());
+    println!(
+        "Run `systemctl daemon-reload && systemctl enable {}` to start it on boot",
+        name
+    );
+    Ok(())
+}
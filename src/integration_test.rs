@@ -0,0 +1,148 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Local;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+use crate::config::Config;
+use crate::ping::{ping_sinks, pinger, TargetState};
+use crate::rotate::dated_path;
+use crate::{ping_loop, Shared};
+
+/// Replies printed by the fake `ping`, in the iputils `-D` format
+const REPLIES: &str = "\
+PING 192.0.2.1 (192.0.2.1) 56(84) bytes of data.
+[1700000000.100000] 64 bytes from 192.0.2.1: icmp_seq=1 ttl=57 time=12.3 ms
+[1700000001.100000] 64 bytes from 192.0.2.1: icmp_seq=2 ttl=57 time=0.045 ms
+[1700000002.100000] 64 bytes from 192.0.2.1: icmp_seq=3 ttl=57 time=1250 ms
+";
+
+/// Writes a `ping` to `dir` that keeps its PID in `ping.pid`, prints `REPLIES` and then runs
+/// `then`
+fn write_fake_ping(dir: &Path, then: &str) {
+    let path = dir.join("ping");
+    std::fs::write(
+        &path,
+        format!(
+            "#!/bin/sh\necho $$ > {}\ncat <<'EOF'\n{}EOF\n{}\n",
+            dir.join("ping.pid").display(),
+            REPLIES,
+            then
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[tokio::test]
+async fn pinger_writes_parsed_replies_to_ping_log() {
+    let dir = tempfile::tempdir().unwrap();
+    write_fake_ping(dir.path(), "exec sleep 60");
+
+    let config = Config {
+        ping_command: dir.path().join("ping"),
+        ping_log: dir.path().join("ping.log"),
+        ping_stats: dir.path().join("ping_stats.json"),
+        ..Config::default()
+    };
+    let shared = Shared::default();
+    let mut state = TargetState::new(&config, "192.0.2.1", None);
+    let addr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    let sinks = ping_sinks(&config, &shared).unwrap();
+
+    // The fake ping never exits, so the pinger runs until the timeout
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        pinger(&config, &mut state, &shared, addr, sinks),
+    )
+    .await;
+    assert!(result.is_err(), "pinger stopped early: {:?}", result);
+
+    let log = dated_path(&config.ping_log, Local::now().date_naive());
+    let log = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(
+        log.lines().collect::<Vec<_>>(),
+        [
+            "192.0.2.1 1700000000.100000 12.3",
+            "192.0.2.1 1700000001.100000 0.045",
+            "192.0.2.1 1700000002.100000 1250",
+        ]
+    );
+    assert_eq!(state.stats.sent, 3);
+    assert_eq!(state.stats.received, 3);
+}
+
+#[tokio::test]
+async fn pinger_stops_when_ping_is_killed() {
+    let dir = tempfile::tempdir().unwrap();
+    // The background sleep keeps the output open, so ping is gone before the pinger reads to
+    // the end of it
+    write_fake_ping(dir.path(), "sleep 2 &\nexec sleep 60");
+
+    let config = Config {
+        ping_command: dir.path().join("ping"),
+        ping_log: dir.path().join("ping.log"),
+        ping_stats: dir.path().join("ping_stats.json"),
+        ..Config::default()
+    };
+    let shared = Shared::default();
+    let mut state = TargetState::new(&config, "192.0.2.1", None);
+    let addr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    let sinks = ping_sinks(&config, &shared).unwrap();
+
+    // Like Ctrl-C, which interrupts ping along with con-mon
+    let pid_file = dir.path().join("ping.pid");
+    let interrupt = async {
+        let pid = loop {
+            match std::fs::read_to_string(&pid_file).map(|pid| pid.trim().parse()) {
+                Ok(Ok(pid)) => break Pid::from_raw(pid),
+                _ => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        };
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        kill(pid, Signal::SIGINT).unwrap();
+    };
+    let (result, ()) = tokio::time::timeout(
+        Duration::from_secs(5),
+        futures_util::future::join(pinger(&config, &mut state, &shared, addr, sinks), interrupt),
+    )
+    .await
+    .expect("pinger kept running");
+    result.unwrap();
+    assert_eq!(state.stats.received, 3);
+}
+
+#[tokio::test]
+async fn ping_loop_restarts_ping_that_exits() {
+    let dir = tempfile::tempdir().unwrap();
+    // Exits right away, before the pinger read all of the output the background sleep keeps open
+    write_fake_ping(dir.path(), "sleep 1 &\nexit 0");
+
+    let config = Config {
+        ping_command: dir.path().join("ping"),
+        ping_log: dir.path().join("ping.log"),
+        ping_stats: dir.path().join("ping_stats.json"),
+        restart_min_delay: 1,
+        ping_restart_cooldown: 0,
+        ..Config::default()
+    };
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        ping_loop(
+            config.clone(),
+            "192.0.2.1".to_string(),
+            None,
+            Shared::default(),
+        ),
+    )
+    .await;
+    assert!(result.is_err(), "ping loop stopped: {:?}", result);
+
+    // Restarted after the first ping printed its replies and exited
+    let log = dated_path(&config.ping_log, Local::now().date_naive());
+    let log = std::fs::read_to_string(&log).unwrap();
+    assert!(log.lines().count() > REPLIES.lines().count(), "{}", log);
+}
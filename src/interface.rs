@@ -0,0 +1,28 @@
+use std::io;
+use std::net::IpAddr;
+
+use socket2::Socket;
+
+/// Makes `socket` send and receive only through `interface`, with `SO_BINDTODEVICE`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn bind_to_interface(socket: &Socket, interface: &str, _target: IpAddr) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+/// Makes `socket` send and receive only through `interface`, with `IP_BOUND_IF`
+#[cfg(target_os = "macos")]
+pub fn bind_to_interface(socket: &Socket, interface: &str, target: IpAddr) -> io::Result<()> {
+    let index = std::num::NonZeroU32::new(nix::net::if_::if_nametoindex(interface)?);
+    match target {
+        IpAddr::V4(_) => socket.bind_device_by_index_v4(index),
+        IpAddr::V6(_) => socket.bind_device_by_index_v6(index),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub fn bind_to_interface(_socket: &Socket, interface: &str, _target: IpAddr) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Can't bind to {} on this platform", interface),
+    ))
+}
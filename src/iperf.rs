@@ -0,0 +1,74 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::speedtest::{ServerInfo, SpeedtestResult};
+
+/// Runs a single `iperf3` test and returns its JSON report
+async fn run_iperf3(host: &str, port: u16, reverse: bool) -> Result<Value> {
+    let mut command = Command::new("iperf3");
+    command
+        .arg("-c")
+        .arg(host)
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("-J")
+        .kill_on_drop(true);
+    if reverse {
+        command.arg("-R");
+    }
+    let output = command.output().await.context("iperf3 not found in PATH")?;
+    let report: Value = serde_json::from_slice(&output.stdout)?;
+    if let Some(err) = report["error"].as_str() {
+        return Err(anyhow!("iperf3 failed: {}", err));
+    }
+    if !output.status.success() {
+        return Err(anyhow!(
+            "iperf3 failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    debug!("iperf3: {}", report);
+    Ok(report)
+}
+
+/// Rate the receiving side of the test measured
+fn received_bps(report: &Value) -> Result<f64> {
+    report["end"]["sum_received"]["bits_per_second"]
+        .as_f64()
+        .ok_or(anyhow!("iperf3 report has no sum_received.bits_per_second"))
+}
+
+/// Measures upload and, in reverse mode, download speed against an `iperf3` server
+pub async fn iperf3_speedtest(host: &str, port: u16) -> Result<SpeedtestResult> {
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+    let upload_report = run_iperf3(host, port, false).await?;
+    let download_report = run_iperf3(host, port, true).await?;
+
+    // Only TCP tests on Linux report the round trip time, in microseconds
+    let ping = upload_report["end"]["streams"][0]["sender"]["mean_rtt"]
+        .as_f64()
+        .map_or(0.0, |rtt| rtt / 1000.0);
+
+    Ok(SpeedtestResult {
+        download: received_bps(&download_report)?,
+        upload: received_bps(&upload_report)?,
+        ping,
+        timestamp,
+        server: ServerInfo {
+            host: format!("{}:{}", host, port),
+            ..ServerInfo::default()
+        },
+        client: None,
+        bytes_sent: upload_report["end"]["sum_sent"]["bytes"]
+            .as_u64()
+            .unwrap_or(0),
+        bytes_received: download_report["end"]["sum_received"]["bytes"]
+            .as_u64()
+            .unwrap_or(0),
+        geolocation: None,
+        extra: Map::new(),
+    })
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Tags of the records written, e.g. `site=home,isp=comcast`, to tell sites apart
+///
+/// Names are Prometheus label names, letters, digits and underscores not starting with a
+/// digit, which every sink accepts as is.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Labels(pub BTreeMap<String, String>);
+
+impl Labels {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+/// Whether `name` is a valid Prometheus label name
+fn is_label_name(name: &str) -> bool {
+    name.chars()
+        .enumerate()
+        .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()))
+        && !name.is_empty()
+}
+
+impl FromStr for Labels {
+    type Err = Error;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut labels = BTreeMap::new();
+        for label in string.split(',').filter(|label| !label.trim().is_empty()) {
+            let (name, value) = label
+                .split_once('=')
+                .ok_or(anyhow!("Label `{}` is not `name=value`", label))?;
+            let name = name.trim();
+            if !is_label_name(name) {
+                return Err(anyhow!(
+                    "Label name `{}` may only have letters, digits and underscores",
+                    name
+                ));
+            }
+            labels.insert(name.to_string(), value.trim().to_string());
+        }
+        Ok(Self(labels))
+    }
+}
+
+impl fmt::Display for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels: Vec<_> = self
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        write!(f, "{}", labels.join(","))
+    }
+}
+
+impl TryFrom<String> for Labels {
+    type Error = Error;
+    fn try_from(string: String) -> Result<Self> {
+        string.parse()
+    }
+}
+
+impl From<Labels> for String {
+    fn from(labels: Labels) -> Self {
+        labels.to_string()
+    }
+}
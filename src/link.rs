@@ -0,0 +1,72 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::watch;
+use tokio::time::interval;
+use tracing::{info, warn};
+
+/// How often the state of the interface is read
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the watched interface is up
+pub type LinkState = watch::Receiver<bool>;
+
+/// Reads the operational state of `interface`, e.g. `up` or `down`
+async fn operstate(interface: &str) -> std::io::Result<String> {
+    let path = format!("/sys/class/net/{}/operstate", interface);
+    Ok(tokio::fs::read_to_string(path).await?.trim().to_string())
+}
+
+/// Polls the state of `interface` every second and tells the pingers when it goes down or up
+///
+/// A missing interface counts as down, and `unknown` as up, which is what tunnels and
+/// dummy interfaces report while they work.
+pub async fn watch_interface(interface: String, up: watch::Sender<bool>) -> Result<()> {
+    let mut iv = interval(POLL_INTERVAL);
+    loop {
+        iv.tick().await;
+        let is_up = match operstate(&interface).await {
+            Ok(state) => !matches!(
+                state.as_str(),
+                "down" | "lowerlayerdown" | "notpresent" | "dormant"
+            ),
+            Err(err) => {
+                if *up.borrow() {
+                    warn!("Couldn't read the state of {}: {}", interface, err);
+                }
+                false
+            }
+        };
+        if up.send_replace(is_up) != is_up {
+            if is_up {
+                info!(%interface, "Interface {} is up, resuming the pingers", interface);
+            } else {
+                info!(%interface, "Interface {} is down, pausing the pingers", interface);
+            }
+        }
+    }
+}
+
+/// Waits until the interface is up, right away if none is watched
+pub async fn wait_until_up(link: &mut Option<LinkState>) {
+    if let Some(link) = link {
+        // The watcher stopping means there is nothing to wait for
+        let _ = link.wait_for(|up| *up).await;
+    }
+}
+
+/// Runs `pinger` until it stops, or drops it when the interface goes down and returns `false`
+pub async fn run_while_up(
+    link: &mut Option<LinkState>,
+    pinger: impl Future<Output = Result<()>>,
+) -> Result<bool> {
+    let Some(link) = link else {
+        pinger.await?;
+        return Ok(true);
+    };
+    tokio::select! {
+        result = pinger => result.map(|_| true),
+        Ok(_) = link.wait_for(|up| !*up) => Ok(false),
+    }
+}
@@ -0,0 +1,153 @@
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::{Html, Response};
+use axum::{routing::get, Router};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::ping::Ping;
+
+/// Page drawing the live latency of every target
+const DASHBOARD: &str = include_str!("../assets/dashboard.html");
+
+/// Every client gets its own subscription to the pings
+type LivePings = Arc<broadcast::Receiver<Ping>>;
+
+async fn ws_handler(ws: WebSocketUpgrade, State(pings): State<LivePings>) -> Response {
+    let rx = pings.resubscribe();
+    ws.on_upgrade(move |socket| stream_pings(socket, rx))
+}
+
+/// Sends each ping as JSON until the client goes away
+async fn stream_pings(mut socket: WebSocket, mut rx: broadcast::Receiver<Ping>) {
+    loop {
+        let ping = match rx.recv().await {
+            Ok(ping) => ping,
+            // A slow client misses some pings rather than holding up the others
+            Err(RecvError::Lagged(skipped)) => {
+                debug!("WebSocket client skipped {} pings", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        let json = match serde_json::to_string(&ping) {
+            Ok(json) => json,
+            Err(_) => continue,
+        };
+        if socket.send(Message::Text(json.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Serves the dashboard on `/` and streams the pings from `rx` on `/ws`
+pub fn router(rx: broadcast::Receiver<Ping>) -> Router {
+    Router::new()
+        .route("/", get(|| async { Html(DASHBOARD) }))
+        .route("/ws", get(ws_handler))
+        .with_state(Arc::new(rx))
+}
+
+/// Streams every parsed ping as JSON to the WebSocket clients connected to `/ws`
+pub async fn ws_server(addr: SocketAddr, rx: broadcast::Receiver<Ping>) -> Result<()> {
+    let app = router(rx);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Streaming pings on ws://{}/ws", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Removes the socket file once the server stops
+pub struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Writes each ping as a JSON line until the client goes away
+pub async fn stream_lines(mut stream: impl AsyncWrite + Unpin, mut rx: broadcast::Receiver<Ping>) {
+    loop {
+        let ping = match rx.recv().await {
+            Ok(ping) => ping,
+            Err(RecvError::Lagged(skipped)) => {
+                debug!("Socket client skipped {} pings", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        let mut line = match serde_json::to_vec(&ping) {
+            Ok(line) => line,
+            Err(_) => continue,
+        };
+        line.push(b'\n');
+        if stream.write_all(&line).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Listens on a Unix socket at `path`, which is removed again when the guard is dropped
+pub fn bind_unix(path: &Path) -> Result<(UnixListener, SocketFile)> {
+    // A socket left behind by a previous run would make binding fail
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!("{} exists and is not a socket", path.display()));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(anyhow!(
+                "{} is in use, is another con-mon running?",
+                path.display()
+            ));
+        }
+        warn!("Replacing stale socket {}", path.display());
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    Ok((listener, SocketFile(path.to_path_buf())))
+}
+
+/// Streams every parsed ping as a JSON line to the processes connected to the Unix socket at `path`
+pub async fn socket_server(path: &Path, rx: broadcast::Receiver<Ping>) -> Result<()> {
+    let (listener, _socket) = bind_unix(path)?;
+    info!("Streaming pings on {}", path.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        debug!("Socket client connected");
+        tokio::spawn(stream_lines(stream, rx.resubscribe()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn binds_only_over_stale_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Another con-mon is listening
+        let path = dir.path().join("control.sock");
+        let (_listener, _socket) = bind_unix(&path).unwrap();
+        assert!(bind_unix(&path).is_err());
+
+        let log = dir.path().join("ping.log");
+        std::fs::write(&log, "kept").unwrap();
+        assert!(bind_unix(&log).is_err());
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "kept");
+
+        // Left behind by a con-mon that is gone
+        let stale = dir.path().join("stale.sock");
+        drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+        assert!(bind_unix(&stale).is_ok());
+    }
+}
@@ -1,189 +1,1020 @@
-use std::fmt;
-use std::fs::{File, OpenOptions};
-use std::io::Write;
-use std::{process::Stdio, str::FromStr, time::Duration};
-
-use log::info;
-use serde_json::{json, Value};
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::sync::oneshot::channel;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{broadcast, mpsc, watch, Notify};
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time;
 use tokio::time::interval;
-use tokio::{process::Command, time};
 
 use anyhow::anyhow;
-use anyhow::Error;
+use anyhow::Context;
 use anyhow::Result;
 
-use log::{debug, error, warn, LevelFilter};
-use simplelog::{ColorChoice, CombinedLogger, Config, TermLogger, TerminalMode, WriteLogger};
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, error, info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::time::ChronoUtc;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+mod alerts;
+mod bandwidth;
+mod captive_portal;
+mod config;
+mod control;
+mod discovery;
+mod dmesg;
+mod dns_probe;
+mod docker;
+mod export;
+mod failover;
+mod fping;
+mod gateway;
+mod geolocation;
+mod http_probe;
+mod icmp;
+mod install;
+#[cfg(test)]
+mod integration_test;
+mod interface;
+mod iperf;
+mod labels;
+mod link;
+mod live;
+mod metrics;
+mod mtu;
+mod native_speedtest;
+mod netns;
+mod notify_script;
+mod otel;
+mod outage;
+mod pidfile;
+mod ping;
+mod privacy;
+mod quality;
+mod query;
+mod report;
+mod ring_buffer;
+mod rotate;
+mod route;
+mod self_test;
+mod session;
+mod simulate;
+mod sinks;
+mod speedtest;
+mod stats;
+#[cfg(feature = "systemd")]
+mod systemd;
+mod tail;
+mod tcp_probe;
+mod traceroute;
+mod trend;
+mod update;
+mod wg_probe;
 
-use lazy_static::lazy_static;
-use regex::Regex;
+use alerts::Alerts;
+use config::{Action, Config, ExtraOutput, LogFormat, PingBackend, SpeedtestBackend};
+use discovery::Discovery;
+use failover::{FailoverDetector, SharedFailover};
+use metrics::{metrics_server, SharedMetrics};
+use otel::Otel;
+use ping::{
+    batch_pinger, cool_down, fping_pinger, native_pinger, ping_sinks, pinger, resolve, Ping,
+    PingFormat, TargetState,
+};
+use ring_buffer::{RingBuffer, SharedPings};
+use sinks::alarm::LatencyAlarm;
+use sinks::clickhouse::ClickHouseSink;
+use sinks::console::ConsoleSink;
+use sinks::csv::CsvSink;
+use sinks::heatmap::{GrafanaHeatmapSink, SharedHeatmap};
+use sinks::influx::InfluxSink;
+use sinks::mqtt::MqttSink;
+use sinks::parquet::ParquetSink;
+use sinks::Writer;
+use speedtest::{
+    list_speedtest_servers, next_speedtest, record_speedtest, speed_tester,
+    speedtest_cli_install_hint, speedtest_cli_installed,
+};
+use stats::SharedPercentiles;
 
-/// Maximum time to wait for ping before restarting
-const PING_TIMEOUT: u64 = 10;
+/// Where speedtests were stored as a single JSON array before switching to JSONL
+const LEGACY_SPEEDTEST_LOG: &str = "speedtests.json";
 
-/// Speedtest interval in seconds
-const SPEEDTEST_INTERVAL: u64 = 30 * 60;
+/// Outputs shared by all tasks
+#[derive(Clone, Default)]
+struct Shared {
+    metrics: SharedMetrics,
+    percentiles: SharedPercentiles,
+    heatmap: Option<SharedHeatmap>,
+    influx: Option<InfluxSink>,
+    console: Option<ConsoleSink>,
+    alarm: Option<LatencyAlarm>,
+    clickhouse: Option<ClickHouseSink>,
+    mqtt: Option<MqttSink>,
+    csv: Option<CsvSink>,
+    parquet: Option<ParquetSink>,
+    failover: Option<SharedFailover>,
+    alerts: Option<Arc<Alerts>>,
+    /// Pings for the WebSocket clients, noised like the sinks
+    live: Option<broadcast::Sender<Ping>>,
+    /// Latest pings for the API, noised like the sinks
+    pings: Option<SharedPings>,
+    /// Pings with the actual latencies for `tail`, only kept apart with `--export-privacy`
+    raw_live: Option<broadcast::Sender<Ping>>,
+    /// Latest pings with the actual latencies for `tail`, only kept apart with `--export-privacy`
+    raw_pings: Option<SharedPings>,
+    otel: Option<Arc<Otel>>,
+    /// Told about the first reply of every target
+    ready: Option<mpsc::UnboundedSender<()>>,
+    /// Notified to run a speedtest right away
+    speedtest_now: Arc<Notify>,
+    /// Output format of the system's `ping`
+    ping_format: PingFormat,
+    /// Whether the interface given by `--watch-interface` is up
+    link: Option<link::LinkState>,
+}
 
-#[derive(Debug)]
-struct Ping {
-    timestamp: String,
-    ms: u16,
+/// Why the monitor stopped
+#[derive(Debug, Clone, Copy)]
+enum Stop {
+    Shutdown,
+    /// Started again with the config read anew
+    Reload,
 }
 
-impl fmt::Display for Ping {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {}", self.timestamp, self.ms)
+/// Logs to the terminal and the log file, as text or JSON lines
+///
+/// The log file is written on a background thread, so a slow disk doesn't delay the pingers.
+/// Lines still queued are written when the returned guard is dropped, so it has to be kept
+/// until con-mon exits; anything logged after that is lost.
+fn init_logging(config: &Config) -> Result<WorkerGuard> {
+    let term_level = if config.console_display {
+        // Log lines would scroll the table away
+        LevelFilter::OFF
+    } else if config.dry_run {
+        // A dry run shows what would have been written, which is logged at debug level
+        LevelFilter::DEBUG
+    } else {
+        LevelFilter::INFO
+    };
+    let log_file: Writer = if config.dry_run {
+        Box::new(std::io::sink())
+    } else {
+        Box::new(
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&config.log_file)?,
+        )
+    };
+    let (log_file, guard) = tracing_appender::non_blocking(log_file);
+    let format = config.log_timestamp_format.chrono_format();
+    let timer = ChronoUtc::new(format.unwrap_or_default().to_string());
+    let term = tracing_subscriber::fmt::layer()
+        .with_ansi(config.color())
+        .with_timer(timer.clone());
+    let file = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_timer(timer)
+        .with_writer(log_file);
+    let (term, file) = match (config.log_format, format) {
+        (LogFormat::Text, Some(_)) => (term.boxed(), file.boxed()),
+        (LogFormat::Text, None) => (term.without_time().boxed(), file.without_time().boxed()),
+        (LogFormat::Json, Some(_)) => (term.json().boxed(), file.json().boxed()),
+        (LogFormat::Json, None) => (
+            term.json().without_time().boxed(),
+            file.json().without_time().boxed(),
+        ),
+    };
+    tracing_subscriber::registry()
+        .with(term.with_filter(term_level))
+        .with(file.with_filter(LevelFilter::INFO))
+        .try_init()?;
+    Ok(guard)
+}
+
+/// Runs `task` until it finishes or a shutdown is broadcast, dropping it closes its files
+async fn until_shutdown(
+    task: impl Future<Output = Result<()>>,
+    mut shutdown: broadcast::Receiver<()>,
+) -> Result<()> {
+    tokio::select! {
+        result = task => result,
+        _ = shutdown.recv() => Ok(()),
     }
 }
 
-impl FromStr for Ping {
-    type Err = Error;
-    fn from_str(string: &str) -> Result<Self, Self::Err> {
-        lazy_static! {
-            static ref RE: Regex = Regex::new(r"\[(.+)\].*time=(\d+)").unwrap();
-        }
-        let cap = RE
-            .captures(string)
-            .ok_or(anyhow!("No capture groups found"))?;
-        let timestamp = cap
-            .get(1)
-            .ok_or(anyhow!("Missing timestamp"))?
-            .as_str()
-            .to_string();
-        let duration = cap
-            .get(2)
-            .ok_or(anyhow!("Missing ping time"))?
-            .as_str()
-            .parse()?;
-        Ok(Self {
-            timestamp,
-            ms: duration,
-        })
+/// Waits for SIGINT or SIGTERM and broadcasts the shutdown
+async fn shutdown_on_signal(stop: mpsc::UnboundedSender<Stop>) -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {},
     }
+    info!("Shutting down");
+    // Nobody listening means everything stopped already
+    let _ = stop.send(Stop::Shutdown);
+    Ok(())
 }
 
-async fn pinger() -> Result<()> {
-    let mut handle = Command::new("ping")
-        .arg("-D")
-        .arg("1.1.1.1")
-        .stdout(Stdio::piped())
-        .spawn()?;
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut config = Config::load()?;
 
-    let stdout = handle.stdout.take().unwrap();
-    let reader = BufReader::new(stdout);
+    if config.docker {
+        std::env::set_current_dir(docker::DATA_DIR)
+            .with_context(|| format!("Couldn't change to the data volume {}", docker::DATA_DIR))?;
+    }
 
-    let (send, recv) = channel::<()>();
-    // Ensure the child process is spawned in the runtime so it can
-    // make progress on its own while we await for any output.
-    tokio::spawn(async move {
-        tokio::select! {
-            status = handle.wait() => {
-                debug!("child status was: {}", status.expect("no status"));
-            },
-            _ = recv => handle.kill().await.expect("Kill failed"),
+    match &config.action {
+        Some(Action::Query(args)) => {
+            if let Some(query::Query::Outages) = args.what {
+                return query::outages(&config.outage_log, args);
+            }
+            let db = config.ping_db.as_deref().unwrap_or(Path::new("ping.db"));
+            return query::run(db, args);
+        }
+        Some(Action::Report(args)) => return report::run(&config, args),
+        Some(Action::Export(args)) => return export::run(args),
+        Some(Action::Tail(args)) => return tail::run(&config, args).await,
+        Some(Action::Install(args)) => {
+            let options: Vec<_> = std::env::args_os()
+                .skip(1)
+                .take_while(|arg| arg != "install")
+                .collect();
+            return install::run(args, &options);
+        }
+        Some(Action::SelfTest) => {
+            if !self_test::run(&config).await? {
+                std::process::exit(1);
+            }
+            return Ok(());
         }
-    });
+        None => {}
+    }
+
+    if config.check_update {
+        return update::check_update().await;
+    }
+
+    if config.speedtest_list_servers {
+        return list_speedtest_servers().await;
+    }
+
+    if config.once {
+        // Retrying a missing speedtest-cli would only delay the error
+        if config.speedtest_backend == SpeedtestBackend::SpeedtestNet
+            && !config.native_speedtest
+            && !speedtest_cli_installed().await
+        {
+            return Err(anyhow!(
+                "speedtest-cli is not installed, install it with `{}` or use --native-speedtest",
+                speedtest_cli_install_hint()
+            ));
+        }
+        let result = speed_tester(&config).await?;
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+
+    // Flushes the log file when main returns
+    let _log_guard = init_logging(&config)?;
+
+    if config.docker {
+        match docker::container_id() {
+            Some(id) => info!(container = %id, "Running in container {}", id),
+            None => info!("Running in a container"),
+        }
+    }
+
+    // Dropped at the end of main, which removes the file again
+    let _pid_file = config
+        .pid_file
+        .as_deref()
+        .filter(|_| !config.dry_run)
+        .map(pidfile::PidFile::acquire)
+        .transpose()?;
 
-    let mut outfile = File::options().append(true).create(true).open("ping.log")?;
+    sinks::set_labels(config.label.clone());
 
-    let mut lines = reader.lines();
+    if let Err(err) = session::session_init(&config) {
+        warn!("Couldn't write the session start to the logs: {}", err);
+    }
+
+    if Path::new(LEGACY_SPEEDTEST_LOG).exists() {
+        info!(
+            "Found {} from an older version, speedtests are now appended to {}. \
+             Convert the old results with `jq -c '.[]' {} >> {}`",
+            LEGACY_SPEEDTEST_LOG,
+            config.speedtest_log.display(),
+            LEGACY_SPEEDTEST_LOG,
+            config.speedtest_log.display()
+        );
+    }
+
+    let (stop, mut stopped) = mpsc::unbounded_channel();
+    tokio::spawn(shutdown_on_signal(stop.clone()));
     loop {
-        let line = time::timeout(Duration::from_secs(PING_TIMEOUT), lines.next_line());
-        match line.await {
-            Ok(Ok(Some(line))) => {
-                // Timeout check passed
-                debug!("Ping: {}", line);
-                match line.parse::<Ping>() {
-                    Ok(ping) => {
-                        outfile.write_all(format!("{}\n", ping).as_bytes())?;
-                        outfile.flush()?;
+        match run(config.clone(), stop.clone(), &mut stopped).await? {
+            Stop::Shutdown => return Ok(()),
+            // Logging and the PID file keep the settings they were started with
+            Stop::Reload => match Config::load() {
+                Ok(reloaded) => {
+                    info!("Reloaded the config");
+                    config = reloaded;
+                }
+                Err(err) => error!("Couldn't reload the config, keeping the old one: {:#}", err),
+            },
+        }
+    }
+}
+
+/// Pings `target` through `interface`, restarting the pinger with a backoff whenever it stops
+async fn ping_loop(
+    config: Config,
+    target: String,
+    interface: Option<String>,
+    shared: Shared,
+) -> Result<()> {
+    if config.mtu_probe {
+        tokio::spawn(mtu::check_mtu(target.clone()));
+    }
+    let mut state = TargetState::new(&config, &target, interface.as_deref());
+    state.ready = shared.ready.clone();
+    let min_delay = Duration::from_secs(config.restart_min_delay);
+    let max_delay = Duration::from_secs(config.restart_max_delay).max(min_delay);
+    let cooldown = Duration::from_secs(config.ping_restart_cooldown);
+    let mut delay = min_delay;
+    let mut link = shared.link.clone();
+    loop {
+        link::wait_until_up(&mut link).await;
+        state.replied = false;
+        // Resolved on every start, the address of a dynamic DNS name may have changed
+        let run = async {
+            match resolve(&target).await {
+                Ok(addr) => {
+                    if state.addr != Some(addr) && target.parse::<IpAddr>().is_err() {
+                        info!(%target, %addr, "Resolved {} to {}", target, addr);
+                    }
+                    state.addr = Some(addr);
+                    if let Some(size) = config.batch_size {
+                        let sinks = ping_sinks(&config, &shared)?;
+                        batch_pinger(&config, &mut state, &shared, addr, size, sinks).await?;
+                    } else if config.ping_backend == PingBackend::Native {
+                        let sinks = ping_sinks(&config, &shared)?;
+                        native_pinger(&config, &mut state, &shared, addr, sinks).await?;
+                    } else {
+                        let sinks = ping_sinks(&config, &shared)?;
+                        pinger(&config, &mut state, &shared, addr, sinks).await?;
                     }
-                    Err(err) => warn!("Couldn't parse: {}", err),
+                }
+                Err(err) => {
+                    warn!(%target, "Couldn't resolve {}: {}", target, err);
+                    state.lost(&config, &shared, Utc::now());
                 }
             }
-            Ok(Ok(None)) => {
-                error!("Task gave no more lines");
-                break;
+            Ok(())
+        };
+        // Started again once the interface is back, without waiting for the cooldown
+        if !link::run_while_up(&mut link, run).await? {
+            continue;
+        }
+        if state.replied {
+            delay = min_delay;
+        }
+        // Anywhere between half and all of the delay, so pingers don't restart in lockstep
+        let wait = delay.mul_f64(0.5 + fastrand::f64() / 2.0).max(cooldown);
+        info!(target = %state.target, "Pinger in cooldown, restarting in {:.1?}", wait);
+        cool_down(std::slice::from_ref(&state.target), wait, &shared.metrics).await;
+        delay = (delay * 2).min(max_delay);
+    }
+}
+
+/// Runs the monitor until it is told to stop
+async fn run(
+    mut config: Config,
+    stop: mpsc::UnboundedSender<Stop>,
+    stopped: &mut mpsc::UnboundedReceiver<Stop>,
+) -> Result<Stop> {
+    if config.native_ping {
+        config.ping_backend = PingBackend::Native;
+    }
+    if config.netns.is_some() && config.ping_backend == PingBackend::Native {
+        return Err(anyhow!(
+            "--netns only applies to the ping and fping backends, not to the native pinger"
+        ));
+    }
+
+    if config.docker {
+        // The container's gateway is the host, which is what should be monitored
+        match gateway::default_gateway() {
+            Ok(gateway) => {
+                info!("Pinging the host at {} as the container's gateway", gateway);
+                config.ping_target = vec![gateway.to_string()];
             }
-            _ => {
-                info!("Ping took longer than {} seconds.", PING_TIMEOUT);
-                info!("Restarting pinger");
-                break;
+            Err(err) => warn!("Couldn't find the container's gateway: {}", err),
+        }
+    } else if config.gateway_ping {
+        match gateway::default_gateway() {
+            Ok(gateway) => {
+                info!("Pinging {} as the default gateway", gateway);
+                let gateway = gateway.to_string();
+                if !config.ping_target.contains(&gateway) {
+                    config.ping_target.push(gateway);
+                }
             }
+            Err(err) => warn!("Couldn't find the default gateway: {}", err),
         }
     }
 
-    // Kill the ping process
-    send.send(()).unwrap();
-    Ok(())
-}
+    async fn tester(config: Config, shared: Shared) -> Result<()> {
+        let mut iv = interval(Duration::from_secs(config.speedtest_interval));
+        let mut trend = trend::TrendTracker::load(&config.speedtest_log, config.trend_window);
 
-async fn speed_tester() -> Result<()> {
-    debug!("Speedtest started");
-    let output = Command::new("speedtest-cli").arg("--json").output().await?;
-    if !output.status.success() {
-        error!("Speedtest failed: {:?}", output.stderr);
-        return Ok(());
+        loop {
+            let scheduled = async {
+                if config.speedtest_time.is_empty() {
+                    iv.tick().await;
+                } else {
+                    let next = next_speedtest(&config.speedtest_time);
+                    debug!("Next speedtest in {:?}", next);
+                    time::sleep(next).await;
+                }
+            };
+            tokio::select! {
+                _ = scheduled => {
+                    if bandwidth::budget_exhausted(&config) {
+                        info!("Skipping the speedtest, this month's data budget is used up");
+                        continue;
+                    }
+                }
+                _ = shared.speedtest_now.notified() => info!("Speedtest requested"),
+            }
+            let start = std::time::SystemTime::now();
+            match speed_tester(&config).await {
+                Ok(result) => {
+                    info!(
+                        download_bps = result.download,
+                        upload_bps = result.upload,
+                        ping_ms = result.ping,
+                        "Speedtest finished"
+                    );
+                    if let Some(otel) = &shared.otel {
+                        otel.record_speedtest(&result, start, std::time::SystemTime::now());
+                    }
+                    record_speedtest(&config, &shared, &result).await?;
+                    if let Some(script) = &config.notify_script {
+                        notify_script::notify(
+                            script,
+                            notify_script::Event::SpeedtestComplete(&result),
+                        );
+                    }
+                    if let Err(err) = trend.record(&config, &result) {
+                        warn!("Couldn't write speed trend: {}", err);
+                    }
+                }
+                Err(err) => error!(error = %err, "Speedtest failed"),
+            }
+        }
     }
-    let output = String::from_utf8(output.stdout)?;
-    debug!("Speed: {}", &output);
-    let output_json = serde_json::from_str(&output)?;
-
-    let all_tests_file = File::options()
-        .append(true)
-        .create(true)
-        .read(true)
-        .open("speedtests.json")?;
-
-    let all_tests = match serde_json::from_reader::<_, Value>(&all_tests_file) {
-        Ok(mut array) => {
-            let x = array
-                .as_array_mut()
-                .ok_or(anyhow!("Speedtest file has wrong format, delete it"))?;
-            x.push(output_json);
-            json!(x)
-        }
-        Err(_) => json!(vec![output_json]),
-    };
 
-    serde_json::to_writer(all_tests_file, &all_tests)?;
+    /// Pings all targets through `interface` with fping, restarting it like `ping_loop`
+    async fn fping_loop(config: Config, interface: Option<String>, shared: Shared) -> Result<()> {
+        let mut states: Vec<_> = config
+            .ping_target
+            .iter()
+            .map(|target| {
+                if config.mtu_probe {
+                    tokio::spawn(mtu::check_mtu(target.clone()));
+                }
+                let mut state = TargetState::new(&config, target, interface.as_deref());
+                state.ready = shared.ready.clone();
+                state
+            })
+            .collect();
+        let targets: Vec<_> = states.iter().map(|state| state.target.clone()).collect();
+        let min_delay = Duration::from_secs(config.restart_min_delay);
+        let max_delay = Duration::from_secs(config.restart_max_delay).max(min_delay);
+        let cooldown = Duration::from_secs(config.ping_restart_cooldown);
+        let mut delay = min_delay;
+        let mut link = shared.link.clone();
+        loop {
+            link::wait_until_up(&mut link).await;
+            for state in &mut states {
+                state.replied = false;
+            }
+            if !link::run_while_up(
+                &mut link,
+                fping_pinger(&config, &mut states, &shared, ping_sinks(&config, &shared)?),
+            )
+            .await?
+            {
+                continue;
+            }
+            if states.iter().any(|state| state.replied) {
+                delay = min_delay;
+            }
+            let wait = delay.mul_f64(0.5 + fastrand::f64() / 2.0).max(cooldown);
+            info!("fping in cooldown, restarting in {:.1?}", wait);
+            cool_down(&targets, wait, &shared.metrics).await;
+            delay = (delay * 2).min(max_delay);
+        }
+    }
 
-    Ok(())
-}
+    let (shutdown, _) = broadcast::channel(1);
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    CombinedLogger::init(vec![
-        TermLogger::new(
-            LevelFilter::Info,
-            Config::default(),
-            TerminalMode::Mixed,
-            ColorChoice::Auto,
-        ),
-        WriteLogger::new(
-            LevelFilter::Info,
-            Config::default(),
-            OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open("con_mon.log")?,
+    let mut shared = Shared::default();
+    shared.metrics.lock().unwrap().labels = config.label.clone();
+    // Failures of these are logged, only the pingers stop con-mon
+    let mut background = JoinSet::new();
+
+    shared.alerts = Alerts::from_config(&config)?.map(Arc::new);
+
+    if let Some(interface) = &config.watch_interface {
+        let (up, link) = watch::channel(true);
+        shared.link = Some(link);
+        background.spawn(until_shutdown(
+            link::watch_interface(interface.clone(), up),
+            shutdown.subscribe(),
+        ));
+    }
+
+    if config.ping_backend == PingBackend::Ping {
+        shared.ping_format = PingFormat::detect(&config.ping_command).await;
+        debug!("Parsing ping output as {:?}", shared.ping_format);
+    }
+
+    if config.metrics_addr.is_some()
+        || config.ws_addr.is_some()
+        || config.ping_socket.is_some()
+        || config.control_socket.is_some()
+    {
+        shared.live = Some(broadcast::channel(100).0);
+    }
+    let live = || shared.live.as_ref().map(broadcast::Sender::subscribe);
+
+    if config.metrics_addr.is_some() || config.control_socket.is_some() {
+        shared.pings = Some(Arc::new(std::sync::Mutex::new(RingBuffer::new(
+            config.ping_buffer_size,
+        ))));
+    }
+
+    if config.control_socket.is_some() && config.export_privacy.is_some() {
+        shared.raw_live = Some(broadcast::channel(100).0);
+        shared.raw_pings = Some(Arc::new(std::sync::Mutex::new(RingBuffer::new(
+            config.ping_buffer_size,
+        ))));
+    }
+
+    if let (Some(addr), Some(pings)) = (config.metrics_addr, shared.pings.clone()) {
+        background.spawn(until_shutdown(
+            metrics_server(
+                addr,
+                shared.metrics.clone(),
+                pings,
+                config.ping_db.clone(),
+                live(),
+            ),
+            shutdown.subscribe(),
+        ));
+    }
+
+    if let Some((addr, rx)) = config.ws_addr.zip(live()) {
+        background.spawn(until_shutdown(
+            live::ws_server(addr, rx),
+            shutdown.subscribe(),
+        ));
+    }
+
+    match (&config.ping_socket, live()) {
+        (Some(path), _) if config.dry_run => {
+            info!("Dry run, not creating the socket {}", path.display())
+        }
+        (Some(path), Some(rx)) => {
+            let path = path.clone();
+            background.spawn(until_shutdown(
+                async move { live::socket_server(&path, rx).await },
+                shutdown.subscribe(),
+            ));
+        }
+        _ => {}
+    }
+
+    if let Some(endpoint) = &config.otlp_endpoint {
+        shared.otel = Some(Arc::new(Otel::new(
+            endpoint,
+            config.otlp_protocol,
+            &config.label,
+        )?));
+    }
+
+    match &config.influx_url {
+        Some(url) if config.dry_run => info!("Dry run, not writing to InfluxDB at {}", url),
+        Some(url) => {
+            let mut influx = InfluxSink::new(
+                url,
+                &config.influx_token,
+                &config.influx_org,
+                &config.influx_bucket,
+            );
+            influx.labels = config.label.clone();
+            // Not wrapped in until_shutdown, it flushes the buffer on shutdown itself
+            background.spawn(influx.clone().flush_periodically(shutdown.subscribe()));
+            shared.influx = Some(influx);
+        }
+        None => {}
+    }
+
+    match &config.clickhouse_url {
+        Some(url) if config.dry_run => info!("Dry run, not writing to ClickHouse at {}", url),
+        Some(url) => {
+            let mut clickhouse =
+                ClickHouseSink::new(url, &config.clickhouse_database, &config.clickhouse_table);
+            clickhouse.user = config.clickhouse_user.clone();
+            clickhouse.password = config.clickhouse_password.clone();
+            clickhouse.batch_size = config.clickhouse_batch_size;
+            clickhouse.flush_interval = Duration::from_secs(config.clickhouse_flush_interval);
+            background.spawn(clickhouse.clone().flush_periodically(shutdown.subscribe()));
+            shared.clickhouse = Some(clickhouse);
+        }
+        None => {}
+    }
+
+    match config.mqtt_broker {
+        Some(broker) if config.dry_run => info!("Dry run, not publishing to MQTT at {}", broker),
+        Some(broker) => {
+            let (mqtt, eventloop) =
+                MqttSink::new(broker, &config.mqtt_client_id, &config.mqtt_topic_prefix);
+            background.spawn(mqtt.clone().run(eventloop, shutdown.subscribe()));
+            shared.mqtt = Some(mqtt);
+        }
+        None => {}
+    }
+
+    if config.output_format.contains(&ExtraOutput::Csv) {
+        let mut csv = CsvSink::new(
+            &config.csv_ping_log,
+            &config.csv_speedtest_log,
+            config.sinks(),
+        );
+        csv.labels = config.label.clone();
+        csv.jitter_window = config.jitter_window;
+        shared.csv = Some(csv);
+    }
+    if config.output_format.contains(&ExtraOutput::Parquet) {
+        let mut parquet = ParquetSink::new(&config.parquet_log, config.sinks());
+        parquet.batch_size = config.parquet_batch_size;
+        parquet.labels = config.label.clone();
+        parquet.flush_interval = Duration::from_secs(config.parquet_flush_minutes * 60);
+        background.spawn(parquet.clone().flush_periodically(shutdown.subscribe()));
+        shared.parquet = Some(parquet);
+    }
+
+    if config.console_display {
+        let console = ConsoleSink::new(shared.metrics.clone());
+        background.spawn(until_shutdown(
+            console.clone().redraw_periodically(),
+            shutdown.subscribe(),
+        ));
+        shared.console = Some(console);
+    }
+
+    if let Some(threshold) = config.latency_alarm_ms {
+        shared.alarm = Some(LatencyAlarm::new(
+            threshold,
+            Duration::from_secs(config.alarm_cooldown),
+        ));
+    }
+
+    if let Some(path) = &config.heatmap_log {
+        let heatmap = Arc::new(std::sync::Mutex::new(GrafanaHeatmapSink::new(
+            config.heatmap_buckets.clone(),
+        )));
+        background.spawn(until_shutdown(
+            sinks::heatmap::write_every_minute(heatmap.clone(), config.sinks().append(path)?),
+            shutdown.subscribe(),
+        ));
+        shared.heatmap = Some(heatmap);
+    }
+
+    background.spawn(until_shutdown(
+        stats::write_hourly(
+            shared.percentiles.clone(),
+            config.sinks().append(&config.hourly_stats_log)?,
         ),
-    ])?;
+        shutdown.subscribe(),
+    ));
 
-    async fn tester() -> Result<()> {
-        let mut iv = interval(Duration::from_secs(SPEEDTEST_INTERVAL));
+    let needs_speedtest_cli = config.speedtest_backend == SpeedtestBackend::SpeedtestNet
+        && (!config.native_speedtest || config.native_speedtest_fallback);
+    let speedtest_cli_missing = needs_speedtest_cli && !speedtest_cli_installed().await;
+    if speedtest_cli_missing && config.native_speedtest {
+        warn!(
+            "speedtest-cli is not installed, failed native speedtests won't fall back to it. Install it with `{}`",
+            speedtest_cli_install_hint()
+        );
+    } else if speedtest_cli_missing && config.simulate.is_none() {
+        error!(
+            "speedtest-cli is not installed, speedtests are disabled. Install it with `{}` or use --native-speedtest",
+            speedtest_cli_install_hint()
+        );
+    }
 
-        loop {
-            iv.tick().await;
-            speed_tester().await?;
+    // Replays run offline, the speedtests would only fail
+    if config.simulate.is_none() && (config.native_speedtest || !speedtest_cli_missing) {
+        background.spawn(until_shutdown(
+            tester(config.clone(), shared.clone()),
+            shutdown.subscribe(),
+        ));
+    }
+
+    if !config.http_probe_url.is_empty() {
+        let config = config.clone();
+        let outfile = config.sinks().append(&config.http_probe_log)?;
+        let metrics = shared.metrics.clone();
+        background.spawn(until_shutdown(
+            async move {
+                http_probe::http_prober(
+                    config.http_probe_url,
+                    Duration::from_secs(config.http_probe_interval),
+                    Duration::from_secs(config.http_probe_timeout),
+                    config.bind_interface,
+                    outfile,
+                    metrics,
+                )
+                .await
+            },
+            shutdown.subscribe(),
+        ));
+    }
+
+    if config.captive_portal_check {
+        background.spawn(until_shutdown(
+            captive_portal::captive_portal_checker(
+                config.captive_portal_url.clone(),
+                Duration::from_secs(config.captive_portal_interval),
+                shared.metrics.clone(),
+            ),
+            shutdown.subscribe(),
+        ));
+    }
+
+    if config.route_check {
+        let outfile = config.sinks().append(&config.route_change_log)?;
+        background.spawn(until_shutdown(
+            route::route_monitor(
+                config.route_check_target.clone(),
+                Duration::from_secs(config.route_check_interval),
+                outfile,
+            ),
+            shutdown.subscribe(),
+        ));
+    }
+
+    if !config.tcp_probe_target.is_empty() {
+        let config = config.clone();
+        let outfile = config.sinks().append(&config.tcp_probe_log)?;
+        let metrics = shared.metrics.clone();
+        background.spawn(until_shutdown(
+            async move {
+                tcp_probe::tcp_prober(
+                    config.tcp_probe_target,
+                    Duration::from_secs(config.tcp_probe_interval),
+                    Duration::from_secs(config.tcp_probe_timeout),
+                    config.bind_interface,
+                    outfile,
+                    metrics,
+                )
+                .await
+            },
+            shutdown.subscribe(),
+        ));
+    }
+
+    if !config.dns_probe_host.is_empty() {
+        let config = config.clone();
+        let outfile = config.sinks().append(&config.dns_probe_log)?;
+        background.spawn(until_shutdown(
+            async move {
+                dns_probe::dns_prober(
+                    config.dns_probe_host,
+                    config.dns_resolver,
+                    Duration::from_secs(config.dns_probe_interval),
+                    config.dns_probe_threshold,
+                    outfile,
+                )
+                .await
+            },
+            shutdown.subscribe(),
+        ));
+    }
+
+    if config.dns_check_host {
+        let hostnames: Vec<_> = config
+            .ping_target
+            .iter()
+            .filter(|target| target.parse::<IpAddr>().is_err())
+            .collect();
+        if hostnames.is_empty() {
+            warn!("None of the ping targets is a hostname, not checking DNS");
+        }
+        for hostname in hostnames {
+            let outfile = config.sinks().append(&config.dns_change_log)?;
+            background.spawn(until_shutdown(
+                dns_probe::dns_checker(
+                    hostname.clone(),
+                    Duration::from_secs(config.dns_check_interval),
+                    outfile,
+                ),
+                shutdown.subscribe(),
+            ));
+        }
+    }
+
+    if !config.wg_interface.is_empty() {
+        let config = config.clone();
+        let outfile = config.sinks().append(&config.wg_probe_log)?;
+        background.spawn(until_shutdown(
+            async move {
+                wg_probe::wg_prober(
+                    config.wg_interface,
+                    Duration::from_secs(config.wg_probe_interval),
+                    Duration::from_secs(config.wg_handshake_max_age),
+                    outfile,
+                )
+                .await
+            },
+            shutdown.subscribe(),
+        ));
+    }
+
+    match &config.control_socket {
+        Some(path) if config.dry_run => {
+            info!("Dry run, not creating the socket {}", path.display())
         }
+        Some(path) => {
+            let (path, shared, stop) = (path.clone(), shared.clone(), stop.clone());
+            background.spawn(until_shutdown(
+                async move { control::control_server(&path, shared, stop).await },
+                shutdown.subscribe(),
+            ));
+        }
+        None => {}
     }
 
-    tokio::spawn(tester());
+    let mut pingers = JoinSet::new();
+    if let Some(path) = &config.simulate {
+        let (config, shared, path) = (config.clone(), shared.clone(), path.clone());
+        pingers.spawn(until_shutdown(
+            async move { simulate::replay(&config, &shared, &path).await },
+            shutdown.subscribe(),
+        ));
+    }
+    let interfaces: Vec<_> = match config.interfaces.is_empty() {
+        true => vec![None],
+        false => config.interfaces.iter().cloned().map(Some).collect(),
+    };
+    if config.interfaces.len() > 1 {
+        let failover = FailoverDetector::new(config.outage_threshold);
+        shared.failover = Some(Arc::new(std::sync::Mutex::new(failover)));
+    }
+    #[cfg(feature = "systemd")]
+    {
+        // A first reply per target and interface, the replayed pings don't send any
+        let states = match config.simulate {
+            Some(_) => 0,
+            None => config.ping_target.len() * interfaces.len(),
+        };
+        let (ready, first_replies) = mpsc::unbounded_channel();
+        shared.ready = Some(ready);
+        background.spawn(until_shutdown(
+            systemd::notify_ready(first_replies, states),
+            shutdown.subscribe(),
+        ));
+    }
+    let fping = config.ping_backend == PingBackend::Fping && config.batch_size.is_none();
+    for interface in interfaces
+        .iter()
+        .filter(|_| fping && config.simulate.is_none())
+    {
+        pingers.spawn(until_shutdown(
+            fping_loop(config.clone(), interface.clone(), shared.clone()),
+            shutdown.subscribe(),
+        ));
+    }
+    for target in config
+        .ping_target
+        .iter()
+        .filter(|_| !fping && config.simulate.is_none())
+    {
+        for interface in &interfaces {
+            pingers.spawn(until_shutdown(
+                ping_loop(
+                    config.clone(),
+                    target.clone(),
+                    interface.clone(),
+                    shared.clone(),
+                ),
+                shutdown.subscribe(),
+            ));
+        }
+    }
 
+    let (discovered, mut discoveries) = mpsc::unbounded_channel();
+    if let Some(service) = config.mdns_discover.clone() {
+        background.spawn(until_shutdown(
+            discovery::discover(
+                service,
+                config.discovered_targets.clone(),
+                config.sinks(),
+                discovered,
+            ),
+            shutdown.subscribe(),
+        ));
+    } else {
+        // Closed right away, or the loop below would wait for it forever
+        drop(discovered);
+    }
+    // Pingers of the discovered targets, aborted when they go away
+    let mut discovered_pingers: HashMap<String, Vec<AbortHandle>> = HashMap::new();
+
+    let mut reason = None;
     loop {
-        pinger().await?;
+        tokio::select! {
+            Some(result) = pingers.join_next() => match result {
+                Err(err) if err.is_cancelled() => {}
+                result => result??,
+            },
+            Some(discovery) = discoveries.recv() => match discovery {
+                // Only the recorded pings are replayed
+                Discovery::Added(_) if config.simulate.is_some() => {}
+                Discovery::Added(target) if config.ping_target.contains(&target) => {}
+                Discovery::Added(target) if fping => {
+                    // An fping of its own, so the host can be stopped on its own
+                    let config = Config {
+                        ping_target: vec![target.clone()],
+                        ..config.clone()
+                    };
+                    let handles = interfaces
+                        .iter()
+                        .map(|interface| {
+                            pingers.spawn(until_shutdown(
+                                fping_loop(config.clone(), interface.clone(), shared.clone()),
+                                shutdown.subscribe(),
+                            ))
+                        })
+                        .collect();
+                    discovered_pingers.insert(target, handles);
+                }
+                Discovery::Added(target) => {
+                    let handles = interfaces
+                        .iter()
+                        .map(|interface| {
+                            pingers.spawn(until_shutdown(
+                                ping_loop(
+                                    config.clone(),
+                                    target.clone(),
+                                    interface.clone(),
+                                    shared.clone(),
+                                ),
+                                shutdown.subscribe(),
+                            ))
+                        })
+                        .collect();
+                    discovered_pingers.insert(target, handles);
+                }
+                Discovery::Removed(target) => {
+                    for handle in discovered_pingers.remove(&target).into_iter().flatten() {
+                        handle.abort();
+                    }
+                }
+            },
+            Some(result) = background.join_next() => match result {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => error!("Background task failed: {}", err),
+                Err(err) => error!("Background task panicked: {}", err),
+            },
+            Some(stop) = stopped.recv(), if reason.is_none() => {
+                reason = Some(stop);
+                // Nobody listening means everything stopped already
+                let _ = shutdown.send(());
+            }
+            else => break,
+        }
+    }
+
+    if let Some(otel) = &shared.otel {
+        otel.shutdown()?;
     }
+    Ok(reason.unwrap_or(Stop::Shutdown))
 }
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::{routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::info;
+
+use crate::labels::Labels;
+use crate::live;
+use crate::ping::Ping;
+use crate::query::{self, QualityPoint, Resolution};
+use crate::ring_buffer::SharedPings;
+
+/// Pings `/api/pings` returns without `last`
+const API_PINGS: usize = 100;
+
+/// Metrics state shared between the monitoring tasks and the metrics server
+pub type SharedMetrics = Arc<Mutex<MetricsState>>;
+
+/// Latest measurements exposed on `/metrics`
+#[derive(Debug, Default, Serialize)]
+pub struct MetricsState {
+    /// Last ping latency per target
+    pub ping_latency_ms: BTreeMap<String, f64>,
+    /// Packet loss over the recent pings per target as a value between 0 and 1
+    pub packet_loss_ratio: BTreeMap<String, f64>,
+    /// Connection quality score from 0 to 100 per target
+    pub quality_score: BTreeMap<String, f64>,
+    /// Seconds until the pinger of a target is restarted, 0 while it runs
+    pub pinger_cooldown_remaining_s: BTreeMap<String, f64>,
+    /// Latency of the last HTTP probe per URL
+    pub http_probe_latency_ms: BTreeMap<String, f64>,
+    /// Connection latency of the last TCP probe per address
+    pub tcp_probe_latency_ms: BTreeMap<SocketAddr, f64>,
+    pub download_bps: Option<f64>,
+    pub upload_bps: Option<f64>,
+    /// 1 while a captive portal intercepts HTTP, 0 once checked and there is none
+    pub captive_portal_active: Option<f64>,
+    /// `--label`s added to every sample
+    #[serde(skip)]
+    pub labels: Labels,
+}
+
+impl MetricsState {
+    /// Renders the state in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_labeled(
+            &mut out,
+            &self.labels,
+            "conmon_ping_latency_ms",
+            "Latency of the last ping in milliseconds",
+            "target",
+            &self.ping_latency_ms,
+        );
+        write_labeled(
+            &mut out,
+            &self.labels,
+            "conmon_packet_loss_ratio",
+            "Ratio of the recent pings that got no reply",
+            "target",
+            &self.packet_loss_ratio,
+        );
+        write_labeled(
+            &mut out,
+            &self.labels,
+            "conmon_quality_score",
+            "Connection quality from 0 to 100 combining latency, jitter and packet loss",
+            "target",
+            &self.quality_score,
+        );
+        write_labeled(
+            &mut out,
+            &self.labels,
+            "conmon_pinger_cooldown_remaining_s",
+            "Seconds until the pinger is restarted, 0 while it runs",
+            "target",
+            &self.pinger_cooldown_remaining_s,
+        );
+        write_labeled(
+            &mut out,
+            &self.labels,
+            "conmon_http_probe_latency_ms",
+            "Latency of the last HTTP probe in milliseconds",
+            "url",
+            &self.http_probe_latency_ms,
+        );
+        write_header(
+            &mut out,
+            "conmon_tcp_probe_latency_ms",
+            "Connection latency of the last TCP probe in milliseconds",
+        );
+        for (addr, value) in &self.tcp_probe_latency_ms {
+            let (ip, port) = (addr.ip().to_string(), addr.port().to_string());
+            writeln!(
+                out,
+                "conmon_tcp_probe_latency_ms{} {}",
+                label_set(&self.labels, &[("target", &ip), ("port", &port)]),
+                value
+            )
+            .unwrap();
+        }
+        write_single(
+            &mut out,
+            &self.labels,
+            "conmon_download_bps",
+            "Download speed of the last speedtest in bits per second",
+            self.download_bps,
+        );
+        write_single(
+            &mut out,
+            &self.labels,
+            "conmon_upload_bps",
+            "Upload speed of the last speedtest in bits per second",
+            self.upload_bps,
+        );
+        write_single(
+            &mut out,
+            &self.labels,
+            "conmon_captive_portal_active",
+            "1 while a captive portal intercepts HTTP requests, 0 otherwise",
+            self.captive_portal_active,
+        );
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} gauge", name).unwrap();
+}
+
+/// `{name="value",…}` of the `--label`s and `extra`, empty without any labels
+fn label_set(labels: &Labels, extra: &[(&str, &str)]) -> String {
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .chain(extra.iter().copied())
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+fn write_labeled(
+    out: &mut String,
+    labels: &Labels,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &BTreeMap<String, f64>,
+) {
+    write_header(out, name, help);
+    for (key, value) in values {
+        writeln!(
+            out,
+            "{}{} {}",
+            name,
+            label_set(labels, &[(label, key)]),
+            value
+        )
+        .unwrap();
+    }
+}
+
+fn write_single(out: &mut String, labels: &Labels, name: &str, help: &str, value: Option<f64>) {
+    write_header(out, name, help);
+    if let Some(value) = value {
+        writeln!(out, "{}{} {}", name, label_set(labels, &[]), value).unwrap();
+    }
+}
+
+async fn metrics_handler(State(state): State<SharedMetrics>) -> String {
+    state.lock().unwrap().render()
+}
+
+#[derive(Debug, Deserialize)]
+struct PingsQuery {
+    last: Option<usize>,
+}
+
+async fn pings_handler(
+    State(pings): State<SharedPings>,
+    Query(query): Query<PingsQuery>,
+) -> Json<Vec<Ping>> {
+    Json(pings.lock().unwrap().last(query.last.unwrap_or(API_PINGS)))
+}
+
+#[derive(Debug, Deserialize)]
+struct QualityQuery {
+    from: Option<String>,
+    to: Option<String>,
+    target: Option<String>,
+    #[serde(default)]
+    resolution: Resolution,
+}
+
+async fn quality_handler(
+    State(db): State<Arc<PathBuf>>,
+    Query(args): Query<QualityQuery>,
+) -> Result<Json<Vec<QualityPoint>>, (StatusCode, String)> {
+    let bad_request = |err: anyhow::Error| (StatusCode::BAD_REQUEST, err.to_string());
+    let from = args.from.as_deref().map(query::parse_time).transpose();
+    let to = args.to.as_deref().map(query::parse_time).transpose();
+    let (from, to) = (from.map_err(bad_request)?, to.map_err(bad_request)?);
+    let series = tokio::task::spawn_blocking(move || {
+        query::quality_series(&db, from, to, args.target.as_deref(), args.resolution)
+    })
+    .await;
+    match series {
+        Ok(Ok(series)) => Ok(Json(series)),
+        Ok(Err(err)) => Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+        Err(err) => Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+    }
+}
+
+/// Serves the metrics in Prometheus format on `/metrics`, the latest pings as JSON on
+/// `/api/pings?last=100`, the connection quality per period on
+/// `/api/quality?from=…&to=…&resolution=minute` if there is a ping database `db`, and the live
+/// dashboard if `live` is set
+pub async fn metrics_server(
+    addr: SocketAddr,
+    state: SharedMetrics,
+    pings: SharedPings,
+    db: Option<PathBuf>,
+    live: Option<broadcast::Receiver<Ping>>,
+) -> Result<()> {
+    let mut app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(state)
+        .merge(
+            Router::new()
+                .route("/api/pings", get(pings_handler))
+                .with_state(pings),
+        );
+    if let Some(db) = db {
+        app = app.merge(
+            Router::new()
+                .route("/api/quality", get(quality_handler))
+                .with_state(Arc::new(db)),
+        );
+    }
+    if let Some(rx) = live {
+        app = app.merge(live::router(rx));
+        info!("Serving the dashboard on http://{}/", addr);
+    }
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Serving metrics on http://{}/metrics", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
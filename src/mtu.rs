@@ -0,0 +1,80 @@
+use std::net::IpAddr;
+use std::process::Stdio;
+
+use anyhow::{anyhow, Result};
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+/// Largest MTU probed for, that of Ethernet
+const MAX_MTU: u16 = 1500;
+
+/// Smallest MTU IPv4 requires every link to support
+const MIN_MTU: u16 = 68;
+
+/// Path MTU below which a warning is logged, often a misconfigured VPN
+const MTU_WARN: u16 = 1400;
+
+/// Bytes of IP and ICMP header in front of the ping payload
+fn header_size(target: IpAddr) -> u16 {
+    match target {
+        IpAddr::V4(_) => 20 + 8,
+        IpAddr::V6(_) => 40 + 8,
+    }
+}
+
+/// Whether a packet of `mtu` bytes reaches `target` without being fragmented
+async fn fits(target: IpAddr, mtu: u16) -> Result<bool> {
+    let mut command = Command::new("ping");
+    if target.is_ipv6() {
+        command.arg("-6");
+    }
+    let status = command
+        .args(["-c", "1", "-W", "1", "-M", "do", "-s"])
+        .arg((mtu - header_size(target)).to_string())
+        .arg(target.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await?;
+    debug!("MTU {} to {}: {}", mtu, target, status);
+    Ok(status.success())
+}
+
+/// Finds the path MTU to `target` by pinging with the don't fragment bit set
+pub async fn mtu_probe(target: IpAddr) -> Result<u16> {
+    if fits(target, MAX_MTU).await? {
+        return Ok(MAX_MTU);
+    }
+    if !fits(target, MIN_MTU).await? {
+        return Err(anyhow!("{} doesn't answer pings", target));
+    }
+    // Invariant: `low` fits, `high` doesn't
+    let (mut low, mut high) = (MIN_MTU, MAX_MTU);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if fits(target, mid).await? {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(low)
+}
+
+/// Probes the path MTU to `target` and logs it, warning if it is unusually small
+pub async fn check_mtu(target: String) {
+    let mtu = match crate::ping::resolve(&target).await {
+        Ok(addr) => mtu_probe(addr).await,
+        Err(err) => Err(err),
+    };
+    match mtu {
+        Ok(mtu) if mtu < MTU_WARN => warn!(
+            %target,
+            mtu,
+            "Path MTU below {} bytes, check VPNs and tunnels",
+            MTU_WARN
+        ),
+        Ok(mtu) => info!(%target, mtu, "Path MTU"),
+        Err(err) => warn!(%target, "Couldn't probe path MTU: {}", err),
+    }
+}
@@ -0,0 +1,321 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chrono::{SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::task::JoinSet;
+use tracing::debug;
+
+use crate::speedtest::{ServerInfo, SpeedtestResult};
+
+/// Lists the servers closest to the client
+const SERVER_LIST_URL: &str =
+    "https://www.speedtest.net/api/js/servers?engine=js&https_functional=true&limit=10";
+
+/// Number of candidate servers whose latency is measured
+const CANDIDATES: usize = 5;
+
+/// Parallel connections used for the download and upload
+const STREAMS: usize = 4;
+
+/// Maximum time spent on each of download and upload
+const TRANSFER_TIME: Duration = Duration::from_secs(10);
+
+/// Bytes sent per upload request
+const UPLOAD_SIZE: usize = 8 * 1024 * 1024;
+
+/// Page loading the fast.com app, whose script contains the API token
+const FAST_COM_URL: &str = "https://fast.com/";
+
+/// Hands out the fast.com download targets
+const FAST_COM_API_URL: &str = "https://api.fast.com/netflix/speedtest/v2";
+
+#[derive(Debug, Deserialize)]
+struct Server {
+    /// Upload endpoint, the other files live next to it
+    url: String,
+    name: String,
+    sponsor: String,
+    country: String,
+    host: String,
+    id: String,
+    #[serde(default)]
+    distance: f64,
+}
+
+impl Server {
+    fn base_url(&self) -> &str {
+        self.url
+            .rsplit_once('/')
+            .map_or(self.url.as_str(), |(base, _)| base)
+    }
+}
+
+/// Round trip time of a tiny request to `server` in milliseconds
+async fn latency(client: &reqwest::Client, server: &Server) -> Result<f64> {
+    let url = format!("{}/latency.txt", server.base_url());
+    let mut best = f64::INFINITY;
+    for _ in 0..3 {
+        let start = Instant::now();
+        client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        best = best.min(start.elapsed().as_secs_f64() * 1000.0);
+    }
+    Ok(best)
+}
+
+/// Downloads from each of `urls` in parallel until `deadline` and returns the bytes received
+async fn download(client: &reqwest::Client, urls: Vec<String>, deadline: Instant) -> Result<u64> {
+    let mut streams = JoinSet::new();
+    for url in urls {
+        let client = client.clone();
+        streams.spawn(async move {
+            let mut response = client.get(url).send().await?.error_for_status()?;
+            let mut received = 0;
+            while Instant::now() < deadline {
+                match response.chunk().await? {
+                    Some(chunk) => received += chunk.len() as u64,
+                    None => break,
+                }
+            }
+            Ok::<_, anyhow::Error>(received)
+        });
+    }
+    let mut total = 0;
+    while let Some(received) = streams.join_next().await {
+        total += received??;
+    }
+    Ok(total)
+}
+
+/// Uploads to each of `urls` in parallel until `deadline` and returns the bytes sent
+async fn upload(client: &reqwest::Client, urls: Vec<String>, deadline: Instant) -> Result<u64> {
+    let payload = bytes::Bytes::from(vec![b'0'; UPLOAD_SIZE]);
+    let mut streams = JoinSet::new();
+    for url in urls {
+        let client = client.clone();
+        let payload = payload.clone();
+        streams.spawn(async move {
+            let mut sent = 0;
+            while Instant::now() < deadline {
+                client
+                    .post(&url)
+                    .body(payload.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                sent += payload.len() as u64;
+            }
+            Ok::<_, anyhow::Error>(sent)
+        });
+    }
+    let mut total = 0;
+    while let Some(sent) = streams.join_next().await {
+        total += sent??;
+    }
+    Ok(total)
+}
+
+/// Download and upload speed in bits per second along with the bytes transferred
+async fn measure(
+    client: &reqwest::Client,
+    download_urls: Vec<String>,
+    upload_urls: Vec<String>,
+) -> Result<(f64, u64, f64, u64)> {
+    let start = Instant::now();
+    let bytes_received = download(client, download_urls, start + TRANSFER_TIME).await?;
+    let download_bps = bytes_received as f64 * 8.0 / start.elapsed().as_secs_f64();
+
+    let start = Instant::now();
+    let bytes_sent = upload(client, upload_urls, start + TRANSFER_TIME).await?;
+    let upload_bps = bytes_sent as f64 * 8.0 / start.elapsed().as_secs_f64();
+
+    Ok((download_bps, bytes_received, upload_bps, bytes_sent))
+}
+
+/// Runs a speedtest against the Speedtest.net server with the lowest latency
+pub async fn native_speedtest() -> Result<SpeedtestResult> {
+    let client = reqwest::Client::builder()
+        .timeout(TRANSFER_TIME * 2)
+        .build()?;
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+
+    let servers: Vec<Server> = client
+        .get(SERVER_LIST_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut best = None;
+    for server in servers.into_iter().take(CANDIDATES) {
+        match latency(&client, &server).await {
+            Ok(ping) if best.as_ref().is_none_or(|(best, _)| ping < *best) => {
+                best = Some((ping, server))
+            }
+            Ok(_) => {}
+            Err(err) => debug!("Skipping speedtest server {}: {}", server.host, err),
+        }
+    }
+    let (ping, server) = best.ok_or(anyhow!("No speedtest server reachable"))?;
+    debug!("Speedtest server {} with {:.1} ms", server.host, ping);
+
+    let download_urls = (0..STREAMS)
+        .map(|i| format!("{}/random4000x4000.jpg?x={}", server.base_url(), i))
+        .collect();
+    let upload_urls = vec![server.url.clone(); STREAMS];
+    let (download_bps, bytes_received, upload_bps, bytes_sent) =
+        measure(&client, download_urls, upload_urls).await?;
+
+    Ok(SpeedtestResult {
+        download: download_bps,
+        upload: upload_bps,
+        ping,
+        timestamp,
+        server: ServerInfo {
+            host: server.host,
+            url: Some(server.url),
+            id: Some(server.id),
+            name: Some(server.name),
+            sponsor: Some(server.sponsor),
+            country: Some(server.country),
+            distance: Some(server.distance),
+            latency: Some(ping),
+            extra: Map::new(),
+        },
+        client: None,
+        bytes_sent,
+        bytes_received,
+        geolocation: None,
+        extra: Map::new(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct FastComResponse {
+    client: Value,
+    targets: Vec<FastComTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FastComTarget {
+    url: String,
+    #[serde(default)]
+    location: Value,
+}
+
+/// The range endpoint of a fast.com target, which serves or accepts `size` bytes
+fn fast_com_range(url: &str, size: usize) -> String {
+    url.replacen("/speedtest", &format!("/speedtest/range/0-{}", size), 1)
+}
+
+/// Scrapes the API token out of the fast.com app script
+async fn fast_com_token(client: &reqwest::Client) -> Result<String> {
+    lazy_static! {
+        static ref SCRIPT: Regex = Regex::new(r#"src="(/app-[^"]+\.js)""#).unwrap();
+        static ref TOKEN: Regex = Regex::new(r#"token:"([A-Za-z0-9]+)""#).unwrap();
+    }
+    let page = client.get(FAST_COM_URL).send().await?.text().await?;
+    let script = SCRIPT
+        .captures(&page)
+        .and_then(|cap| cap.get(1))
+        .ok_or(anyhow!("fast.com app script not found"))?
+        .as_str();
+    let script = client
+        .get(format!("{}{}", FAST_COM_URL.trim_end_matches('/'), script))
+        .send()
+        .await?
+        .text()
+        .await?;
+    Ok(TOKEN
+        .captures(&script)
+        .and_then(|cap| cap.get(1))
+        .ok_or(anyhow!("fast.com token not found"))?
+        .as_str()
+        .to_string())
+}
+
+/// Runs a speedtest against the Netflix servers behind fast.com
+pub async fn fast_com_speedtest() -> Result<SpeedtestResult> {
+    let client = reqwest::Client::builder()
+        .timeout(TRANSFER_TIME * 2)
+        .build()?;
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+
+    let token = fast_com_token(&client).await?;
+    let response: FastComResponse = client
+        .get(FAST_COM_API_URL)
+        .query(&[
+            ("https", "true"),
+            ("token", token.as_str()),
+            ("urlCount", &STREAMS.to_string()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let first = response
+        .targets
+        .first()
+        .ok_or(anyhow!("fast.com returned no targets"))?;
+
+    let mut ping = f64::INFINITY;
+    for _ in 0..3 {
+        let start = Instant::now();
+        client
+            .get(fast_com_range(&first.url, 0))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        ping = ping.min(start.elapsed().as_secs_f64() * 1000.0);
+    }
+
+    let download_urls = response
+        .targets
+        .iter()
+        .map(|target| fast_com_range(&target.url, 25 * 1024 * 1024))
+        .collect();
+    let upload_urls = response
+        .targets
+        .iter()
+        .map(|target| fast_com_range(&target.url, UPLOAD_SIZE))
+        .collect();
+    let (download_bps, bytes_received, upload_bps, bytes_sent) =
+        measure(&client, download_urls, upload_urls).await?;
+
+    let mut server = Map::new();
+    server.insert("location".to_string(), first.location.clone());
+
+    Ok(SpeedtestResult {
+        download: download_bps,
+        upload: upload_bps,
+        ping,
+        timestamp,
+        server: ServerInfo {
+            host: reqwest::Url::parse(&first.url)?
+                .host_str()
+                .unwrap_or_default()
+                .to_string(),
+            latency: Some(ping),
+            extra: server,
+            ..ServerInfo::default()
+        },
+        client: serde_json::from_value(response.client).ok(),
+        bytes_sent,
+        bytes_received,
+        geolocation: None,
+        extra: Map::new(),
+    })
+}
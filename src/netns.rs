@@ -0,0 +1,31 @@
+use std::path::Path;
+
+use anyhow::Result;
+use tokio::process::Command;
+
+/// Makes `command` run in the network namespace at `path`, e.g. `/var/run/netns/mynamespace`,
+/// by calling `setns(2)` in the child before it executes
+#[cfg(target_os = "linux")]
+pub fn enter(command: &mut Command, path: &Path) -> Result<()> {
+    use anyhow::Context;
+    use nix::sched::{setns, CloneFlags};
+
+    // Opened with `O_CLOEXEC`, so the command doesn't inherit it
+    let namespace = std::fs::File::open(path)
+        .with_context(|| format!("Couldn't open the network namespace {}", path.display()))?;
+    // SAFETY: `setns` is a single system call, which is safe between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            setns(&namespace, CloneFlags::CLONE_NEWNET).map_err(std::io::Error::from)
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn enter(_command: &mut Command, path: &Path) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Can't enter the network namespace {}, they only exist on Linux",
+        path.display()
+    ))
+}
@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+use crate::outage::Outage;
+use crate::speedtest::SpeedtestResult;
+
+/// Time the script may run before it is killed
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the script is told about
+#[derive(Debug)]
+pub enum Event<'a> {
+    OutageStart { target: &'a str, loss_pct: f64 },
+    OutageRecovered(&'a Outage),
+    SpeedtestComplete(&'a SpeedtestResult),
+}
+
+impl Event<'_> {
+    /// The event as `CONMON_*` environment variables
+    fn env(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Event::OutageStart { target, loss_pct } => vec![
+                ("CONMON_EVENT", "outage_start".to_string()),
+                ("CONMON_TARGET", target.to_string()),
+                ("CONMON_LOSS_PCT", loss_pct.to_string()),
+            ],
+            Event::OutageRecovered(outage) => vec![
+                ("CONMON_EVENT", "outage_recovered".to_string()),
+                ("CONMON_TARGET", outage.target.clone()),
+                ("CONMON_LOSS_PCT", outage.peak_loss_pct.to_string()),
+                ("CONMON_START", outage.start.clone()),
+                ("CONMON_DURATION_S", outage.duration_s.to_string()),
+                ("CONMON_WORST_STATE", outage.worst_state.clone()),
+            ],
+            Event::SpeedtestComplete(result) => vec![
+                ("CONMON_EVENT", "speedtest_complete".to_string()),
+                ("CONMON_DOWNLOAD_BPS", result.download.to_string()),
+                ("CONMON_UPLOAD_BPS", result.upload.to_string()),
+                ("CONMON_PING_MS", result.ping.to_string()),
+            ],
+        }
+    }
+}
+
+/// Runs `script` in the background with the event in its environment
+pub fn notify(script: &Path, event: Event) {
+    let env = event.env();
+    tokio::spawn(run(script.to_path_buf(), env));
+}
+
+async fn run(script: PathBuf, env: Vec<(&'static str, String)>) {
+    let status = Command::new(&script).envs(env).kill_on_drop(true).status();
+    match timeout(SCRIPT_TIMEOUT, status).await {
+        Ok(Ok(status)) if status.success() => debug!("{} finished", script.display()),
+        Ok(Ok(status)) => warn!("{} failed: {}", script.display(), status),
+        Ok(Err(err)) => warn!("Couldn't run {}: {}", script.display(), err),
+        Err(_) => warn!(
+            "Killed {} after {} seconds",
+            script.display(),
+            SCRIPT_TIMEOUT.as_secs()
+        ),
+    }
+}
@@ -0,0 +1,117 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use opentelemetry::trace::{Span, SpanKind, Tracer, TracerProvider};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+
+use crate::config::OtlpProtocol;
+use crate::labels::Labels;
+use crate::ping::{epoch_micros, Ping};
+use crate::sinks::Sink;
+use crate::speedtest::SpeedtestResult;
+
+/// Exports pings and speedtests as OpenTelemetry spans over OTLP
+pub struct Otel {
+    provider: SdkTracerProvider,
+    tracer: SdkTracer,
+}
+
+impl Otel {
+    /// Exports to `endpoint`, with the labels as resource attributes
+    pub fn new(endpoint: &str, protocol: OtlpProtocol, labels: &Labels) -> Result<Self> {
+        let exporter = match protocol {
+            OtlpProtocol::Grpc => SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?,
+            // The endpoint is used as is, so it needs the path of the traces signal
+            OtlpProtocol::HttpProtobuf => SpanExporter::builder()
+                .with_http()
+                .with_protocol(Protocol::HttpBinary)
+                .with_endpoint(if endpoint.ends_with("/v1/traces") {
+                    endpoint.to_string()
+                } else {
+                    format!("{}/v1/traces", endpoint.trim_end_matches('/'))
+                })
+                .build()?,
+        };
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name("con-mon")
+                    .with_attributes(
+                        labels
+                            .iter()
+                            .map(|(name, value)| KeyValue::new(name.clone(), value.clone())),
+                    )
+                    .build(),
+            )
+            .build();
+        let tracer = provider.tracer("con-mon");
+        Ok(Self { provider, tracer })
+    }
+
+    /// Records a ping as a span ending when the reply arrived
+    pub fn record_ping(&self, ping: &Ping, peer_ip: Option<IpAddr>) -> Result<()> {
+        let end = UNIX_EPOCH + Duration::from_micros(epoch_micros(&ping.timestamp)?.try_into()?);
+        let start = end - Duration::from_secs_f64(ping.ms / 1000.0);
+        let mut attributes = vec![
+            KeyValue::new("net.peer.name", ping.target.clone()),
+            KeyValue::new("rtt.ms", ping.ms),
+        ];
+        if let Some(ip) = peer_ip {
+            attributes.push(KeyValue::new("net.peer.ip", ip.to_string()));
+        }
+        self.tracer
+            .span_builder("ping")
+            .with_kind(SpanKind::Client)
+            .with_start_time(start)
+            .with_attributes(attributes)
+            .start(&self.tracer)
+            .end_with_timestamp(end);
+        Ok(())
+    }
+
+    /// Records a speedtest that ran from `start` to `end`
+    pub fn record_speedtest(&self, result: &SpeedtestResult, start: SystemTime, end: SystemTime) {
+        self.tracer
+            .span_builder("speedtest")
+            .with_kind(SpanKind::Client)
+            .with_start_time(start)
+            .with_attributes([
+                KeyValue::new("download.bps", result.download),
+                KeyValue::new("upload.bps", result.upload),
+                KeyValue::new("ping.ms", result.ping),
+            ])
+            .start(&self.tracer)
+            .end_with_timestamp(end);
+    }
+
+    /// Exports the spans that are still buffered
+    pub fn shutdown(&self) -> Result<()> {
+        self.provider.shutdown()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for Arc<Otel> {
+    fn name(&self) -> &'static str {
+        "OpenTelemetry"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        self.record_ping(ping, None)
+    }
+
+    async fn write_reply(&mut self, ping: &Ping, peer: Option<IpAddr>) -> Result<()> {
+        self.record_ping(ping, peer)
+    }
+}
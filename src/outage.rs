@@ -0,0 +1,200 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::dmesg::KernelMessage;
+use crate::stats::StatsWindow;
+
+/// An incident in which a target was degraded or offline, written once it recovered
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Outage {
+    pub target: String,
+    pub start: String,
+    /// Older records call this `end`
+    #[serde(alias = "end")]
+    pub recovered_at: String,
+    /// Length of the whole incident
+    pub duration_s: i64,
+    /// Time spent offline during the incident
+    #[serde(default)]
+    pub total_downtime_s: i64,
+    pub peak_loss_pct: f64,
+    /// Worst state reached, `degraded` or `offline`
+    #[serde(default)]
+    pub worst_state: String,
+    /// Kernel log from when the incident started, with `--correlate-dmesg`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dmesg_context: Vec<KernelMessage>,
+}
+
+/// Connectivity to a target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Online,
+    /// Some pings are lost
+    Degraded {
+        since: DateTime<Utc>,
+    },
+    /// Every recent ping was lost
+    Offline {
+        since: DateTime<Utc>,
+    },
+}
+
+impl State {
+    fn name(&self) -> &'static str {
+        match self {
+            State::Online => "online",
+            State::Degraded { .. } => "degraded",
+            State::Offline { .. } => "offline",
+        }
+    }
+}
+
+/// Thresholds of consecutive pings moving a target between states
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    /// Lost pings after which an online target is degraded
+    pub degraded_after: u32,
+    /// Lost pings after which a target is offline
+    pub offline_after: u32,
+    /// Replies after which a target is online again
+    pub recover_after: u32,
+    /// Loss percentage over the window that also counts as degraded
+    pub loss_pct: f64,
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Moves a target between online, degraded and offline and records the incidents
+#[derive(Debug)]
+pub struct OutageTracker {
+    target: String,
+    /// Whether each of the recent pings was lost
+    lost: StatsWindow<bool>,
+    thresholds: Thresholds,
+    state: State,
+    failures: u32,
+    successes: u32,
+    /// Start, peak loss and offline time of the ongoing incident
+    incident: Option<(DateTime<Utc>, f64, chrono::Duration)>,
+    worst: State,
+}
+
+impl OutageTracker {
+    pub fn new(target: &str, size: usize, thresholds: Thresholds) -> Self {
+        Self {
+            target: target.to_string(),
+            lost: StatsWindow::new(size),
+            thresholds,
+            state: State::Online,
+            failures: 0,
+            successes: 0,
+            incident: None,
+            worst: State::Online,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Percentage of the recent pings that got no reply
+    pub fn loss_pct(&self) -> f64 {
+        if self.lost.is_empty() {
+            return 0.0;
+        }
+        100.0 * self.lost.sum() / self.lost.len() as f64
+    }
+
+    /// Records a ping answered or lost at `now`, returning the incident it ended if any
+    pub fn record(&mut self, received: bool, now: DateTime<Utc>) -> Option<Outage> {
+        self.lost.push(!received);
+        if received {
+            self.successes += 1;
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+            self.successes = 0;
+        }
+
+        let loss = self.loss_pct();
+        if let Some((_, peak, _)) = &mut self.incident {
+            *peak = peak.max(loss);
+        }
+        let next = match self.state {
+            State::Online | State::Degraded { .. }
+                if self.failures >= self.thresholds.offline_after =>
+            {
+                State::Offline { since: now }
+            }
+            State::Online
+                if self.failures >= self.thresholds.degraded_after
+                    || loss > self.thresholds.loss_pct =>
+            {
+                State::Degraded { since: now }
+            }
+            State::Degraded { .. } | State::Offline { .. }
+                if self.successes >= self.thresholds.recover_after
+                    && loss <= self.thresholds.loss_pct =>
+            {
+                State::Online
+            }
+            state => state,
+        };
+        if next == self.state {
+            return None;
+        }
+        self.transition(next, now, loss)
+    }
+
+    fn transition(&mut self, next: State, now: DateTime<Utc>, loss: f64) -> Option<Outage> {
+        if let State::Offline { since } = self.state {
+            if let Some((_, _, offline)) = &mut self.incident {
+                *offline += now - since;
+            }
+        }
+        self.state = next;
+        match next {
+            State::Online => {
+                let (start, peak, offline) = self.incident.take()?;
+                let outage = Outage {
+                    target: self.target.clone(),
+                    start: rfc3339(start),
+                    recovered_at: rfc3339(now),
+                    duration_s: (now - start).num_seconds(),
+                    total_downtime_s: offline.num_seconds(),
+                    peak_loss_pct: peak,
+                    worst_state: self.worst.name().to_string(),
+                    dmesg_context: Vec::new(),
+                };
+                self.worst = State::Online;
+                info!(
+                    target = %self.target,
+                    state = "online",
+                    duration_s = outage.duration_s,
+                    total_downtime_s = outage.total_downtime_s,
+                    peak_loss_pct = outage.peak_loss_pct,
+                    "Connectivity recovered"
+                );
+                Some(outage)
+            }
+            State::Degraded { .. } | State::Offline { .. } => {
+                self.incident
+                    .get_or_insert((now, loss, chrono::Duration::zero()));
+                if matches!(next, State::Offline { .. }) || self.worst == State::Online {
+                    self.worst = next;
+                }
+                warn!(
+                    target = %self.target,
+                    state = next.name(),
+                    loss_pct = loss,
+                    "Connectivity changed"
+                );
+                None
+            }
+        }
+    }
+}
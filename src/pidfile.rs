@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use nix::errno::Errno;
+use nix::sys::signal::kill;
+use nix::unistd::Pid;
+use tracing::warn;
+
+/// Holds the pid file for as long as con-mon runs, removing it when dropped
+pub struct PidFile {
+    path: PathBuf,
+}
+
+/// Whether a process with `pid` exists, it may belong to another user
+fn is_running(pid: i32) -> bool {
+    matches!(kill(Pid::from_raw(pid), None), Ok(()) | Err(Errno::EPERM))
+}
+
+impl PidFile {
+    /// Writes the PID of this process to `path`, failing if another instance still runs
+    pub fn acquire(path: &Path) -> Result<Self> {
+        if let Ok(content) = std::fs::read_to_string(path) {
+            match content.trim().parse::<i32>() {
+                Ok(pid) if pid > 0 && is_running(pid) => {
+                    return Err(anyhow!(
+                        "con-mon is already running with PID {} according to {}",
+                        pid,
+                        path.display()
+                    ))
+                }
+                _ => warn!("Replacing stale pid file {}", path.display()),
+            }
+        }
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Couldn't write pid file {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!("Couldn't remove pid file {}: {}", self.path.display(), err);
+        }
+    }
+}
@@ -0,0 +1,1080 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::{process::Stdio, str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::sync::oneshot::channel;
+use tokio::time::interval;
+use tokio::{process::Command, time};
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+
+use tracing::{debug, error, info, warn};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::Config;
+use crate::metrics::SharedMetrics;
+use crate::outage::OutageTracker;
+use crate::sinks::{FileSink, Sink, SinkFactory};
+use crate::stats::StatsWindow;
+use crate::Shared;
+
+/// Time between two connection quality checks of a target
+const QUALITY_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ping {
+    #[serde(rename = "ts")]
+    pub timestamp: String,
+    pub target: String,
+    #[serde(rename = "latency_ms")]
+    pub ms: f64,
+}
+
+/// A ping as written to the JSONL ping log
+#[derive(Serialize)]
+pub struct PingRecord<'a> {
+    #[serde(flatten)]
+    pub ping: &'a Ping,
+    pub jitter_ms: f64,
+}
+
+impl fmt::Display for Ping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.target, self.timestamp, self.ms)
+    }
+}
+
+/// Output of the different `ping` implementations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PingFormat {
+    /// iputils with `-D`: `[1700000000.123456] 64 bytes from 1.1.1.1: icmp_seq=1 ttl=55 time=11.3 ms`
+    #[default]
+    LinuxD,
+    /// Without a timestamp: `64 bytes from 1.1.1.1: icmp_seq=0 ttl=55 time=11.3 ms`
+    MacOS,
+    /// `64 bytes from 1.1.1.1: seq=0 ttl=55 time=11.300 ms`
+    BusyBox,
+}
+
+impl PingFormat {
+    /// Order the formats are tried in when parsing a line
+    const ALL: [Self; 3] = [Self::LinuxD, Self::MacOS, Self::BusyBox];
+
+    pub fn regex(self) -> &'static Regex {
+        lazy_static! {
+            static ref LINUX_D: Regex = Regex::new(
+                r"\[(?P<timestamp>.+)\].*from (?P<target>\S+?):? .*time=(?P<ms>\d+(?:\.\d+)?)"
+            )
+            .unwrap();
+            static ref MACOS: Regex = Regex::new(
+                r"^\d+ bytes from (?P<target>\S+?):? icmp_seq=\d+ .*time=(?P<ms>\d+(?:\.\d+)?)"
+            )
+            .unwrap();
+            static ref BUSYBOX: Regex = Regex::new(
+                r"^\d+ bytes from (?P<target>\S+?):? seq=\d+ .*time=(?P<ms>\d+(?:\.\d+)?)"
+            )
+            .unwrap();
+        }
+        match self {
+            Self::LinuxD => &LINUX_D,
+            Self::MacOS => &MACOS,
+            Self::BusyBox => &BUSYBOX,
+        }
+    }
+
+    /// Parses a reply, timestamped now if the format has no timestamp
+    pub fn parse(self, line: &str) -> Option<Ping> {
+        let cap = self.regex().captures(line)?;
+        Some(Ping {
+            timestamp: cap
+                .name("timestamp")
+                .map_or_else(unix_timestamp, |timestamp| timestamp.as_str().to_string()),
+            target: cap["target"].to_string(),
+            ms: cap["ms"].parse().ok()?,
+        })
+    }
+
+    /// Format of `ping`, the binary that is run
+    pub async fn detect(ping: &Path) -> Self {
+        if cfg!(target_os = "macos") {
+            return Self::MacOS;
+        }
+        // BusyBox rejects `-V` with its usage, which names it
+        let version = Command::new(ping).arg("-V").kill_on_drop(true).output();
+        match time::timeout(Duration::from_secs(2), version).await {
+            Ok(Ok(output))
+                if String::from_utf8_lossy(&output.stdout).contains("BusyBox")
+                    || String::from_utf8_lossy(&output.stderr).contains("BusyBox") =>
+            {
+                Self::BusyBox
+            }
+            _ => Self::LinuxD,
+        }
+    }
+}
+
+impl FromStr for Ping {
+    type Err = Error;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match PingFormat::ALL
+            .iter()
+            .find_map(|format| format.parse(string))
+        {
+            Some(ping) => Ok(ping),
+            None => Self::from_log_line(string),
+        }
+    }
+}
+
+impl Ping {
+    /// Parses a line of the text ping log, the format `Display` writes
+    fn from_log_line(line: &str) -> Result<Self> {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [target, timestamp, ms] if epoch_micros(timestamp).is_ok() => Ok(Self {
+                target: target.to_string(),
+                timestamp: timestamp.to_string(),
+                ms: ms.parse()?,
+            }),
+            _ => Err(anyhow!("No capture groups found")),
+        }
+    }
+}
+
+/// Inserts a suffix into a file name, `ping_stats.json` becomes `ping_stats-1.1.1.1.json`
+pub fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// Counters of sent and received pings for a single target, and the loss over the recent ones
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PingStats {
+    pub sent: u64,
+    pub received: u64,
+    /// Whether each of the recent pings was lost, starts empty after a restart
+    #[serde(skip)]
+    recent: StatsWindow<bool>,
+    /// Whether the loss was above the threshold at the last check
+    #[serde(skip)]
+    pub above_threshold: bool,
+}
+
+impl PingStats {
+    /// Loads previously persisted counters, starting from zero if there are none, the loss is
+    /// calculated over the last `window` pings
+    pub fn load(path: &Path, window: usize) -> Self {
+        let stats: Self = File::open(path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default();
+        Self {
+            recent: StatsWindow::new(window),
+            ..stats
+        }
+    }
+
+    /// Counts a sent ping
+    pub fn record(&mut self, received: bool) {
+        self.sent += 1;
+        if received {
+            self.received += 1;
+        }
+        self.recent.push(!received);
+    }
+
+    /// Atomically replaces the counters stored at `path`
+    fn persist(&self, sinks: &dyn SinkFactory, path: &Path) -> Result<()> {
+        sinks.replace(path, &serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Percentage of the recent pings that got no reply
+    pub fn loss_pct(&self) -> f64 {
+        100.0 * self.recent.mean()
+    }
+
+    /// Checks the loss threshold, publishes the loss and persists the counters to `path`
+    pub fn update(&mut self, config: &Config, path: &Path, target: &str, metrics: &SharedMetrics) {
+        let threshold = config.loss_threshold;
+        self.check_threshold(target, threshold);
+        metrics
+            .lock()
+            .unwrap()
+            .packet_loss_ratio
+            .insert(target.to_string(), self.loss_pct() / 100.0);
+        if let Err(err) = self.persist(config.sinks(), path) {
+            warn!("Couldn't persist ping stats for {}: {}", target, err);
+        }
+    }
+
+    /// Logs when the packet loss crosses the threshold in either direction
+    fn check_threshold(&mut self, target: &str, threshold: f64) {
+        let loss = self.loss_pct();
+        if loss > threshold && !self.above_threshold {
+            warn!(
+                %target,
+                loss_pct = loss,
+                threshold_pct = threshold,
+                "Packet loss above threshold"
+            );
+            self.above_threshold = true;
+        } else if loss <= threshold && self.above_threshold {
+            info!(%target, loss_pct = loss, "Packet loss back below threshold");
+            self.above_threshold = false;
+        }
+    }
+}
+
+/// Sliding window of recent latencies for a single target
+#[derive(Debug, Clone)]
+pub struct JitterWindow {
+    pub samples: StatsWindow<f64>,
+    /// Whether the jitter was above the threshold at the last check
+    pub above_threshold: bool,
+}
+
+impl JitterWindow {
+    pub fn new(size: usize) -> Self {
+        Self {
+            samples: StatsWindow::new(size),
+            above_threshold: false,
+        }
+    }
+
+    pub fn push(&mut self, ms: f64) {
+        self.samples.push(ms);
+    }
+
+    /// Mean latency of the samples
+    pub fn mean(&self) -> f64 {
+        self.samples.mean()
+    }
+
+    /// Mean absolute difference between consecutive samples
+    pub fn jitter(&self) -> f64 {
+        if self.samples.len() < 2 {
+            return 0.0;
+        }
+        let deltas = self.samples.iter().zip(self.samples.iter().skip(1));
+        let sum: f64 = deltas.map(|(a, b)| (b - a).abs()).sum();
+        sum / (self.samples.len() - 1) as f64
+    }
+
+    /// Logs when the jitter crosses the threshold in either direction
+    fn check_threshold(&mut self, target: &str, threshold: f64) {
+        let jitter = self.jitter();
+        if jitter > threshold && !self.above_threshold {
+            warn!(
+                %target,
+                jitter_ms = jitter,
+                threshold_ms = threshold,
+                "Jitter above threshold"
+            );
+            self.above_threshold = true;
+        } else if jitter <= threshold && self.above_threshold {
+            info!(%target, jitter_ms = jitter, "Jitter back below threshold");
+            self.above_threshold = false;
+        }
+    }
+}
+
+/// Everything kept about a target across pinger restarts
+pub struct TargetState {
+    /// Name the pings are logged under, the host with the interface if given
+    pub target: String,
+    pub host: String,
+    /// Address the host last resolved to
+    pub addr: Option<IpAddr>,
+    pub interface: Option<String>,
+    pub stats: PingStats,
+    pub stats_path: PathBuf,
+    pub jitter: JitterWindow,
+    pub outages: OutageTracker,
+    /// Whether a reply arrived since the pinger was last started
+    pub replied: bool,
+    /// Taken by the first reply
+    pub ready: Option<mpsc::UnboundedSender<()>>,
+    /// When the connection quality was last checked
+    pub quality_checked: Option<std::time::Instant>,
+    /// Kernel log from the start of the current outage
+    pub dmesg_context: Vec<crate::dmesg::KernelMessage>,
+}
+
+impl TargetState {
+    pub fn new(config: &Config, host: &str, interface: Option<&str>) -> Self {
+        let target = match interface {
+            Some(interface) => format!("{}%{}", host, interface),
+            None => host.to_string(),
+        };
+        let stats_path = suffixed_path(&config.ping_stats, &target);
+        Self {
+            host: host.to_string(),
+            addr: None,
+            interface: interface
+                .map(str::to_string)
+                .or_else(|| config.bind_interface.clone()),
+            stats: PingStats::load(&stats_path, config.loss_window),
+            stats_path,
+            jitter: JitterWindow::new(config.jitter_window),
+            outages: OutageTracker::new(
+                &target,
+                config.outage_window,
+                crate::outage::Thresholds {
+                    degraded_after: config.degraded_after,
+                    offline_after: config.offline_after,
+                    recover_after: config.recover_after,
+                    loss_pct: config.outage_threshold,
+                },
+            ),
+            target,
+            replied: false,
+            ready: None,
+            quality_checked: None,
+            dmesg_context: Vec::new(),
+        }
+    }
+
+    /// Counts a reply and writes it to all outputs
+    pub async fn reply(
+        &mut self,
+        config: &Config,
+        sinks: &mut [Box<dyn Sink>],
+        shared: &Shared,
+        mut ping: Ping,
+    ) -> Result<()> {
+        self.stats.record(true);
+        self.replied = true;
+        if let Some(ready) = self.ready.take() {
+            let _ = ready.send(());
+        }
+        // Tag with the configured name rather than what ping reports
+        let reported = std::mem::replace(&mut ping.target, self.target.clone());
+        let at = epoch_micros(&ping.timestamp)
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .unwrap_or_else(Utc::now);
+        self.jitter.push(ping.ms);
+        self.jitter
+            .check_threshold(&self.target, config.jitter_threshold);
+        if let Some(live) = &shared.raw_live {
+            // Nobody listening is fine
+            let _ = live.send(ping.clone());
+        }
+        if let Some(pings) = &shared.raw_pings {
+            pings.lock().unwrap().push(ping.clone());
+        }
+        if let Some(console) = &shared.console {
+            console.record(&ping);
+        }
+        if let Some(alarm) = &shared.alarm {
+            if let Err(err) = alarm.check(&ping) {
+                warn!("Couldn't ring the bell: {}", err);
+            }
+        }
+        // The sinks, the API and the metrics get the noised latency, `tail`, the console and the
+        // alarm the actual one
+        let exported = match &config.export_privacy {
+            Some(privacy) => Ping {
+                ms: privacy.noised(ping.ms),
+                ..ping.clone()
+            },
+            None => ping.clone(),
+        };
+        if let Some(live) = &shared.live {
+            // Nobody listening is fine
+            let _ = live.send(exported.clone());
+        }
+        if let Some(pings) = &shared.pings {
+            pings.lock().unwrap().push(exported.clone());
+        }
+        let peer = reported.parse().ok();
+        for sink in sinks.iter_mut() {
+            if let Err(err) = sink.write_reply(&exported, peer).await {
+                warn!("Couldn't write ping to {}: {}", sink.name(), err);
+            }
+        }
+        shared
+            .percentiles
+            .lock()
+            .unwrap()
+            .record(&exported.target, exported.ms);
+        shared
+            .metrics
+            .lock()
+            .unwrap()
+            .ping_latency_ms
+            .insert(exported.target, exported.ms);
+        self.update_stats(config, shared);
+        self.track_outage(config, true, at);
+        Ok(())
+    }
+
+    /// Counts a ping that got no usable reply by `at`
+    pub fn lost(&mut self, config: &Config, shared: &Shared, at: DateTime<Utc>) {
+        self.stats.record(false);
+        self.update_stats(config, shared);
+        self.track_outage(config, false, at);
+    }
+
+    /// Appends the outage a ping ended to the outage log
+    fn track_outage(&mut self, config: &Config, received: bool, at: DateTime<Utc>) {
+        let was_online = self.outages.state() == crate::outage::State::Online;
+        let ended = self.outages.record(received, at);
+        let started = was_online && self.outages.state() != crate::outage::State::Online;
+        if config.traceroute && started {
+            tokio::spawn(crate::traceroute::trace_to_log(
+                self.host.clone(),
+                config.traceroute_max_hops,
+                config.sinks(),
+                config.traceroute_log.clone(),
+            ));
+        }
+        if let Some(script) = config.notify_script.as_deref().filter(|_| started) {
+            let loss_pct = self.outages.loss_pct();
+            crate::notify_script::notify(
+                script,
+                crate::notify_script::Event::OutageStart {
+                    target: &self.target,
+                    loss_pct,
+                },
+            );
+        }
+        if config.correlate_dmesg && started {
+            match crate::dmesg::recent(config.dmesg_lines) {
+                Ok(messages) => self.dmesg_context = messages,
+                Err(err) => warn!("Couldn't read the kernel log: {}", err),
+            }
+        }
+        if let Some(mut outage) = ended {
+            outage.dmesg_context = std::mem::take(&mut self.dmesg_context);
+            if config.mtu_probe {
+                tokio::spawn(crate::mtu::check_mtu(self.host.clone()));
+            }
+            if let Some(script) = &config.notify_script {
+                crate::notify_script::notify(
+                    script,
+                    crate::notify_script::Event::OutageRecovered(&outage),
+                );
+            }
+            let result = config
+                .sinks()
+                .append(&config.outage_log)
+                .and_then(|mut outfile| {
+                    serde_json::to_writer(&mut outfile, &outage)?;
+                    outfile.write_all(b"\n")?;
+                    outfile.flush()?;
+                    Ok(())
+                });
+            if let Err(err) = result {
+                warn!("Couldn't write outage of {}: {}", self.target, err);
+            }
+        }
+    }
+
+    fn update_stats(&mut self, config: &Config, shared: &Shared) {
+        self.stats
+            .update(config, &self.stats_path, &self.target, &shared.metrics);
+        if let Some(alerts) = &shared.alerts {
+            // The lifetime loss would barely move during an outage after days of uptime
+            alerts.check(&self.target, self.outages.loss_pct(), self.jitter.mean());
+        }
+        if let (Some(failover), Some(interface)) = (&shared.failover, &self.interface) {
+            failover
+                .lock()
+                .unwrap()
+                .record(interface, &self.host, self.outages.loss_pct());
+        }
+        self.check_quality(shared);
+    }
+
+    /// Logs, publishes and alerts on the connection quality once every `QUALITY_INTERVAL`
+    pub fn check_quality(&mut self, shared: &Shared) {
+        match self.quality_checked {
+            // The first interval starts with the first ping
+            None => {
+                self.quality_checked = Some(std::time::Instant::now());
+                return;
+            }
+            Some(checked) if checked.elapsed() < QUALITY_INTERVAL => return,
+            Some(_) => self.quality_checked = Some(std::time::Instant::now()),
+        }
+        let quality = crate::quality::ConnectionQuality {
+            latency_ms: self.jitter.mean(),
+            jitter_ms: self.jitter.jitter(),
+            loss_pct: self.outages.loss_pct(),
+        };
+        let score = quality.quality_score();
+        info!(
+            target = %self.target,
+            score,
+            latency_ms = quality.latency_ms,
+            latency_p95_ms = self.jitter.samples.percentile(0.95),
+            latency_std_dev_ms = self.jitter.samples.std_dev(),
+            jitter_ms = quality.jitter_ms,
+            loss_pct = quality.loss_pct,
+            "Connection quality"
+        );
+        shared
+            .metrics
+            .lock()
+            .unwrap()
+            .quality_score
+            .insert(self.target.clone(), f64::from(score));
+        if let Some(alerts) = &shared.alerts {
+            alerts.check_quality(&self.target, score, quality.loss_pct, quality.latency_ms);
+        }
+    }
+}
+
+/// Opens everything the parsed pings are written to
+pub fn ping_sinks(config: &Config, shared: &Shared) -> Result<Vec<Box<dyn Sink>>> {
+    let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(FileSink::open(config)?)];
+    if let Some(path) = &config.ping_db {
+        sinks.push(Box::new(config.sinks().sqlite(path)?));
+    }
+    if let Some(influx) = &shared.influx {
+        sinks.push(Box::new(influx.clone()));
+    }
+    if let Some(csv) = &shared.csv {
+        sinks.push(Box::new(csv.clone()));
+    }
+    if let Some(otel) = &shared.otel {
+        sinks.push(Box::new(otel.clone()));
+    }
+    if let Some(clickhouse) = &shared.clickhouse {
+        sinks.push(Box::new(clickhouse.clone()));
+    }
+    if let Some(mqtt) = &shared.mqtt {
+        sinks.push(Box::new(mqtt.clone()));
+    }
+    if let Some(parquet) = &shared.parquet {
+        sinks.push(Box::new(parquet.clone()));
+    }
+    if let Some(heatmap) = &shared.heatmap {
+        sinks.push(Box::new(heatmap.clone()));
+    }
+    Ok(sinks)
+}
+
+pub async fn pinger(
+    config: &Config,
+    state: &mut TargetState,
+    shared: &Shared,
+    addr: IpAddr,
+    mut sinks: Vec<Box<dyn Sink>>,
+) -> Result<()> {
+    let target = state.host.clone();
+    let mut command = Command::new(&config.ping_command);
+    if addr.is_ipv6() {
+        command.arg("-6");
+    }
+    if let Some(interface) = &state.interface {
+        command.arg("-I").arg(interface);
+    }
+    // Only iputils can timestamp the replies
+    if shared.ping_format == PingFormat::LinuxD {
+        command.arg("-D");
+    }
+    if let Some(path) = &config.netns {
+        crate::netns::enter(&mut command, path)?;
+    }
+    let mut handle = command
+        .arg(addr.to_string())
+        .stdout(Stdio::piped())
+        // Don't leave ping running if the runtime shuts down before it is killed
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let stdout = handle.stdout.take().unwrap();
+    let reader = BufReader::new(stdout);
+
+    let (send, recv) = channel::<()>();
+    // Ensure the child process is spawned in the runtime so it can
+    // make progress on its own while we await for any output.
+    tokio::spawn(async move {
+        tokio::select! {
+            status = handle.wait() => {
+                debug!("child status was: {}", status.expect("no status"));
+            },
+            _ = recv => handle.kill().await.expect("Kill failed"),
+        }
+    });
+
+    let mut lines = reader.lines();
+    loop {
+        let line = time::timeout(Duration::from_secs(config.ping_timeout), lines.next_line());
+        match line.await {
+            Ok(Ok(Some(line))) => {
+                // Timeout check passed
+                if line.starts_with("PING ") {
+                    // Header printed before the first reply
+                    continue;
+                }
+                match line.parse::<Ping>() {
+                    Ok(ping) => {
+                        debug!(%target, latency_ms = ping.ms, "Ping");
+                        state.reply(config, &mut sinks, shared, ping).await?
+                    }
+                    Err(err) => {
+                        warn!(%target, line, "Couldn't parse: {}", err);
+                        state.lost(config, shared, Utc::now());
+                    }
+                }
+            }
+            Ok(Ok(None)) => {
+                error!("Task gave no more lines");
+                break;
+            }
+            _ => {
+                state.lost(config, shared, Utc::now());
+                info!(
+                    "Ping to {} took longer than {} seconds.",
+                    target, config.ping_timeout
+                );
+                info!("Restarting pinger for {}", target);
+                break;
+            }
+        }
+    }
+
+    // Kill the ping process, unless it is gone already
+    let _ = send.send(());
+    Ok(())
+}
+
+/// Waits for `wait` while counting down the cooldown gauge of `targets`
+pub async fn cool_down(targets: &[String], wait: Duration, metrics: &SharedMetrics) {
+    let end = time::Instant::now() + wait;
+    loop {
+        let remaining = end.saturating_duration_since(time::Instant::now());
+        {
+            let gauge = &mut metrics.lock().unwrap().pinger_cooldown_remaining_s;
+            for target in targets {
+                gauge.insert(target.clone(), remaining.as_secs_f64());
+            }
+        }
+        if remaining.is_zero() {
+            return;
+        }
+        time::sleep(remaining.min(Duration::from_secs(1))).await;
+    }
+}
+
+/// Pings all targets with a single `fping` process
+pub async fn fping_pinger(
+    config: &Config,
+    states: &mut [TargetState],
+    shared: &Shared,
+    mut sinks: Vec<Box<dyn Sink>>,
+) -> Result<()> {
+    let mut command = Command::new("fping");
+    command.args(["-D", "-l", "-p", "1000"]);
+    // Every target of a pinger goes through the same interface
+    if let Some(interface) = states.first().and_then(|state| state.interface.as_ref()) {
+        command.arg("-I").arg(interface);
+    }
+    if let Some(path) = &config.netns {
+        crate::netns::enter(&mut command, path)?;
+    }
+    let mut handle = command
+        .args(states.iter().map(|state| &state.host))
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Couldn't run fping, is it installed?")?;
+
+    let mut parser = crate::fping::FpingParser::default();
+    let mut lines = BufReader::new(handle.stdout.take().unwrap()).lines();
+    loop {
+        let line = time::timeout(Duration::from_secs(config.ping_timeout), lines.next_line());
+        match line.await {
+            Ok(Ok(Some(line))) => {
+                let Some(record) = parser.parse(&line) else {
+                    debug!(line, "Ignoring fping output");
+                    continue;
+                };
+                let Some(state) = states.iter_mut().find(|state| state.host == record.host) else {
+                    warn!(line, "fping reported on an unknown host");
+                    continue;
+                };
+                for _ in 0..record.missed {
+                    state.lost(config, shared, Utc::now());
+                }
+                match record.ping {
+                    Some(ping) => {
+                        debug!(target = %state.target, latency_ms = ping.ms, "Ping");
+                        state.reply(config, &mut sinks, shared, ping).await?;
+                    }
+                    None => state.lost(config, shared, Utc::now()),
+                }
+            }
+            Ok(Ok(None)) => {
+                error!("fping gave no more lines");
+                break;
+            }
+            _ => {
+                for state in states.iter_mut() {
+                    state.lost(config, shared, Utc::now());
+                }
+                info!(
+                    "fping reported nothing for {} seconds, restarting it",
+                    config.ping_timeout
+                );
+                break;
+            }
+        }
+    }
+
+    // Already gone if it exited by itself
+    let _ = handle.kill().await;
+    Ok(())
+}
+
+/// Seconds since the epoch, formatted like the timestamps of `ping -D`
+pub fn unix_timestamp() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{}.{:06}", now.as_secs(), now.subsec_micros())
+}
+
+/// Address of a ping target given as an IP address or host name
+pub async fn resolve(target: &str) -> Result<IpAddr> {
+    if let Ok(addr) = target.parse() {
+        return Ok(addr);
+    }
+    tokio::net::lookup_host((target, 0))
+        .await?
+        .next()
+        .map(|addr| addr.ip())
+        .ok_or(anyhow!("Couldn't resolve {}", target))
+}
+
+/// Converts a timestamp of seconds since the epoch like those of `ping -D` to microseconds
+pub fn epoch_micros(timestamp: &str) -> Result<i64> {
+    let (secs, frac) = timestamp.split_once('.').unwrap_or((timestamp, "0"));
+    let micros: String = frac.chars().chain("000000".chars()).take(6).collect();
+    let micros: i64 = micros.parse()?;
+    secs.parse::<i64>()?
+        .checked_mul(1_000_000)
+        .and_then(|secs| secs.checked_add(micros))
+        .ok_or(anyhow!("Timestamp {} out of range", timestamp))
+}
+
+/// Pings once a second over a raw ICMP socket instead of spawning `ping`
+pub async fn native_pinger(
+    config: &Config,
+    state: &mut TargetState,
+    shared: &Shared,
+    addr: IpAddr,
+    mut sinks: Vec<Box<dyn Sink>>,
+) -> Result<()> {
+    let target = state.target.clone();
+
+    let mut iv = interval(Duration::from_secs(1));
+    loop {
+        iv.tick().await;
+        let timeout = Duration::from_secs(config.ping_timeout);
+        match crate::icmp::native_ping(addr, timeout, state.interface.clone()).await {
+            Ok(ms) => {
+                let ping = Ping {
+                    target: target.clone(),
+                    timestamp: unix_timestamp(),
+                    ms,
+                };
+                debug!(%target, latency_ms = ping.ms, "Ping");
+                state.reply(config, &mut sinks, shared, ping).await?;
+            }
+            Err(err) if crate::icmp::is_timeout(&err) => {
+                state.lost(config, shared, Utc::now());
+                info!(
+                    "Ping to {} took longer than {} seconds.",
+                    target, config.ping_timeout
+                );
+                info!("Restarting pinger for {}", target);
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Sends batches of `size` pings over a raw ICMP socket every `--ping-interval`
+pub async fn batch_pinger(
+    config: &Config,
+    state: &mut TargetState,
+    shared: &Shared,
+    addr: IpAddr,
+    size: u16,
+    mut sinks: Vec<Box<dyn Sink>>,
+) -> Result<()> {
+    let target = state.target.clone();
+    let mut batch_log = config.sinks().append(&config.batch_log)?;
+
+    let mut iv = interval(Duration::from_secs(config.ping_interval));
+    loop {
+        iv.tick().await;
+        let timestamp = unix_timestamp();
+        let timeout = Duration::from_secs(config.batch_timeout);
+        let rtts =
+            crate::icmp::native_ping_batch(addr, size, timeout, state.interface.clone()).await?;
+        for rtt in &rtts {
+            match rtt {
+                Some(ms) => {
+                    let ping = Ping {
+                        target: target.clone(),
+                        timestamp: timestamp.clone(),
+                        ms: *ms,
+                    };
+                    state.reply(config, &mut sinks, shared, ping).await?;
+                }
+                None => state.lost(config, shared, Utc::now()),
+            }
+        }
+
+        // Logged like the pings, with the noised latencies
+        let rtts: Vec<_> = match &config.export_privacy {
+            Some(privacy) => rtts
+                .iter()
+                .map(|rtt| rtt.map(|ms| privacy.noised(ms)))
+                .collect(),
+            None => rtts,
+        };
+        let batch = crate::icmp::PingBatch::new(&target, timestamp, &rtts);
+        debug!(%target, lost_count = batch.lost_count, avg_ms = batch.avg_ms, "Ping batch");
+        serde_json::to_writer(&mut batch_log, &batch)?;
+        batch_log.write_all(b"\n")?;
+        batch_log.flush()?;
+
+        if batch.lost_count == batch.sent {
+            info!("No replies to a batch of pings to {}", target);
+            info!("Restarting pinger for {}", target);
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `line`, asserting the target and latency
+    fn parsed(line: &str, target: &str, ms: f64) -> Ping {
+        let ping: Ping = line.parse().unwrap();
+        assert_eq!(ping.target, target, "target of {}", line);
+        assert_eq!(ping.ms, ms, "latency of {}", line);
+        ping
+    }
+
+    /// Asserts that `ping` was timestamped while the test ran
+    fn assert_timestamped_now(ping: &Ping) {
+        let now = epoch_micros(&unix_timestamp()).unwrap();
+        let sent = epoch_micros(&ping.timestamp).unwrap();
+        assert!(
+            (now - sent).abs() < 60_000_000,
+            "timestamp {}",
+            ping.timestamp
+        );
+    }
+
+    #[test]
+    fn parses_linux_ping() {
+        let ping = parsed(
+            "[1700000000.123456] 64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=11.3 ms",
+            "1.1.1.1",
+            11.3,
+        );
+        assert_eq!(ping.timestamp, "1700000000.123456");
+
+        let ping = parsed(
+            "[1700000000.123456] 64 bytes from one.one.one.one (1.1.1.1): icmp_seq=1 ttl=57 time=11.3 ms",
+            "one.one.one.one",
+            11.3,
+        );
+        assert_eq!(ping.timestamp, "1700000000.123456");
+
+        parsed(
+            "[1700000000.123456] 64 bytes from 2606:4700:4700::1111: icmp_seq=1 ttl=57 time=9.87 ms",
+            "2606:4700:4700::1111",
+            9.87,
+        );
+    }
+
+    #[test]
+    fn parses_macos_ping() {
+        let ping = parsed(
+            "64 bytes from 1.1.1.1: icmp_seq=0 ttl=55 time=11.325 ms",
+            "1.1.1.1",
+            11.325,
+        );
+        assert_timestamped_now(&ping);
+    }
+
+    #[test]
+    fn parses_busybox_ping() {
+        let ping = parsed(
+            "64 bytes from 1.1.1.1: seq=0 ttl=55 time=11.300 ms",
+            "1.1.1.1",
+            11.3,
+        );
+        assert_timestamped_now(&ping);
+    }
+
+    #[test]
+    fn parses_sub_millisecond_times() {
+        parsed(
+            "[1700000000.123456] 64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time=0.045 ms",
+            "127.0.0.1",
+            0.045,
+        );
+        parsed(
+            "64 bytes from 127.0.0.1: seq=0 ttl=64 time=0.031 ms",
+            "127.0.0.1",
+            0.031,
+        );
+    }
+
+    #[test]
+    fn parses_times_above_a_second() {
+        parsed(
+            "[1700000000.123456] 64 bytes from 1.1.1.1: icmp_seq=7 ttl=57 time=1234 ms",
+            "1.1.1.1",
+            1234.0,
+        );
+        parsed(
+            "64 bytes from 1.1.1.1: icmp_seq=7 ttl=55 time=15234.567 ms",
+            "1.1.1.1",
+            15234.567,
+        );
+    }
+
+    #[test]
+    fn parses_ping_log_lines() {
+        let ping = parsed("1.1.1.1 1700000000.123456 11.3", "1.1.1.1", 11.3);
+        assert_eq!(ping.timestamp, "1700000000.123456");
+        assert_eq!(ping.to_string(), "1.1.1.1 1700000000.123456 11.3");
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "",
+            "PING 1.1.1.1 (1.1.1.1) 56(84) bytes of data.",
+            "Request timeout for icmp_seq 3",
+            "From 192.168.1.1 icmp_seq=1 Destination Host Unreachable",
+            "[1700000000.123456] 64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=abc ms",
+            "64 bytes from 1.1.1.1: icmp_seq=1 ttl=57",
+            "--- 1.1.1.1 ping statistics ---",
+            "1.1.1.1 yesterday 11.3",
+            "1.1.1.1 1700000000.123456 fast",
+        ] {
+            let result = line.parse::<Ping>();
+            assert!(result.is_err(), "{:?} parsed as {:?}", line, result);
+        }
+    }
+
+    #[test]
+    fn loss_is_over_the_recent_pings() {
+        let mut stats = PingStats::load(Path::new("/nonexistent/ping_stats.json"), 4);
+        assert_eq!(stats.loss_pct(), 0.0);
+        for _ in 0..1000 {
+            stats.record(true);
+        }
+        // A lifetime ratio would still be below 1 %
+        for _ in 0..3 {
+            stats.record(false);
+        }
+        assert_eq!(stats.loss_pct(), 75.0);
+        for _ in 0..4 {
+            stats.record(true);
+        }
+        assert_eq!(stats.loss_pct(), 0.0);
+        assert_eq!((stats.sent, stats.received), (1007, 1004));
+    }
+
+    #[tokio::test]
+    async fn metrics_and_api_get_the_noised_latency() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            ping_stats: dir.path().join("ping_stats.json"),
+            export_privacy: Some("epsilon=0.01".parse().unwrap()),
+            ..Config::default()
+        };
+        let shared = Shared {
+            pings: Some(std::sync::Arc::new(std::sync::Mutex::new(
+                crate::ring_buffer::RingBuffer::new(10),
+            ))),
+            ..Shared::default()
+        };
+        let mut state = TargetState::new(&config, "1.1.1.1", None);
+        let ping = Ping {
+            timestamp: "1700000000.000000".to_string(),
+            target: "1.1.1.1".to_string(),
+            ms: 12.3,
+        };
+        state.reply(&config, &mut [], &shared, ping).await.unwrap();
+
+        let exported = shared.pings.as_ref().unwrap().lock().unwrap().last(1)[0].ms;
+        assert_ne!(exported, 12.3);
+        let metrics = shared.metrics.lock().unwrap().render();
+        assert!(
+            metrics.contains(&format!(
+                "conmon_ping_latency_ms{{target=\"1.1.1.1\"}} {}",
+                exported
+            )),
+            "{}",
+            metrics
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn ping_log_lines_round_trip(
+            secs in 0u64..10_000_000_000,
+            micros in 0u32..1_000_000,
+            target in "[a-z0-9][a-z0-9.:-]{0,40}",
+            ms in proptest::num::f64::POSITIVE | proptest::num::f64::ZERO,
+        ) {
+            let ping = Ping {
+                timestamp: format!("{}.{:06}", secs, micros),
+                target,
+                ms,
+            };
+            let parsed: Ping = ping.to_string().parse().unwrap();
+            proptest::prop_assert_eq!(parsed.timestamp, ping.timestamp);
+            proptest::prop_assert_eq!(parsed.target, ping.target);
+            proptest::prop_assert_eq!(parsed.ms, ping.ms);
+        }
+
+        #[test]
+        fn parsing_arbitrary_strings_never_panics(line in proptest::prelude::any::<String>()) {
+            let _ = line.parse::<Ping>();
+        }
+
+        #[test]
+        fn parsing_ping_like_lines_never_panics(
+            line in r"(\[[0-9.]{0,24}\] )?[0-9]{1,3} bytes from [a-z0-9.:]{1,20}:? (icmp_)?seq=[0-9]{1,6} ttl=[0-9]{1,3} time=[0-9.]{0,24} ms",
+            log_line in r"[a-z0-9.:]{1,20} -?[0-9]{1,24}(\.[0-9+-]{0,10})? -?[0-9.e]{1,24}",
+        ) {
+            let _ = line.parse::<Ping>();
+            let _ = log_line.parse::<Ping>();
+        }
+    }
+}
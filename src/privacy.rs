@@ -0,0 +1,145 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Laplace noise added to the latencies before they are written, e.g. `epsilon=0.1`
+///
+/// The noise has a scale of `sensitivity / epsilon`, where the sensitivity is how many
+/// milliseconds a single latency is hidden within, 1 by default.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ExportPrivacy {
+    pub epsilon: f64,
+    pub sensitivity: f64,
+}
+
+impl ExportPrivacy {
+    /// Scale of the Laplace distribution the noise is drawn from
+    pub fn scale(&self) -> f64 {
+        self.sensitivity / self.epsilon
+    }
+
+    /// `ms` with noise added, latencies below 0 are written as 0
+    pub fn noised(&self, ms: f64) -> f64 {
+        (ms + laplace(&mut fastrand::Rng::new(), self.scale())).max(0.0)
+    }
+}
+
+/// Draws from the Laplace distribution centered on 0 by inverting its CDF
+fn laplace(rng: &mut fastrand::Rng, scale: f64) -> f64 {
+    let u = rng.f64() - 0.5;
+    // `u` is -0.5 once in 2^53 draws, which would be infinite noise
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).max(f64::MIN_POSITIVE).ln()
+}
+
+impl FromStr for ExportPrivacy {
+    type Err = Error;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut epsilon = None;
+        let mut sensitivity = 1.0;
+        for setting in string.split(',') {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or(anyhow!("`{}` is not `key=value`", setting))?;
+            let value: f64 = value
+                .trim()
+                .parse()
+                .with_context(|| format!("`{}` is no number", value))?;
+            match key.trim() {
+                "epsilon" => epsilon = Some(value),
+                "sensitivity" => sensitivity = value,
+                key => return Err(anyhow!("Unknown privacy setting `{}`", key)),
+            }
+        }
+        let epsilon = epsilon.ok_or(anyhow!("`epsilon` is missing in `{}`", string))?;
+        if !(epsilon > 0.0 && sensitivity > 0.0) {
+            return Err(anyhow!("`epsilon` and `sensitivity` have to be above 0"));
+        }
+        Ok(Self {
+            epsilon,
+            sensitivity,
+        })
+    }
+}
+
+impl fmt::Display for ExportPrivacy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "epsilon={},sensitivity={}",
+            self.epsilon, self.sensitivity
+        )
+    }
+}
+
+impl TryFrom<String> for ExportPrivacy {
+    type Error = Error;
+    fn try_from(string: String) -> Result<Self> {
+        string.parse()
+    }
+}
+
+impl From<ExportPrivacy> for String {
+    fn from(privacy: ExportPrivacy) -> Self {
+        privacy.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings() {
+        let privacy: ExportPrivacy = "epsilon=0.1".parse().unwrap();
+        assert_eq!(privacy.epsilon, 0.1);
+        assert_eq!(privacy.sensitivity, 1.0);
+        assert_eq!(privacy.scale(), 10.0);
+
+        let privacy: ExportPrivacy = "epsilon=0.5, sensitivity=2".parse().unwrap();
+        assert_eq!(privacy.scale(), 4.0);
+        assert_eq!(
+            privacy.to_string().parse::<ExportPrivacy>().unwrap(),
+            privacy
+        );
+
+        assert!("sensitivity=2".parse::<ExportPrivacy>().is_err());
+        assert!("epsilon=0".parse::<ExportPrivacy>().is_err());
+        assert!("epsilon=0.1,delta=0.01".parse::<ExportPrivacy>().is_err());
+    }
+
+    #[test]
+    fn noise_follows_laplace_distribution() {
+        let mut rng = fastrand::Rng::with_seed(7);
+        let scale = 10.0;
+        let samples: Vec<f64> = (0..200_000).map(|_| laplace(&mut rng, scale)).collect();
+        let n = samples.len() as f64;
+
+        // Laplace(0, b) has mean 0, mean absolute deviation b and variance 2b²
+        let mean = samples.iter().sum::<f64>() / n;
+        let mean_abs = samples.iter().map(|x| x.abs()).sum::<f64>() / n;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        assert!(mean.abs() < 0.1, "mean {}", mean);
+        assert!((mean_abs - scale).abs() < 0.1, "mean absolute {}", mean_abs);
+        assert!(
+            (variance - 2.0 * scale * scale).abs() < 3.0,
+            "variance {}",
+            variance
+        );
+
+        // P(|X| > t) = e^(-t/b)
+        for t in [5.0, 10.0, 30.0] {
+            let tail = samples.iter().filter(|x| x.abs() > t).count() as f64 / n;
+            let expected = f64::exp(-t / scale);
+            assert!((tail - expected).abs() < 0.005, "P(|X| > {}) = {}", t, tail);
+        }
+    }
+
+    #[test]
+    fn noised_latency_is_never_negative() {
+        let privacy: ExportPrivacy = "epsilon=0.01".parse().unwrap();
+        assert!((0..1000).all(|_| privacy.noised(1.0) >= 0.0));
+    }
+}
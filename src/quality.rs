@@ -0,0 +1,37 @@
+/// Latency at or below which it doesn't lower the score
+const GOOD_LATENCY_MS: f64 = 20.0;
+
+/// Latency at or above which its part of the score is zero
+const BAD_LATENCY_MS: f64 = 500.0;
+
+/// Jitter at or below which it doesn't lower the score
+const GOOD_JITTER_MS: f64 = 5.0;
+
+/// Jitter at or above which its part of the score is zero
+const BAD_JITTER_MS: f64 = 100.0;
+
+/// Packet loss at or above which its part of the score is zero
+const BAD_LOSS_PCT: f64 = 10.0;
+
+/// Recent latency, jitter and packet loss of a target
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionQuality {
+    pub latency_ms: f64,
+    pub jitter_ms: f64,
+    pub loss_pct: f64,
+}
+
+/// 1 at or below `good`, 0 at or above `bad` and linear in between
+fn normalize(value: f64, good: f64, bad: f64) -> f64 {
+    (1.0 - (value - good) / (bad - good)).clamp(0.0, 1.0)
+}
+
+impl ConnectionQuality {
+    /// Score from 0 to 100, packet loss and latency weigh twice as much as jitter
+    pub fn quality_score(&self) -> u8 {
+        let latency = normalize(self.latency_ms, GOOD_LATENCY_MS, BAD_LATENCY_MS);
+        let jitter = normalize(self.jitter_ms, GOOD_JITTER_MS, BAD_JITTER_MS);
+        let loss = normalize(self.loss_pct, 0.0, BAD_LOSS_PCT);
+        (100.0 * (0.4 * latency + 0.2 * jitter + 0.4 * loss)).round() as u8
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat};
+use clap::{Args, Subcommand};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::outage::Outage;
+use crate::quality::ConnectionQuality;
+use crate::report::read_jsonl;
+use crate::sinks::sqlite::SqliteSink;
+use crate::stats::percentile;
+
+/// Prints latency and packet loss statistics from the ping database, or the outage log
+#[derive(Debug, Clone, Args)]
+pub struct QueryArgs {
+    /// Only include pings, or outages starting, at or after this ISO-8601 time, e.g.
+    /// `2024-01-15T08:00:00Z`
+    #[arg(long, global = true)]
+    from: Option<String>,
+
+    /// Only include pings, or outages starting, at or before this ISO-8601 time
+    #[arg(long, global = true)]
+    to: Option<String>,
+
+    /// Only include pings or outages of this target
+    #[arg(long, global = true)]
+    target: Option<String>,
+
+    #[command(subcommand)]
+    pub what: Option<Query>,
+}
+
+/// What to query instead of the ping statistics
+#[derive(Debug, Clone, Subcommand)]
+pub enum Query {
+    /// Print the outages from the outage log that started in the time range
+    Outages,
+}
+
+/// Parses an ISO-8601 date or date-time into seconds since the epoch, naive times are UTC
+pub fn parse_time(time: &str) -> Result<f64> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(time) {
+        return Ok(time.timestamp_micros() as f64 / 1e6);
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(time.and_utc().timestamp_micros() as f64 / 1e6);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(time, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() as f64);
+    }
+    Err(anyhow!("Couldn't parse `{}` as an ISO-8601 time", time))
+}
+
+/// Length of the periods of a quality time series
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    Second,
+    #[default]
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Resolution {
+    fn seconds(self) -> i64 {
+        match self {
+            Self::Second => 1,
+            Self::Minute => 60,
+            Self::Hour => 60 * 60,
+            Self::Day => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Connection quality of all matching targets over one period
+#[derive(Debug, Serialize)]
+pub struct QualityPoint {
+    /// Start of the period
+    pub ts: String,
+    pub quality: u8,
+    /// `None` if no ping got a reply
+    pub latency_p50: Option<f64>,
+    pub loss_pct: f64,
+}
+
+/// Replies of a period and the pings expected in it
+#[derive(Debug, Default)]
+struct Period {
+    latencies: Vec<f64>,
+    /// Absolute differences between consecutive replies of the same target
+    deltas: Vec<f64>,
+    expected: f64,
+}
+
+/// Connection quality per `resolution` period between `from` and `to`, in seconds since the
+/// epoch, of `target` or all targets
+pub fn quality_series(
+    db: &Path,
+    from: Option<f64>,
+    to: Option<f64>,
+    target: Option<&str>,
+    resolution: Resolution,
+) -> Result<Vec<QualityPoint>> {
+    let sink = SqliteSink::open_read_only(db)?;
+    let rows: Vec<(String, f64, f64)> = sink
+        .connection()
+        .prepare(
+            "SELECT target, CAST(timestamp AS REAL) AS ts, latency_ms FROM pings
+             WHERE (?1 IS NULL OR ts >= ?1)
+               AND (?2 IS NULL OR ts <= ?2)
+               AND (?3 IS NULL OR target = ?3)
+             ORDER BY target, ts",
+        )?
+        .query_map(params![from, to, target], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let length = resolution.seconds();
+    let mut periods: BTreeMap<i64, Period> = BTreeMap::new();
+    for pings in rows.chunk_by(|a, b| a.0 == b.0) {
+        for (i, (_, ts, ms)) in pings.iter().enumerate() {
+            let start = (*ts as i64).div_euclid(length) * length;
+            let period = periods.entry(start).or_default();
+            period.latencies.push(*ms);
+            if let Some((_, previous_ts, previous_ms)) = i.checked_sub(1).map(|i| &pings[i]) {
+                if (*previous_ts as i64).div_euclid(length) * length == start {
+                    period.deltas.push((ms - previous_ms).abs());
+                }
+            }
+        }
+        // Only replies are stored, so one ping a second is expected while the target was pinged
+        let first = pings[0].1.floor() as i64;
+        let last = pings[pings.len() - 1].1.floor() as i64 + 1;
+        let mut start = first.div_euclid(length) * length;
+        while start < last {
+            let overlap = (start + length).min(last) - start.max(first);
+            periods.entry(start).or_default().expected += overlap as f64;
+            start += length;
+        }
+    }
+
+    Ok(periods
+        .into_iter()
+        .map(|(start, mut period)| {
+            period.latencies.sort_by(f64::total_cmp);
+            let replies = period.latencies.len() as f64;
+            let loss_pct = 100.0 * (1.0 - replies / period.expected.max(replies));
+            let mean = |values: &[f64]| match values.len() {
+                0 => 0.0,
+                len => values.iter().sum::<f64>() / len as f64,
+            };
+            let quality = ConnectionQuality {
+                latency_ms: mean(&period.latencies),
+                jitter_ms: mean(&period.deltas),
+                loss_pct,
+            };
+            QualityPoint {
+                ts: DateTime::from_timestamp(start, 0)
+                    .unwrap_or_default()
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                // Nothing got through, however good the latency of no reply looks
+                quality: if period.latencies.is_empty() {
+                    0
+                } else {
+                    quality.quality_score()
+                },
+                latency_p50: percentile(&period.latencies, 0.5),
+                loss_pct,
+            }
+        })
+        .collect())
+}
+
+/// Prints the outages of `outage_log` matching `args`, oldest first
+pub fn outages(outage_log: &Path, args: &QueryArgs) -> Result<()> {
+    let from = args.from.as_deref().map(parse_time).transpose()?;
+    let to = args.to.as_deref().map(parse_time).transpose()?;
+
+    let outages: Vec<Outage> = read_jsonl(outage_log)?;
+    let mut printed = 0;
+    for outage in outages {
+        if args
+            .target
+            .as_ref()
+            .is_some_and(|target| *target != outage.target)
+        {
+            continue;
+        }
+        let Ok(start) = parse_time(&outage.start) else {
+            continue;
+        };
+        if from.is_some_and(|from| start < from) || to.is_some_and(|to| start > to) {
+            continue;
+        }
+        println!(
+            "{}: {} to {}, {} s, {} s offline, peak loss {:.2}%{}",
+            outage.target,
+            outage.start,
+            outage.recovered_at,
+            outage.duration_s,
+            outage.total_downtime_s,
+            outage.peak_loss_pct,
+            match outage.worst_state.as_str() {
+                "" => String::new(),
+                state => format!(", {}", state),
+            }
+        );
+        printed += 1;
+    }
+    if printed == 0 {
+        println!("No outages");
+    }
+    Ok(())
+}
+
+/// Prints the statistics for every target matching `args`
+pub fn run(db: &Path, args: &QueryArgs) -> Result<()> {
+    let from = args.from.as_deref().map(parse_time).transpose()?;
+    let to = args.to.as_deref().map(parse_time).transpose()?;
+
+    let sink = SqliteSink::open_read_only(db)?;
+    let conn = sink.connection();
+
+    let targets: Vec<String> = match &args.target {
+        Some(target) => vec![target.clone()],
+        None => conn
+            .prepare("SELECT DISTINCT target FROM pings ORDER BY target")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?,
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT CAST(timestamp AS REAL), latency_ms FROM pings
+         WHERE target = ?1
+           AND (?2 IS NULL OR CAST(timestamp AS REAL) >= ?2)
+           AND (?3 IS NULL OR CAST(timestamp AS REAL) <= ?3)",
+    )?;
+    for target in targets {
+        let rows: Vec<(f64, f64)> = stmt
+            .query_map(params![target, from, to], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        if rows.is_empty() {
+            println!("{}: no pings", target);
+            continue;
+        }
+
+        let first = rows.iter().map(|(ts, _)| *ts).fold(f64::INFINITY, f64::min);
+        let last = rows
+            .iter()
+            .map(|(ts, _)| *ts)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let mut latencies: Vec<f64> = rows.iter().map(|(_, ms)| *ms).collect();
+        latencies.sort_by(f64::total_cmp);
+
+        let avg = latencies.iter().sum::<f64>() / latencies.len() as f64;
+        // Only replies are stored, so the loss is estimated from the one second ping interval
+        let expected = ((last - first).round() + 1.0).max(latencies.len() as f64);
+        let loss = 100.0 * (1.0 - latencies.len() as f64 / expected);
+
+        println!("{}:", target);
+        println!("  pings:       {}", latencies.len());
+        println!("  min latency: {:.3} ms", latencies[0]);
+        println!("  max latency: {:.3} ms", latencies[latencies.len() - 1]);
+        println!("  avg latency: {:.3} ms", avg);
+        println!(
+            "  p95 latency: {:.3} ms",
+            percentile(&latencies, 0.95).unwrap_or_default()
+        );
+        println!("  packet loss: {:.2}%", loss);
+    }
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use clap::Args;
+use rusqlite::params;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::outage::Outage;
+use crate::query::parse_time;
+use crate::session::is_session_start;
+use crate::sinks::sqlite::SqliteSink;
+use crate::speedtest::SpeedtestResult;
+
+/// Page the report data is filled into
+const TEMPLATE: &str = include_str!("../assets/report.html");
+
+/// Chart library embedded into the page so it works offline
+const CHART_JS: &str = include_str!("../assets/chart.js");
+
+/// Maximum points drawn per latency line, more are averaged into buckets
+const MAX_LATENCY_POINTS: usize = 1000;
+
+/// Width of the packet loss bars in seconds
+const LOSS_BUCKET: f64 = 3600.0;
+
+/// Writes an HTML page with charts of the recorded pings, speedtests and outages
+#[derive(Debug, Clone, Args)]
+pub struct ReportArgs {
+    /// Only include data at or after this ISO-8601 time, e.g. `2024-01-01`
+    #[arg(long)]
+    from: Option<String>,
+
+    /// Only include data at or before this ISO-8601 time
+    #[arg(long)]
+    to: Option<String>,
+
+    /// File the report is written to
+    #[arg(long, default_value = "report.html")]
+    output: PathBuf,
+}
+
+/// Reads the records of a JSONL file, skipping lines that don't parse
+pub fn read_jsonl<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Couldn't read {}", path.display())),
+    };
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if is_session_start(&line) {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(err) => warn!("Skipping line of {}: {}", path.display(), err),
+        }
+    }
+    Ok(records)
+}
+
+/// Seconds since the epoch of an RFC 3339 time
+fn rfc3339_secs(time: &str) -> Option<f64> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|time| time.timestamp_micros() as f64 / 1e6)
+}
+
+fn in_range(ts: f64, from: Option<f64>, to: Option<f64>) -> bool {
+    from.is_none_or(|from| ts >= from) && to.is_none_or(|to| ts <= to)
+}
+
+/// Averages sorted pings into at most `MAX_LATENCY_POINTS` chart points
+fn latency_points(rows: &[(f64, f64)]) -> Vec<Value> {
+    let (first, last) = (rows[0].0, rows[rows.len() - 1].0);
+    let width = ((last - first) / MAX_LATENCY_POINTS as f64).max(1.0);
+    let mut points = Vec::new();
+    let mut bucket = (first, 0.0, 0);
+    for &(ts, ms) in rows {
+        if ts >= bucket.0 + width {
+            points.push(json!({"x": bucket.0 * 1000.0, "y": bucket.1 / bucket.2 as f64}));
+            bucket = (ts, 0.0, 0);
+        }
+        bucket.1 += ms;
+        bucket.2 += 1;
+    }
+    points.push(json!({"x": bucket.0 * 1000.0, "y": bucket.1 / bucket.2 as f64}));
+    points
+}
+
+/// Loss per hour estimated from the one second ping interval, only replies are stored
+fn loss_per_bucket(rows: &[(f64, f64)], first: f64, last: f64) -> Vec<f64> {
+    let start = (first / LOSS_BUCKET).floor() * LOSS_BUCKET;
+    let count = ((last - start) / LOSS_BUCKET).floor() as usize + 1;
+    let mut received = vec![0usize; count];
+    for &(ts, _) in rows {
+        received[((ts - start) / LOSS_BUCKET) as usize] += 1;
+    }
+    received
+        .iter()
+        .enumerate()
+        .map(|(i, &received)| {
+            let bucket_start = start + i as f64 * LOSS_BUCKET;
+            let expected = (bucket_start + LOSS_BUCKET).min(last) - bucket_start.max(first) + 1.0;
+            (100.0 * (1.0 - received as f64 / expected)).clamp(0.0, 100.0)
+        })
+        .collect()
+}
+
+/// Writes the report for everything recorded between `args.from` and `args.to`
+pub fn run(config: &Config, args: &ReportArgs) -> Result<()> {
+    let from = args.from.as_deref().map(parse_time).transpose()?;
+    let to = args.to.as_deref().map(parse_time).transpose()?;
+
+    let db = config.ping_db.as_deref().unwrap_or(Path::new("ping.db"));
+    let sink = SqliteSink::open_read_only(db)?;
+    let conn = sink.connection();
+    let mut stmt = conn.prepare(
+        "SELECT target, CAST(timestamp AS REAL), latency_ms FROM pings
+         WHERE (?1 IS NULL OR CAST(timestamp AS REAL) >= ?1)
+           AND (?2 IS NULL OR CAST(timestamp AS REAL) <= ?2)
+         ORDER BY target, CAST(timestamp AS REAL)",
+    )?;
+    let rows: Vec<(String, f64, f64)> = stmt
+        .query_map(params![from, to], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut pings: Vec<(String, Vec<(f64, f64)>)> = Vec::new();
+    for (target, ts, ms) in rows {
+        match pings.last_mut() {
+            Some((last, samples)) if *last == target => samples.push((ts, ms)),
+            _ => pings.push((target, vec![(ts, ms)])),
+        }
+    }
+
+    let first = from.unwrap_or_else(|| {
+        pings
+            .iter()
+            .map(|(_, samples)| samples[0].0)
+            .fold(f64::INFINITY, f64::min)
+    });
+    let last = to.unwrap_or_else(|| {
+        pings
+            .iter()
+            .map(|(_, samples)| samples[samples.len() - 1].0)
+            .fold(f64::NEG_INFINITY, f64::max)
+    });
+
+    let latency: Vec<Value> = pings
+        .iter()
+        .map(|(target, samples)| json!({"target": target, "points": latency_points(samples)}))
+        .collect();
+    let (loss, loss_labels) = if pings.is_empty() {
+        (Vec::new(), Vec::new())
+    } else {
+        let loss: Vec<Value> = pings
+            .iter()
+            .map(|(target, samples)| {
+                json!({"target": target, "values": loss_per_bucket(samples, first, last)})
+            })
+            .collect();
+        let start = (first / LOSS_BUCKET).floor() * LOSS_BUCKET;
+        let buckets = ((last - start) / LOSS_BUCKET).floor() as i64 + 1;
+        let labels = (0..buckets)
+            .filter_map(|i| {
+                Utc.timestamp_opt(start as i64 + i * LOSS_BUCKET as i64, 0)
+                    .single()
+            })
+            .map(|time| time.format("%Y-%m-%d %H:00").to_string())
+            .collect();
+        (loss, labels)
+    };
+
+    let speedtests: Vec<SpeedtestResult> = read_jsonl(&config.speedtest_log)?;
+    let (mut download, mut upload) = (Vec::new(), Vec::new());
+    for result in &speedtests {
+        match rfc3339_secs(&result.timestamp) {
+            Some(ts) if in_range(ts, from, to) => {
+                download.push(json!({"x": ts * 1000.0, "y": result.download / 1e6}));
+                upload.push(json!({"x": ts * 1000.0, "y": result.upload / 1e6}));
+            }
+            _ => {}
+        }
+    }
+
+    let outages: Vec<Outage> = read_jsonl(&config.outage_log)?;
+    let outages: Vec<Outage> = outages
+        .into_iter()
+        .filter(|outage| rfc3339_secs(&outage.start).is_some_and(|ts| in_range(ts, from, to)))
+        .collect();
+
+    let data = json!({
+        "latency": latency,
+        "loss": loss,
+        "loss_labels": loss_labels,
+        "download": download,
+        "upload": upload,
+        "outages": outages,
+    });
+    let range = format!(
+        "{} to {}",
+        args.from.as_deref().unwrap_or("the beginning"),
+        args.to.as_deref().unwrap_or("now")
+    );
+    // Keep the data from closing the script element early
+    let data = data.to_string().replace("</", "<\\/");
+    let page = TEMPLATE
+        .replace("{{range}}", &range)
+        .replace("{{chartjs}}", CHART_JS)
+        .replace("{{data}}", &data);
+    std::fs::write(&args.output, page)
+        .with_context(|| format!("Couldn't write report to {}", args.output.display()))?;
+    println!("Wrote report to {}", args.output.display());
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::ping::Ping;
+
+/// Latest pings shared between the pingers and the API
+pub type SharedPings = Arc<Mutex<RingBuffer<Ping>>>;
+
+/// Holds the last `capacity` items, dropping the oldest
+#[derive(Debug)]
+pub struct RingBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T: Clone> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+    }
+
+    /// Up to `count` of the newest items, oldest first
+    pub fn last(&self, count: usize) -> Vec<T> {
+        let skip = self.items.len().saturating_sub(count);
+        self.items.iter().skip(skip).cloned().collect()
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use chrono::{Local, NaiveDate};
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::ping::suffixed_path;
+
+lazy_static! {
+    /// Several writers share a base path, only one of them compresses a finished file
+    static ref ROTATION_LOCK: Mutex<()> = Mutex::new(());
+    /// Finished files being compressed in the background
+    static ref COMPRESSING: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+/// How finished log files are compressed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    Gzip,
+    /// Compresses several times better than gzip, for slow SD cards
+    Zstd,
+    /// Keeps the finished files as they are
+    None,
+}
+
+impl Compression {
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::Gzip => Some("gz"),
+            Self::Zstd => Some("zst"),
+            Self::None => None,
+        }
+    }
+}
+
+/// Size a log may grow to before its oldest lines are removed
+#[derive(Debug, Clone, Copy)]
+pub struct SizeLimit {
+    pub max_bytes: u64,
+    /// Percentage of the lines removed once the file is too large
+    pub trim_pct: f64,
+}
+
+/// Appends to a file named after the current day and compresses it once the day is over
+pub struct RotatingFileWriter {
+    base: PathBuf,
+    date: NaiveDate,
+    file: File,
+    limit: Option<SizeLimit>,
+    compression: Compression,
+}
+
+impl RotatingFileWriter {
+    /// Opens today's file for `base`, `ping.log` becomes `ping-2024-01-15.log`
+    pub fn open(
+        base: &Path,
+        limit: Option<SizeLimit>,
+        compression: Compression,
+    ) -> io::Result<Self> {
+        let date = Local::now().date_naive();
+        Ok(Self {
+            base: base.to_path_buf(),
+            date,
+            file: open_append(&dated_path(base, date))?,
+            limit,
+            compression,
+        })
+    }
+
+    /// Reopens the current file if another writer replaced it by trimming it
+    fn reopen_if_replaced(&mut self) -> io::Result<()> {
+        let path = dated_path(&self.base, self.date);
+        let replaced = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.ino() != self.file.metadata()?.ino(),
+            Err(_) => true,
+        };
+        if replaced {
+            self.file = open_append(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the oldest lines of the current file if it grew beyond the limit, called with
+    /// `ROTATION_LOCK` held so no other writer appends in between
+    fn trim_if_needed(&mut self) -> io::Result<()> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+        if self.file.metadata()?.len() <= limit.max_bytes {
+            return Ok(());
+        }
+        let path = dated_path(&self.base, self.date);
+        let mut contents = Vec::new();
+        File::open(&path)?.read_to_end(&mut contents)?;
+        let lines = contents.iter().filter(|byte| **byte == b'\n').count();
+        let remove = ((lines as f64 * limit.trim_pct / 100.0).ceil() as usize).max(1);
+        let start = contents
+            .iter()
+            .enumerate()
+            .filter(|(_, byte)| **byte == b'\n')
+            .nth(remove - 1)
+            .map_or(contents.len(), |(i, _)| i + 1);
+        // Renamed over the file, so `tail` never sees it half written
+        let trimmed = suffixed_path(&path, "trimmed");
+        std::fs::write(&trimmed, &contents[start..])?;
+        std::fs::rename(&trimmed, &path)?;
+        self.file = open_append(&path)?;
+        info!(
+            "Removed the oldest {} lines of {} to keep it below {} bytes",
+            remove,
+            path.display(),
+            limit.max_bytes
+        );
+        Ok(())
+    }
+
+    /// Closes and compresses the current file if the date changed since it was opened
+    fn rotate_if_needed(&mut self) -> io::Result<()> {
+        let today = Local::now().date_naive();
+        if today == self.date {
+            return Ok(());
+        }
+        self.file.flush()?;
+        self.file = open_append(&dated_path(&self.base, today))?;
+        let finished = dated_path(&self.base, self.date);
+        self.date = today;
+
+        let Some(extension) = self.compression.extension() else {
+            return Ok(());
+        };
+        let _lock = ROTATION_LOCK.lock().unwrap();
+        if finished.exists() && COMPRESSING.lock().unwrap().insert(finished.clone()) {
+            // In the background, so writing isn't held up by compressing a whole day
+            let compression = self.compression;
+            tokio::spawn(async move {
+                match compress(&finished, compression, extension).await {
+                    Ok(compressed) => info!("Rotated log to {}", compressed.display()),
+                    Err(err) => warn!("Couldn't compress {}: {}", finished.display(), err),
+                }
+                COMPRESSING.lock().unwrap().remove(&finished);
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.rotate_if_needed()?;
+        let _lock = ROTATION_LOCK.lock().unwrap();
+        self.reopen_if_replaced()?;
+        self.trim_if_needed()?;
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    File::options().append(true).create(true).open(path)
+}
+
+/// The file `base` is written to on `date`
+pub fn dated_path(base: &Path, date: NaiveDate) -> PathBuf {
+    suffixed_path(base, &date.to_string())
+}
+
+/// Compresses `path` into e.g. `path.gz` and removes the original
+async fn compress(path: &Path, compression: Compression, extension: &str) -> io::Result<PathBuf> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    let compressed = PathBuf::from(name);
+
+    let file = tokio::fs::File::create(&compressed).await?;
+    let mut encoder: Box<dyn AsyncWrite + Send + Unpin> = match compression {
+        Compression::Zstd => Box::new(ZstdEncoder::new(file)),
+        _ => Box::new(GzipEncoder::new(file)),
+    };
+    let mut original = tokio::fs::File::open(path).await?;
+    tokio::io::copy(&mut original, &mut encoder).await?;
+    // Writes the end of the stream and flushes the file
+    encoder.shutdown().await?;
+    tokio::fs::File::open(&compressed).await?.sync_all().await?;
+    tokio::fs::remove_file(path).await?;
+    Ok(compressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trimming_keeps_what_other_writers_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("ping.log");
+        let limit = Some(SizeLimit {
+            max_bytes: 100,
+            trim_pct: 50.0,
+        });
+        // Every pinger has its own writer to the same file
+        let mut first = RotatingFileWriter::open(&base, limit, Compression::None).unwrap();
+        let mut second = RotatingFileWriter::open(&base, limit, Compression::None).unwrap();
+        for i in 0..50 {
+            first.write_all(format!("a {:02}\n", i).as_bytes()).unwrap();
+            second
+                .write_all(format!("b {:02}\n", i).as_bytes())
+                .unwrap();
+        }
+
+        let contents =
+            std::fs::read_to_string(dated_path(&base, Local::now().date_naive())).unwrap();
+        assert!(contents.len() <= 105, "{}", contents);
+        assert!(contents.ends_with("a 49\nb 49\n"), "{}", contents);
+        let lines: Vec<_> = contents.lines().collect();
+        for pair in lines.windows(2) {
+            assert_ne!(pair[0][..1], pair[1][..1], "lost a line in {}", contents);
+        }
+    }
+}
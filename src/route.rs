@@ -0,0 +1,133 @@
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::process::Command;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use crate::ping::unix_timestamp;
+use crate::sinks::Writer;
+
+/// Where packets to a target leave the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Route {
+    pub interface: String,
+    /// `None` if the target is on the interface's link
+    pub gateway: Option<IpAddr>,
+}
+
+/// A change of the route, `None` while there is none
+#[derive(Debug, Serialize)]
+pub struct RouteChange<'a> {
+    pub timestamp: String,
+    pub target: &'a str,
+    pub previous: Option<&'a Route>,
+    pub current: Option<&'a Route>,
+}
+
+/// Parses `1.1.1.1 via 192.168.1.1 dev eth0 src 192.168.1.10 uid 1000`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_ip_route(output: &str) -> Option<Route> {
+    let fields: Vec<_> = output.split_whitespace().collect();
+    let after = |key| {
+        fields
+            .iter()
+            .position(|field| *field == key)
+            .and_then(|i| fields.get(i + 1))
+    };
+    Some(Route {
+        interface: after("dev")?.to_string(),
+        gateway: after("via").and_then(|gateway| gateway.parse().ok()),
+    })
+}
+
+/// Parses the `gateway:` and `interface:` lines of `route -n get`
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn parse_route_get(output: &str) -> Option<Route> {
+    let value = |key| {
+        output.lines().find_map(|line| {
+            let (name, value) = line.trim().split_once(": ")?;
+            (name == key).then(|| value.trim())
+        })
+    };
+    Some(Route {
+        interface: value("interface")?.to_string(),
+        // On-link targets have a gateway like `link#4`
+        gateway: value("gateway").and_then(|gateway| gateway.parse().ok()),
+    })
+}
+
+/// Looks up the route to `target`, `None` if there is none
+pub async fn route_get(target: &str) -> Result<Option<Route>> {
+    #[cfg(target_os = "linux")]
+    let (program, args, parse) = ("ip", ["route", "get"], parse_ip_route);
+    #[cfg(not(target_os = "linux"))]
+    let (program, args, parse) = ("route", ["-n", "get"], parse_route_get);
+    let output = Command::new(program)
+        .args(args)
+        .arg(target)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("Couldn't run {}", program))?;
+    if !output.status.success() {
+        debug!(
+            "No route to {}: {}",
+            target,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Ok(None);
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Describes `route` for the log
+fn describe(route: Option<&Route>) -> String {
+    match route {
+        Some(Route {
+            interface,
+            gateway: Some(gateway),
+        }) => format!("{} via {}", interface, gateway),
+        Some(Route {
+            interface,
+            gateway: None,
+        }) => format!("{} without gateway", interface),
+        None => "no route".to_string(),
+    }
+}
+
+/// Looks up the route to `target` every `period`, logging changes and appending them to
+/// `outfile`
+pub async fn route_monitor(target: String, period: Duration, mut outfile: Writer) -> Result<()> {
+    let mut iv = interval(period);
+    // `None` until the first lookup
+    let mut previous: Option<Option<Route>> = None;
+    loop {
+        iv.tick().await;
+        let route = route_get(&target).await?;
+        match &previous {
+            None => info!("Route to {}: {}", target, describe(route.as_ref())),
+            Some(previous) if *previous != route => {
+                warn!(
+                    "Route to {} changed from {} to {}",
+                    target,
+                    describe(previous.as_ref()),
+                    describe(route.as_ref())
+                );
+                let change = RouteChange {
+                    timestamp: unix_timestamp(),
+                    target: &target,
+                    previous: previous.as_ref(),
+                    current: route.as_ref(),
+                };
+                outfile.write_all(format!("{}\n", serde_json::to_string(&change)?).as_bytes())?;
+                outfile.flush()?;
+            }
+            Some(_) => {}
+        }
+        previous = Some(route);
+    }
+}
@@ -0,0 +1,259 @@
+use std::fs::File;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::alerts::Alerts;
+use crate::config::{Config, ExtraOutput, PingBackend};
+use crate::fping::FpingParser;
+use crate::ping::{suffixed_path, unix_timestamp, Ping, PingFormat};
+use crate::rotate::dated_path;
+use crate::sinks::clickhouse::ClickHouseSink;
+use crate::sinks::influx::InfluxSink;
+use crate::sinks::mqtt::MqttSink;
+use crate::sinks::sqlite::SqliteSink;
+use crate::{icmp, session};
+
+/// Maximum time a single check may take
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What was checked, and what was found or went wrong
+struct Check {
+    name: String,
+    result: Result<String>,
+}
+
+/// Fails `check` if it takes longer than `CHECK_TIMEOUT`
+async fn limited(check: impl Future<Output = Result<String>>) -> Result<String> {
+    timeout(CHECK_TIMEOUT, check).await.unwrap_or_else(|_| {
+        Err(anyhow!(
+            "No answer within {} seconds",
+            CHECK_TIMEOUT.as_secs()
+        ))
+    })
+}
+
+/// Every file con-mon writes to with `config`
+fn output_paths(config: &Config) -> Vec<PathBuf> {
+    let mut paths = vec![dated_path(&config.ping_log, Local::now().date_naive())];
+    paths.extend(session::log_paths(config).into_iter().cloned());
+    paths.extend(
+        config
+            .ping_target
+            .iter()
+            .map(|target| suffixed_path(&config.ping_stats, target)),
+    );
+    if config.simulate.is_none() {
+        paths.extend([config.speed_trend.clone(), config.monthly_data.clone()]);
+    }
+    if config.mdns_discover.is_some() {
+        paths.push(config.discovered_targets.clone());
+    }
+    if config.output_format.contains(&ExtraOutput::Csv) {
+        paths.extend([
+            config.csv_ping_log.clone(),
+            config.csv_speedtest_log.clone(),
+        ]);
+    }
+    if config.output_format.contains(&ExtraOutput::Parquet) {
+        paths.push(dated_path(&config.parquet_log, Local::now().date_naive()));
+    }
+    paths.push(config.log_file.clone());
+    paths
+}
+
+/// Opens `path` for appending, removing it again if it didn't exist
+fn check_writable(path: &Path) -> Result<String> {
+    let existed = path.exists();
+    File::options().append(true).create(true).open(path)?;
+    if existed {
+        return Ok(String::new());
+    }
+    std::fs::remove_file(path)?;
+    Ok("can be created".to_string())
+}
+
+/// Pings localhost once with the configured backend and parses the reply
+async fn check_ping(config: &Config) -> Result<String> {
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let ping = match config.ping_backend {
+        PingBackend::Ping => {
+            let format = PingFormat::detect(&config.ping_command).await;
+            let mut command = Command::new(&config.ping_command);
+            command.args(["-c", "1"]);
+            if format == PingFormat::LinuxD {
+                command.arg("-D");
+            }
+            let output = command
+                .arg(localhost.to_string())
+                .kill_on_drop(true)
+                .output()
+                .await
+                .context("Couldn't run ping")?;
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .find_map(|line| format.parse(line))
+        }
+        PingBackend::Fping => {
+            let output = Command::new("fping")
+                .args(["-D", "-c", "1"])
+                .arg(localhost.to_string())
+                .kill_on_drop(true)
+                .output()
+                .await
+                .context("Couldn't run fping")?;
+            let mut parser = FpingParser::default();
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .find_map(|line| parser.parse(line)?.ping)
+        }
+        PingBackend::Native => Some(Ping {
+            target: localhost.to_string(),
+            timestamp: unix_timestamp(),
+            ms: icmp::native_ping(localhost, CHECK_TIMEOUT, None).await?,
+        }),
+    };
+    let ping = ping.ok_or(anyhow!("No reply from {} could be parsed", localhost))?;
+    Ok(format!("{} replied in {} ms", ping.target, ping.ms))
+}
+
+/// Opens a TCP connection to the host of `url`
+async fn check_reachable(url: &str) -> Result<String> {
+    let url = reqwest::Url::parse(url)?;
+    let host = url.host_str().ok_or(anyhow!("{} has no host", url))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or(anyhow!("{} has no port", url))?;
+    TcpStream::connect((host, port)).await?;
+    Ok(String::new())
+}
+
+/// The databases and brokers the pings are written to
+async fn check_sinks(config: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+    if let Some(path) = &config.ping_db {
+        checks.push(Check {
+            name: format!("SQLite database {}", path.display()),
+            result: SqliteSink::open(path).map(|_| String::new()),
+        });
+    }
+    if let Some(url) = &config.influx_url {
+        let influx = InfluxSink::new(
+            url,
+            &config.influx_token,
+            &config.influx_org,
+            &config.influx_bucket,
+        );
+        checks.push(Check {
+            name: format!("InfluxDB at {}", url),
+            result: limited(async { influx.check().await.map(|_| String::new()) }).await,
+        });
+    }
+    if let Some(url) = &config.clickhouse_url {
+        let mut clickhouse =
+            ClickHouseSink::new(url, &config.clickhouse_database, &config.clickhouse_table);
+        clickhouse.user = config.clickhouse_user.clone();
+        clickhouse.password = config.clickhouse_password.clone();
+        checks.push(Check {
+            name: format!("ClickHouse at {}", url),
+            result: limited(async { clickhouse.check().await.map(|_| String::new()) }).await,
+        });
+    }
+    if let Some(broker) = config.mqtt_broker {
+        // The broker would disconnect a running con-mon using the same client id
+        let client_id = format!("{}-self-test", config.mqtt_client_id);
+        checks.push(Check {
+            name: format!("MQTT broker at {}", broker),
+            result: limited(async {
+                MqttSink::check(broker, &client_id)
+                    .await
+                    .map(|_| String::new())
+            })
+            .await,
+        });
+    }
+    if let Some(endpoint) = &config.otlp_endpoint {
+        checks.push(Check {
+            name: format!("OpenTelemetry collector at {}", endpoint),
+            result: limited(check_reachable(endpoint)).await,
+        });
+    }
+    checks
+}
+
+/// Checks the credentials of every alerter
+async fn check_alerters(config: &Config) -> Vec<Check> {
+    let alerts = match Alerts::from_config(config) {
+        Ok(Some(alerts)) => alerts,
+        Ok(None) => return Vec::new(),
+        Err(err) => {
+            return vec![Check {
+                name: "Alerter settings".to_string(),
+                result: Err(err),
+            }]
+        }
+    };
+    let mut checks = Vec::new();
+    for alerter in alerts.alerters() {
+        checks.push(Check {
+            name: format!("{} alerts", alerter.name()),
+            result: limited(async { alerter.verify().await.map(|_| String::new()) }).await,
+        });
+    }
+    checks
+}
+
+/// Runs all checks and prints their results, `false` if any failed
+pub async fn run(config: &Config) -> Result<bool> {
+    let mut checks: Vec<_> = output_paths(config)
+        .into_iter()
+        .map(|path| Check {
+            name: format!("Writing {}", path.display()),
+            result: check_writable(&path),
+        })
+        .collect();
+    checks.push(Check {
+        name: "Pinging 127.0.0.1".to_string(),
+        result: limited(check_ping(config)).await,
+    });
+    checks.extend(check_sinks(config).await);
+    checks.extend(check_alerters(config).await);
+
+    let color = config.color();
+    let label = |passed: bool| match (passed, color) {
+        (true, true) => "\x1b[32mPASS\x1b[0m",
+        (true, false) => "PASS",
+        (false, true) => "\x1b[31mFAIL\x1b[0m",
+        (false, false) => "FAIL",
+    };
+    let mut failed = 0;
+    for check in &checks {
+        match &check.result {
+            Ok(found) if found.is_empty() => println!("{} {}", label(true), check.name),
+            Ok(found) => println!("{} {}: {}", label(true), check.name, found),
+            Err(err) => {
+                failed += 1;
+                println!("{} {}: {:#}", label(false), check.name, err);
+            }
+        }
+    }
+    println!();
+    if failed == 0 {
+        println!("{}: all {} checks passed", label(true), checks.len());
+    } else {
+        println!(
+            "{}: {} of {} checks failed",
+            label(false),
+            failed,
+            checks.len()
+        );
+    }
+    Ok(failed == 0)
+}
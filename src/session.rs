@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::Config;
+
+/// First record con-mon writes to its logs on startup, to tell where they came from
+#[derive(Debug, Serialize)]
+pub struct SessionStart {
+    /// Always `session_start`, so readers can skip the record
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub hostname: String,
+    pub os: &'static str,
+    pub kernel: String,
+    pub interfaces: Vec<InterfaceInfo>,
+    pub con_mon_version: &'static str,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Network interface of the host
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub mac: Option<String>,
+    pub ips: Vec<IpAddr>,
+}
+
+/// Whether `line` is the session start record rather than a log record
+pub fn is_session_start(line: &str) -> bool {
+    line.starts_with('{')
+        && serde_json::from_str::<Value>(line).is_ok_and(|record| record["type"] == "session_start")
+}
+
+/// Interfaces with their hardware and IP addresses
+fn interfaces() -> Result<Vec<InterfaceInfo>> {
+    let mut interfaces: BTreeMap<String, InterfaceInfo> = BTreeMap::new();
+    for ifaddr in nix::ifaddrs::getifaddrs()? {
+        let interface = interfaces
+            .entry(ifaddr.interface_name.clone())
+            .or_insert_with(|| InterfaceInfo {
+                name: ifaddr.interface_name,
+                ..InterfaceInfo::default()
+            });
+        let Some(address) = ifaddr.address else {
+            continue;
+        };
+        if let Some(ip) = address.as_sockaddr_in() {
+            interface.ips.push(ip.ip().into());
+        } else if let Some(ip) = address.as_sockaddr_in6() {
+            interface.ips.push(ip.ip().into());
+        } else if let Some(mac) = address.as_link_addr().and_then(|link| link.addr()) {
+            interface.mac = Some(
+                mac.iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(":"),
+            );
+        }
+    }
+    Ok(interfaces.into_values().collect())
+}
+
+impl SessionStart {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            kind: "session_start",
+            hostname: nix::unistd::gethostname()?.to_string_lossy().into_owned(),
+            os: std::env::consts::OS,
+            kernel: nix::sys::utsname::uname()?
+                .release()
+                .to_string_lossy()
+                .into_owned(),
+            interfaces: interfaces()?,
+            con_mon_version: env!("CARGO_PKG_VERSION"),
+            started_at: Utc::now(),
+        })
+    }
+}
+
+/// The logs besides the ping log con-mon appends to with `config`
+pub fn log_paths(config: &Config) -> Vec<&PathBuf> {
+    let mut paths = vec![&config.outage_log, &config.hourly_stats_log];
+    if config.simulate.is_none() {
+        paths.extend([&config.speedtest_log, &config.bandwidth_log]);
+    }
+    if let Some(path) = &config.heatmap_log {
+        paths.push(path);
+    }
+    if config.batch_size.is_some() {
+        paths.push(&config.batch_log);
+    }
+    if config.traceroute {
+        paths.push(&config.traceroute_log);
+    }
+    if !config.http_probe_url.is_empty() {
+        paths.push(&config.http_probe_log);
+    }
+    if config.route_check {
+        paths.push(&config.route_change_log);
+    }
+    if !config.tcp_probe_target.is_empty() {
+        paths.push(&config.tcp_probe_log);
+    }
+    if !config.dns_probe_host.is_empty() {
+        paths.push(&config.dns_probe_log);
+    }
+    if config.dns_check_host {
+        paths.push(&config.dns_change_log);
+    }
+    if !config.wg_interface.is_empty() {
+        paths.push(&config.wg_probe_log);
+    }
+    paths
+}
+
+/// Writes a `SessionStart` record to every log con-mon is about to write to
+pub fn session_init(config: &Config) -> Result<()> {
+    let sinks = config.sinks();
+    let mut logs = vec![sinks.rotating(
+        &config.ping_log,
+        config.ping_log_limit(),
+        config.log_compression,
+    )?];
+    for path in log_paths(config) {
+        logs.push(sinks.append(path)?);
+    }
+
+    let mut record = serde_json::to_vec(&SessionStart::new()?)?;
+    record.push(b'\n');
+    for mut log in logs {
+        log.write_all(&record)?;
+        log.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::ping::{epoch_micros, ping_sinks, Ping, TargetState};
+use crate::session::is_session_start;
+use crate::Shared;
+
+/// Parses a line of the ping log in either the text or the JSONL format
+fn parse_line(line: &str) -> Result<Ping> {
+    if line.starts_with('{') {
+        Ok(serde_json::from_str(line)?)
+    } else {
+        line.parse()
+    }
+}
+
+/// Sleeps as long after `previous` as `micros` was, sped up by `simulate_speed`
+async fn wait(config: &Config, previous: &mut Option<i64>, micros: i64) {
+    if let Some(previous) = previous.replace(micros) {
+        let gap = Duration::from_micros((micros - previous).max(0) as u64);
+        time::sleep(gap.div_f64(config.simulate_speed)).await;
+    }
+}
+
+/// Feeds the pings logged in `path` through the same outputs as live pings,
+/// `simulate_speed` times faster than they were recorded
+pub async fn replay(config: &Config, shared: &Shared, path: &Path) -> Result<()> {
+    if config.simulate_speed <= 0.0 {
+        return Err(anyhow!("--simulate-speed has to be positive"));
+    }
+    // Read up front, the log being replayed may also be the one written to
+    let log = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    info!(
+        "Replaying {} at {}x speed",
+        path.display(),
+        config.simulate_speed
+    );
+
+    let mut sinks = ping_sinks(config, shared)?;
+    let mut targets: HashMap<String, (TargetState, i64)> = HashMap::new();
+    let mut previous: Option<i64> = None;
+    for line in log
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !is_session_start(line))
+    {
+        let ping = match parse_line(line) {
+            Ok(ping) => ping,
+            Err(err) => {
+                warn!(line, "Couldn't parse: {}", err);
+                continue;
+            }
+        };
+        let micros = epoch_micros(&ping.timestamp)?;
+        let (state, last) = targets
+            .entry(ping.target.clone())
+            .or_insert_with(|| (TargetState::new(config, &ping.target, None), micros));
+        // Pings are sent once a second, a longer gap means the ones in between were lost
+        let missed = ((micros - *last) as f64 / 1e6).round() as i64 - 1;
+        for second in 1..=missed {
+            let at = *last + second * 1_000_000;
+            wait(config, &mut previous, at).await;
+            state.lost(
+                config,
+                shared,
+                DateTime::from_timestamp_micros(at).unwrap_or_default(),
+            );
+        }
+        *last = micros;
+        wait(config, &mut previous, micros).await;
+        state.reply(config, &mut sinks, shared, ping).await?;
+    }
+    info!("Replay of {} finished", path.display());
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::ping::Ping;
+
+/// Rings the terminal bell when a ping takes longer than `threshold_ms`, at most once per
+/// `cooldown` for all targets together
+#[derive(Clone)]
+pub struct LatencyAlarm {
+    pub threshold_ms: f64,
+    pub cooldown: Duration,
+    /// When the bell last rang
+    rung: Arc<Mutex<Option<Instant>>>,
+}
+
+impl LatencyAlarm {
+    pub fn new(threshold_ms: f64, cooldown: Duration) -> Self {
+        Self {
+            threshold_ms,
+            cooldown,
+            rung: Arc::default(),
+        }
+    }
+
+    /// Rings the bell if the actual latency of `ping` is above the threshold
+    pub fn check(&self, ping: &Ping) -> io::Result<()> {
+        if self.rings(ping, Instant::now()) {
+            debug!(
+                "Ping to {} took {} ms, ringing the bell",
+                ping.target, ping.ms
+            );
+            ring(&mut io::stdout().lock())?;
+        }
+        Ok(())
+    }
+
+    /// Whether `ping` rings the bell at `now`, which starts the cooldown if it does
+    fn rings(&self, ping: &Ping, now: Instant) -> bool {
+        if ping.ms <= self.threshold_ms {
+            return false;
+        }
+        let mut rung = self.rung.lock().unwrap();
+        if rung.is_some_and(|rung| now.duration_since(rung) < self.cooldown) {
+            return false;
+        }
+        *rung = Some(now);
+        true
+    }
+}
+
+/// Writes BEL, which the terminal turns into a beep or a flash
+fn ring(out: &mut impl Write) -> io::Result<()> {
+    out.write_all(b"\x07")?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ping(ms: f64) -> Ping {
+        Ping {
+            timestamp: "1700000000.000000".to_string(),
+            target: "1.1.1.1".to_string(),
+            ms,
+        }
+    }
+
+    #[test]
+    fn rings_above_threshold_once_per_cooldown() {
+        let alarm = LatencyAlarm::new(200.0, Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(!alarm.rings(&ping(150.0), start));
+        assert!(!alarm.rings(&ping(200.0), start));
+        assert!(alarm.rings(&ping(250.0), start));
+        assert!(!alarm.rings(&ping(900.0), start + Duration::from_secs(9)));
+        assert!(alarm.rings(&ping(201.0), start + Duration::from_secs(10)));
+
+        // Every pinger has its own clone, sharing the cooldown
+        let clone = alarm.clone();
+        assert!(!clone.rings(&ping(300.0), start + Duration::from_secs(11)));
+    }
+
+    #[test]
+    fn rings_with_bel() {
+        let mut out = Vec::new();
+        ring(&mut out).unwrap();
+        assert_eq!(out, b"\x07");
+    }
+}
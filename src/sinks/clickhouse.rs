@@ -0,0 +1,229 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat};
+use reqwest::RequestBuilder;
+use serde_json::json;
+use tokio::sync::broadcast;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use super::{labeled, Sink};
+use crate::ping::{epoch_micros, Ping};
+
+/// Writes pings to a ClickHouse table over its HTTP interface in `JSONEachRow`
+///
+/// The table has to exist, e.g.
+///
+/// ```sql
+/// CREATE TABLE pings (
+///     ts DateTime64(6, 'UTC'),
+///     target LowCardinality(String),
+///     latency_ms Float64
+/// ) ENGINE = MergeTree
+/// PARTITION BY toYYYYMM(ts)
+/// ORDER BY (target, ts);
+/// ```
+///
+/// The labels go to a `labels Map(String, String)` column, if the table has one.
+#[derive(Clone)]
+pub struct ClickHouseSink {
+    pub url: String,
+    pub database: String,
+    pub table: String,
+    pub user: Option<String>,
+    pub password: String,
+    /// Rows buffered before they are sent
+    pub batch_size: usize,
+    /// Maximum time a row stays buffered
+    pub flush_interval: Duration,
+    client: reqwest::Client,
+    buffer: Arc<Mutex<Vec<String>>>,
+}
+
+impl ClickHouseSink {
+    pub fn new(url: &str, database: &str, table: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            database: database.to_string(),
+            table: table.to_string(),
+            user: None,
+            password: String::new(),
+            batch_size: 1000,
+            flush_interval: Duration::from_secs(5),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            buffer: Arc::default(),
+        }
+    }
+
+    pub async fn write_ping(&self, ping: &Ping) -> Result<()> {
+        let ts = DateTime::from_timestamp_micros(epoch_micros(&ping.timestamp)?)
+            .ok_or(anyhow!("Timestamp {} out of range", ping.timestamp))?;
+        let row = json!({
+            "ts": ts.to_rfc3339_opts(SecondsFormat::Micros, true),
+            "target": ping.target,
+            "latency_ms": ping.ms,
+        });
+        let full = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.push(String::from_utf8(labeled(row.to_string().as_bytes()))?);
+            buffer.len() >= self.batch_size
+        };
+        if full {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Runs `query` as the configured user
+    fn request(&self, query: &str) -> RequestBuilder {
+        let request = self.client.post(&self.url).query(&[("query", query)]);
+        match &self.user {
+            Some(user) => request
+                .header("X-ClickHouse-User", user)
+                .header("X-ClickHouse-Key", &self.password),
+            None => request,
+        }
+    }
+
+    /// Checks that the server is reachable and the table exists
+    pub async fn check(&self) -> Result<()> {
+        let query = format!("EXISTS TABLE `{}`.`{}`", self.database, self.table);
+        let response = self.request(&query).send().await?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(anyhow!("ClickHouse answered {}: {}", status, body));
+        }
+        if body.trim() != "1" {
+            return Err(anyhow!(
+                "Table {}.{} doesn't exist",
+                self.database,
+                self.table
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sends all buffered rows
+    pub async fn flush(&self) -> Result<()> {
+        let rows = std::mem::take(&mut *self.buffer.lock().unwrap());
+        if rows.is_empty() {
+            return Ok(());
+        }
+        debug!("Writing {} rows to ClickHouse", rows.len());
+        let query = format!(
+            "INSERT INTO `{}`.`{}` FORMAT JSONEachRow",
+            self.database, self.table
+        );
+        let response = self
+            .request(&query)
+            .query(&[
+                ("date_time_input_format", "best_effort"),
+                ("input_format_skip_unknown_fields", "1"),
+            ])
+            .body(rows.join("\n"))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow!(
+                "ClickHouse rejected {} rows with {}: {}",
+                rows.len(),
+                status,
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Flushes every `flush_interval`, and a last time on shutdown
+    pub async fn flush_periodically(self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let mut iv = interval(self.flush_interval);
+        loop {
+            tokio::select! {
+                _ = iv.tick() => {
+                    if let Err(err) = self.flush().await {
+                        warn!("Couldn't write to ClickHouse: {}", err);
+                    }
+                }
+                _ = shutdown.recv() => return self.flush().await,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for ClickHouseSink {
+    fn name(&self) -> &'static str {
+        "ClickHouse"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        ClickHouseSink::write_ping(self, ping).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs against the ClickHouse at `CONMON_CLICKHOUSE_URL`, by default one started with
+    /// `docker run --rm -p 8123:8123 -e CLICKHOUSE_SKIP_USER_SETUP=1 clickhouse/clickhouse-server`
+    #[tokio::test]
+    #[ignore = "needs a ClickHouse server"]
+    async fn writes_pings_to_clickhouse() {
+        let url = std::env::var("CONMON_CLICKHOUSE_URL")
+            .unwrap_or_else(|_| "http://localhost:8123".to_string());
+        let table = format!("pings_{}", std::process::id());
+        let sink = ClickHouseSink::new(&url, "default", &table);
+        let create = format!(
+            "CREATE TABLE `default`.`{}` (
+                ts DateTime64(6, 'UTC'),
+                target LowCardinality(String),
+                latency_ms Float64
+            ) ENGINE = MergeTree ORDER BY (target, ts)",
+            table
+        );
+        sink.request(&create)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+        sink.check().await.unwrap();
+
+        for (timestamp, ms) in [("1700000000.100000", 12.3), ("1700000001.100000", 0.045)] {
+            let ping = Ping {
+                timestamp: timestamp.to_string(),
+                target: "1.1.1.1".to_string(),
+                ms,
+            };
+            sink.write_ping(&ping).await.unwrap();
+        }
+        sink.flush().await.unwrap();
+
+        let select = format!(
+            "SELECT toUnixTimestamp64Micro(ts), target, latency_ms FROM `default`.`{}` \
+             ORDER BY ts FORMAT TSV",
+            table
+        );
+        let rows = sink.request(&select).send().await.unwrap().text().await;
+        sink.request(&format!("DROP TABLE `default`.`{}`", table))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            rows.unwrap().lines().collect::<Vec<_>>(),
+            [
+                "1700000000100000\t1.1.1.1\t12.3",
+                "1700000001100000\t1.1.1.1\t0.045"
+            ]
+        );
+    }
+}
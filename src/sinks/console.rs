@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use crossterm::cursor::{MoveToColumn, MoveUp};
+use crossterm::queue;
+use crossterm::terminal::{Clear, ClearType};
+use tokio::time::interval;
+
+use crate::metrics::SharedMetrics;
+use crate::ping::Ping;
+
+/// Time between two redraws of the table
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Latencies of a target since con-mon started
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    last: f64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u64,
+}
+
+/// Rows of the table, kept across pinger restarts
+#[derive(Debug, Default)]
+struct ConsoleTable {
+    rows: BTreeMap<String, Row>,
+    /// Lines printed by the last redraw, which the next one moves back over
+    printed: u16,
+}
+
+impl ConsoleTable {
+    fn record(&mut self, ping: &Ping) {
+        self.rows
+            .entry(ping.target.clone())
+            .and_modify(|row| {
+                row.last = ping.ms;
+                row.min = row.min.min(ping.ms);
+                row.max = row.max.max(ping.ms);
+                row.sum += ping.ms;
+                row.count += 1;
+            })
+            .or_insert(Row {
+                last: ping.ms,
+                min: ping.ms,
+                max: ping.ms,
+                sum: ping.ms,
+                count: 1,
+            });
+    }
+
+    /// The header and a line per target, `loss` is the packet loss ratio of every target
+    fn render(&self, loss: &BTreeMap<String, f64>) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{:<30} {:>9} {:>9} {:>9} {:>9} {:>7}",
+            "Target", "Last ms", "Min ms", "Avg ms", "Max ms", "Loss %"
+        )];
+        // Targets that never replied only show up with their loss
+        let targets: BTreeSet<_> = self.rows.keys().chain(loss.keys()).collect();
+        for target in targets {
+            let loss = loss
+                .get(target)
+                .map_or("-".to_string(), |ratio| format!("{:.1}", ratio * 100.0));
+            let line = match self.rows.get(target) {
+                Some(row) => format!(
+                    "{:<30} {:>9.1} {:>9.1} {:>9.1} {:>9.1} {:>7}",
+                    target,
+                    row.last,
+                    row.min,
+                    row.sum / row.count as f64,
+                    row.max,
+                    loss
+                ),
+                None => format!(
+                    "{:<30} {:>9} {:>9} {:>9} {:>9} {:>7}",
+                    target, "-", "-", "-", "-", loss
+                ),
+            };
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Replaces the previously drawn table on `out`
+    fn draw(&mut self, loss: &BTreeMap<String, f64>, out: &mut impl Write) -> io::Result<()> {
+        if self.printed > 0 {
+            queue!(out, MoveUp(self.printed), MoveToColumn(0))?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+        let lines = self.render(loss);
+        for line in &lines {
+            writeln!(out, "{}", line)?;
+        }
+        self.printed = lines.len() as u16;
+        out.flush()
+    }
+}
+
+/// Shows the latest, minimum, average and maximum latency and the packet loss of every target
+/// as a table in the terminal, redrawn in place
+#[derive(Clone)]
+pub struct ConsoleSink {
+    table: Arc<Mutex<ConsoleTable>>,
+    /// Where the packet loss is read from, the sink only sees the replies
+    metrics: SharedMetrics,
+}
+
+impl ConsoleSink {
+    pub fn new(metrics: SharedMetrics) -> Self {
+        Self {
+            table: Arc::default(),
+            metrics,
+        }
+    }
+
+    /// Adds the reply `ping`, with its actual latency rather than the exported one
+    pub fn record(&self, ping: &Ping) {
+        self.table.lock().unwrap().record(ping);
+    }
+
+    /// Redraws the table on stdout every second, so the loss also updates while nothing replies
+    pub async fn redraw_periodically(self) -> Result<()> {
+        let mut iv = interval(REDRAW_INTERVAL);
+        loop {
+            iv.tick().await;
+            let loss = self.metrics.lock().unwrap().packet_loss_ratio.clone();
+            self.table
+                .lock()
+                .unwrap()
+                .draw(&loss, &mut io::stdout().lock())?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ping(target: &str, ms: f64) -> Ping {
+        Ping {
+            timestamp: "1700000000.000000".to_string(),
+            target: target.to_string(),
+            ms,
+        }
+    }
+
+    #[test]
+    fn renders_a_row_per_target() {
+        let mut table = ConsoleTable::default();
+        for ms in [20.0, 10.0, 30.0] {
+            table.record(&ping("1.1.1.1", ms));
+        }
+        let loss = BTreeMap::from([("1.1.1.1".to_string(), 0.25), ("8.8.8.8".to_string(), 1.0)]);
+        let lines = table.render(&loss);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1].split_whitespace().collect::<Vec<_>>(),
+            ["1.1.1.1", "30.0", "10.0", "20.0", "30.0", "25.0"]
+        );
+        assert_eq!(
+            lines[2].split_whitespace().collect::<Vec<_>>(),
+            ["8.8.8.8", "-", "-", "-", "-", "100.0"]
+        );
+    }
+
+    #[test]
+    fn redraws_over_the_previous_table() {
+        let mut table = ConsoleTable::default();
+        table.record(&ping("1.1.1.1", 12.0));
+        let mut out = Vec::new();
+        table.draw(&BTreeMap::new(), &mut out).unwrap();
+        // Nothing to move back over the first time
+        assert!(!String::from_utf8_lossy(&out).contains("\x1b[2A"));
+        assert_eq!(table.printed, 2);
+
+        out.clear();
+        table.draw(&BTreeMap::new(), &mut out).unwrap();
+        assert!(String::from_utf8_lossy(&out).starts_with("\x1b[2A"));
+    }
+}
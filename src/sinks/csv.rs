@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+
+use super::{Sink, SinkFactory, Writer};
+use crate::labels::Labels;
+use crate::ping::{JitterWindow, Ping};
+use crate::speedtest::SpeedtestResult;
+
+/// Columns of the ping CSV, followed by one per label
+const PING_COLUMNS: [&str; 4] = ["timestamp", "target", "latency_ms", "jitter_ms"];
+
+/// Columns of the speedtest CSV, followed by one per label
+const SPEEDTEST_COLUMNS: [&str; 7] = [
+    "timestamp",
+    "download_bps",
+    "upload_bps",
+    "ping_ms",
+    "server",
+    "bytes_sent",
+    "bytes_received",
+];
+
+#[derive(Debug, Serialize)]
+struct PingRow<'a> {
+    timestamp: &'a str,
+    target: &'a str,
+    latency_ms: f64,
+    jitter_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct SpeedtestRow<'a> {
+    timestamp: &'a str,
+    download_bps: f64,
+    upload_bps: f64,
+    ping_ms: f64,
+    server: &'a str,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+/// Appends pings and speedtests to CSV files, starting new ones with a header
+#[derive(Clone)]
+pub struct CsvSink {
+    pub ping_file: PathBuf,
+    pub speedtest_file: PathBuf,
+    /// Written as extra columns, a file started with other labels keeps its header
+    pub labels: Labels,
+    /// Latencies the jitter column is calculated over
+    pub jitter_window: usize,
+    /// Recent latencies of every target
+    jitter: HashMap<String, JitterWindow>,
+    sinks: &'static dyn SinkFactory,
+}
+
+impl CsvSink {
+    pub fn new(ping_file: &Path, speedtest_file: &Path, sinks: &'static dyn SinkFactory) -> Self {
+        Self {
+            ping_file: ping_file.to_path_buf(),
+            speedtest_file: speedtest_file.to_path_buf(),
+            labels: Labels::default(),
+            jitter_window: 60,
+            jitter: HashMap::new(),
+            sinks,
+        }
+    }
+
+    /// Appends `row` to `path`, after the header if the file is new or empty
+    fn append(&self, path: &Path, columns: &[&str], row: impl Serialize) -> Result<()> {
+        let is_new = std::fs::metadata(path).map_or(true, |metadata| metadata.len() == 0);
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer::<Writer>(self.sinks.append(path)?);
+        if is_new {
+            writer.write_record(
+                columns
+                    .iter()
+                    .copied()
+                    .chain(self.labels.iter().map(|(name, _)| name.as_str())),
+            )?;
+        }
+        let values: Vec<_> = self.labels.iter().map(|(_, value)| value).collect();
+        writer.serialize((row, values))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_speedtest(&self, result: &SpeedtestResult) -> Result<()> {
+        self.append(
+            &self.speedtest_file,
+            &SPEEDTEST_COLUMNS,
+            SpeedtestRow {
+                timestamp: &result.timestamp,
+                download_bps: result.download,
+                upload_bps: result.upload,
+                ping_ms: result.ping,
+                server: &result.server.host,
+                bytes_sent: result.bytes_sent,
+                bytes_received: result.bytes_received,
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl Sink for CsvSink {
+    fn name(&self) -> &'static str {
+        "CSV"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        let jitter = self
+            .jitter
+            .entry(ping.target.clone())
+            .or_insert_with(|| JitterWindow::new(self.jitter_window));
+        jitter.push(ping.ms);
+        let jitter_ms = jitter.jitter();
+        self.append(
+            &self.ping_file,
+            &PING_COLUMNS,
+            PingRow {
+                timestamp: &ping.timestamp,
+                target: &ping.target,
+                latency_ms: ping.ms,
+                jitter_ms,
+            },
+        )
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::debug;
+
+use super::sqlite::SqliteSink;
+use super::{labeled, with_labels, Sink, SinkFactory, Writer};
+use crate::config::{Config, OutputFormat};
+use crate::ping::{JitterWindow, Ping, PingRecord};
+use crate::rotate::{dated_path, Compression, RotatingFileWriter, SizeLimit};
+
+/// Writes the pings to the ping log, as text or JSONL
+pub struct FileSink {
+    outfile: Writer,
+    format: OutputFormat,
+    jitter_window: usize,
+    /// Recent latencies of every target, for the jitter of the JSONL records
+    jitter: HashMap<String, JitterWindow>,
+}
+
+impl FileSink {
+    pub fn open(config: &Config) -> Result<Self> {
+        let outfile = config.sinks().rotating(
+            &config.ping_log,
+            config.ping_log_limit(),
+            config.log_compression,
+        )?;
+        Ok(Self {
+            outfile,
+            format: config.format,
+            jitter_window: config.jitter_window,
+            jitter: HashMap::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    fn name(&self) -> &'static str {
+        "ping log"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        match self.format {
+            OutputFormat::Text => write!(self.outfile, "{}", ping)?,
+            OutputFormat::Jsonl => {
+                let jitter = self
+                    .jitter
+                    .entry(ping.target.clone())
+                    .or_insert_with(|| JitterWindow::new(self.jitter_window));
+                jitter.push(ping.ms);
+                let record = PingRecord {
+                    ping,
+                    jitter_ms: jitter.jitter(),
+                };
+                serde_json::to_writer(&mut self.outfile, &record)?
+            }
+        }
+        self.outfile.write_all(b"\n")?;
+        self.outfile.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes to the actual files
+pub struct FileSinks;
+
+impl SinkFactory for FileSinks {
+    fn append(&self, path: &Path) -> io::Result<Writer> {
+        Ok(with_labels(Box::new(
+            File::options().append(true).create(true).open(path)?,
+        )))
+    }
+
+    fn rotating(
+        &self,
+        base: &Path,
+        limit: Option<SizeLimit>,
+        compression: Compression,
+    ) -> io::Result<Writer> {
+        Ok(with_labels(Box::new(RotatingFileWriter::open(
+            base,
+            limit,
+            compression,
+        )?)))
+    }
+
+    fn replace(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, labeled(contents))?;
+        std::fs::rename(tmp, path)
+    }
+
+    fn sqlite(&self, path: &Path) -> Result<SqliteSink> {
+        SqliteSink::open(path)
+    }
+}
+
+/// Only logs what would have been written, no file is created or modified
+pub struct DryRunSinks;
+
+impl SinkFactory for DryRunSinks {
+    fn append(&self, path: &Path) -> io::Result<Writer> {
+        Ok(with_labels(Box::new(DryRunWriter {
+            path: path.to_path_buf(),
+            line: Vec::new(),
+        })))
+    }
+
+    fn rotating(
+        &self,
+        base: &Path,
+        _limit: Option<SizeLimit>,
+        _compression: Compression,
+    ) -> io::Result<Writer> {
+        self.append(&dated_path(base, chrono::Local::now().date_naive()))
+    }
+
+    fn replace(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        debug!(
+            "Dry run, would replace {} with: {}",
+            path.display(),
+            String::from_utf8_lossy(&labeled(contents))
+        );
+        Ok(())
+    }
+
+    fn sqlite(&self, path: &Path) -> Result<SqliteSink> {
+        debug!(
+            "Dry run, using an in-memory database instead of {}",
+            path.display()
+        );
+        SqliteSink::open_in_memory()
+    }
+}
+
+/// Logs every line instead of writing it
+struct DryRunWriter {
+    path: PathBuf,
+    /// Written since the last newline, records are often written in pieces
+    line: Vec<u8>,
+}
+
+impl Write for DryRunWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if self.line.ends_with(b"\n") {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            debug!(
+                "Dry run, would write to {}: {}",
+                self.path.display(),
+                String::from_utf8_lossy(&self.line).trim_end()
+            );
+            self.line.clear();
+        }
+        Ok(())
+    }
+}
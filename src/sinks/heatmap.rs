@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Serialize, Serializer};
+use tokio::time::interval;
+use tracing::warn;
+
+use super::{Sink, Writer};
+use crate::ping::Ping;
+
+/// Time covered by one row of the heatmap
+const ROW_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Heatmap shared between the pingers and the writer
+pub type SharedHeatmap = Arc<Mutex<GrafanaHeatmapSink>>;
+
+/// Latency counts of a target over one minute, keyed by bucket like `5-10` or `500+`
+#[derive(Debug, Serialize)]
+pub struct HeatmapRow {
+    /// Seconds since the epoch
+    pub ts: i64,
+    pub target: String,
+    #[serde(serialize_with = "ordered_map")]
+    pub buckets: Vec<(String, u64)>,
+}
+
+/// Keeps the buckets in ascending order, Grafana takes them in the order of the fields
+fn ordered_map<S: Serializer>(buckets: &[(String, u64)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(buckets.iter().map(|(label, count)| (label, count)))
+}
+
+/// Counts ping latencies into fixed buckets for Grafana's heatmap panel
+///
+/// `assets/grafana-heatmap.json` is a dashboard to import that reads the rows through the
+/// Infinity data source, with the heatmap file served over HTTP; the panel shows the
+/// buckets as they are instead of calculating its own.
+#[derive(Debug)]
+pub struct GrafanaHeatmapSink {
+    /// Upper bounds of the buckets in milliseconds, the last bucket has none
+    bounds: Vec<f64>,
+    counts: BTreeMap<String, Vec<u64>>,
+}
+
+impl GrafanaHeatmapSink {
+    pub fn new(mut bounds: Vec<f64>) -> Self {
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        Self {
+            bounds,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, target: &str, latency_ms: f64) {
+        let bucket = self.bounds.partition_point(|bound| *bound <= latency_ms);
+        let buckets = self.bounds.len() + 1;
+        self.counts
+            .entry(target.to_string())
+            .or_insert_with(|| vec![0; buckets])[bucket] += 1;
+    }
+
+    /// Names of the buckets, e.g. `0-5`, `5-10` and `10+`
+    fn labels(&self) -> Vec<String> {
+        let lower = std::iter::once(0.0).chain(self.bounds.iter().copied());
+        let mut labels: Vec<_> = lower
+            .zip(&self.bounds)
+            .map(|(lower, upper)| format!("{}-{}", lower, upper))
+            .collect();
+        labels.push(format!(
+            "{}+",
+            self.bounds.last().copied().unwrap_or_default()
+        ));
+        labels
+    }
+
+    /// Rows of every target seen so far, which start over at zero
+    pub fn take_rows(&mut self) -> Vec<HeatmapRow> {
+        let ts = Utc::now().timestamp();
+        let labels = self.labels();
+        self.counts
+            .iter_mut()
+            .map(|(target, counts)| HeatmapRow {
+                ts,
+                target: target.clone(),
+                buckets: labels
+                    .iter()
+                    .cloned()
+                    .zip(counts.iter_mut().map(std::mem::take))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Appends a row per target to `outfile` every minute
+pub async fn write_every_minute(heatmap: SharedHeatmap, mut outfile: Writer) -> Result<()> {
+    let mut iv = interval(ROW_INTERVAL);
+    // The first tick completes immediately
+    iv.tick().await;
+    loop {
+        iv.tick().await;
+        let rows = heatmap.lock().unwrap().take_rows();
+        for row in rows {
+            serde_json::to_writer(&mut outfile, &row)?;
+            outfile.write_all(b"\n")?;
+        }
+        if let Err(err) = outfile.flush() {
+            warn!("Couldn't write latency heatmap: {}", err);
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for SharedHeatmap {
+    fn name(&self) -> &'static str {
+        "heatmap"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        self.lock().unwrap().record(&ping.target, ping.ms);
+        Ok(())
+    }
+}
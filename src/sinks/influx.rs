@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use super::Sink;
+use crate::labels::Labels;
+use crate::ping::{epoch_micros, Ping};
+use crate::speedtest::SpeedtestResult;
+
+/// Points buffered before they are sent
+const MAX_BATCH: usize = 100;
+
+/// Maximum time a point stays buffered
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Writes pings and speedtests to InfluxDB 2 in line protocol
+#[derive(Clone)]
+pub struct InfluxSink {
+    pub url: String,
+    pub token: String,
+    pub org: String,
+    pub bucket: String,
+    /// Tags added to every point
+    pub labels: Labels,
+    client: reqwest::Client,
+    buffer: Arc<Mutex<Vec<String>>>,
+}
+
+/// Escapes commas, spaces and equal signs in tag values
+fn escape_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+        .replace('=', "\\=")
+}
+
+impl InfluxSink {
+    pub fn new(url: &str, token: &str, org: &str, bucket: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            org: org.to_string(),
+            bucket: bucket.to_string(),
+            labels: Labels::default(),
+            client: reqwest::Client::builder()
+                .timeout(FLUSH_INTERVAL)
+                .build()
+                .unwrap_or_default(),
+            buffer: Arc::default(),
+        }
+    }
+
+    pub async fn write_ping(&self, ping: &Ping) -> Result<()> {
+        let line = format!(
+            "ping,target={}{} latency_ms={} {}",
+            escape_tag(&ping.target),
+            self.tags(),
+            ping.ms,
+            epoch_micros(&ping.timestamp)?
+        );
+        self.push(line).await
+    }
+
+    pub async fn write_speedtest(&self, result: &SpeedtestResult) -> Result<()> {
+        // Backends that report no usable timestamp get the time of writing
+        let micros = DateTime::parse_from_rfc3339(&result.timestamp).map_or_else(
+            |_| Utc::now().timestamp_micros(),
+            |time| time.timestamp_micros(),
+        );
+        let line = format!(
+            "speedtest{} download_bps={},upload_bps={},ping_ms={} {}",
+            self.tags(),
+            result.download,
+            result.upload,
+            result.ping,
+            micros
+        );
+        self.push(line).await
+    }
+
+    /// `,name=value` of every label
+    fn tags(&self) -> String {
+        self.labels
+            .iter()
+            .map(|(name, value)| format!(",{}={}", name, escape_tag(value)))
+            .collect()
+    }
+
+    /// Buffers a line and sends the batch once it is full
+    async fn push(&self, line: String) -> Result<()> {
+        let full = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.push(line);
+            buffer.len() >= MAX_BATCH
+        };
+        if full {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the token can read the bucket, without writing to it
+    pub async fn check(&self) -> Result<()> {
+        let response = self
+            .client
+            .get(format!("{}/api/v2/buckets", self.url))
+            .query(&[("org", self.org.as_str()), ("name", self.bucket.as_str())])
+            .header("Authorization", format!("Token {}", self.token))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow!(
+                "InfluxDB answered {}: {}",
+                status,
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        let buckets: Value = response.json().await?;
+        if buckets["buckets"]
+            .as_array()
+            .is_none_or(|buckets| buckets.is_empty())
+        {
+            return Err(anyhow!("Bucket {} doesn't exist", self.bucket));
+        }
+        Ok(())
+    }
+
+    /// Sends all buffered points
+    pub async fn flush(&self) -> Result<()> {
+        let lines = std::mem::take(&mut *self.buffer.lock().unwrap());
+        if lines.is_empty() {
+            return Ok(());
+        }
+        debug!("Writing {} points to InfluxDB", lines.len());
+        let response = self
+            .client
+            .post(format!("{}/api/v2/write", self.url))
+            .query(&[
+                ("org", self.org.as_str()),
+                ("bucket", self.bucket.as_str()),
+                ("precision", "us"),
+            ])
+            .header("Authorization", format!("Token {}", self.token))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(lines.join("\n"))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow!(
+                "InfluxDB rejected {} points with {}: {}",
+                lines.len(),
+                status,
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Flushes every few seconds, and a last time on shutdown
+    pub async fn flush_periodically(self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let mut iv = interval(FLUSH_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = iv.tick() => {
+                    if let Err(err) = self.flush().await {
+                        warn!("Couldn't write to InfluxDB: {}", err);
+                    }
+                }
+                _ = shutdown.recv() => return self.flush().await,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for InfluxSink {
+    fn name(&self) -> &'static str {
+        "InfluxDB"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        InfluxSink::write_ping(self, ping).await
+    }
+}
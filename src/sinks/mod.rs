@@ -0,0 +1,139 @@
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+
+use crate::labels::Labels;
+use crate::ping::Ping;
+use crate::rotate::{Compression, SizeLimit};
+
+pub mod alarm;
+pub mod clickhouse;
+pub mod console;
+pub mod csv;
+mod file;
+pub mod heatmap;
+pub mod influx;
+pub mod mqtt;
+pub mod parquet;
+pub mod sqlite;
+
+pub use file::{DryRunSinks, FileSink, FileSinks};
+use sqlite::SqliteSink;
+
+/// Boxed writer handed out by a `SinkFactory`
+pub type Writer = Box<dyn Write + Send>;
+
+/// Somewhere the parsed pings are written to, see `ping::ping_sinks`
+#[async_trait]
+pub trait Sink: Send {
+    /// Name used in log messages
+    fn name(&self) -> &'static str;
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()>;
+
+    /// Writes a reply from `peer`, the address ping reported, for the sinks that record it
+    async fn write_reply(&mut self, ping: &Ping, _peer: Option<IpAddr>) -> Result<()> {
+        self.write_ping(ping).await
+    }
+}
+
+/// Labels added to the JSON records, set once on startup
+static LABELS: OnceLock<Labels> = OnceLock::new();
+
+/// Adds `labels` to every JSON record written from now on
+pub fn set_labels(labels: Labels) {
+    if !labels.is_empty() {
+        let _ = LABELS.set(labels);
+    }
+}
+
+/// `record` with the labels under `labels` if it is a JSON object, as it was otherwise
+pub fn labeled(record: &[u8]) -> Vec<u8> {
+    let Some(labels) = LABELS.get().filter(|_| record.starts_with(b"{")) else {
+        return record.to_vec();
+    };
+    let Ok(mut object) = serde_json::from_slice::<Map<String, Value>>(record) else {
+        return record.to_vec();
+    };
+    object.insert(
+        "labels".to_string(),
+        serde_json::to_value(&labels.0).unwrap_or_default(),
+    );
+    // Pretty printed files stay readable
+    let labeled = if record.contains(&b'\n') {
+        serde_json::to_vec_pretty(&object)
+    } else {
+        serde_json::to_vec(&object)
+    };
+    labeled.unwrap_or_else(|_| record.to_vec())
+}
+
+/// Passes lines on to `inner` with the labels added to the JSON ones
+struct LabeledWriter {
+    inner: Writer,
+    /// Written since the last newline, records are often written in pieces
+    line: Vec<u8>,
+}
+
+/// `sink` adding the labels to its JSON lines, if there are any labels
+fn with_labels(sink: Writer) -> Writer {
+    if LABELS.get().is_none() {
+        return sink;
+    }
+    Box::new(LabeledWriter {
+        inner: sink,
+        line: Vec::new(),
+    })
+}
+
+impl Write for LabeledWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        while let Some(end) = self.line.iter().position(|&byte| byte == b'\n') {
+            let rest = self.line.split_off(end + 1);
+            let line = std::mem::replace(&mut self.line, rest);
+            self.inner.write_all(&labeled(&line[..end]))?;
+            self.inner.write_all(b"\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    /// A line without its newline yet stays buffered, so it gets labeled once complete
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Drop for LabeledWriter {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            let _ = self.inner.write_all(&labeled(&self.line));
+            let _ = self.inner.flush();
+        }
+    }
+}
+
+/// Opens everything con-mon writes to, so a dry run can swap in no-op sinks
+pub trait SinkFactory: Send + Sync {
+    /// Opens `path` for appending, creating it if missing
+    fn append(&self, path: &Path) -> io::Result<Writer>;
+
+    /// Opens a log that is rotated daily and kept within `limit`, see `RotatingFileWriter`
+    fn rotating(
+        &self,
+        base: &Path,
+        limit: Option<SizeLimit>,
+        compression: Compression,
+    ) -> io::Result<Writer>;
+
+    /// Atomically replaces the contents of `path`
+    fn replace(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Opens the ping database at `path`
+    fn sqlite(&self, path: &Path) -> Result<SqliteSink>;
+}
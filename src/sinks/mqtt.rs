@@ -0,0 +1,161 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+use tracing::{debug, info, warn};
+
+use super::{labeled, Sink};
+use crate::ping::Ping;
+use crate::speedtest::SpeedtestResult;
+
+/// Publishes queued before further ones are rejected
+const QUEUE_SIZE: usize = 100;
+
+/// Interval of the keep-alive pings to the broker
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Wait before reconnecting to the broker
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Maximum time to get the last messages out on shutdown
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Publishes pings and speedtests as JSON to an MQTT broker
+///
+/// Pings go to `{prefix}/ping/{target}`, speedtests to `{prefix}/speedtest`, and
+/// `{prefix}/status` is a retained `online`, or `offline` once con-mon is gone.
+#[derive(Clone)]
+pub struct MqttSink {
+    pub broker: SocketAddr,
+    pub client_id: String,
+    pub topic_prefix: String,
+    client: AsyncClient,
+}
+
+impl MqttSink {
+    /// Creates the sink and the event loop that has to be `run` for anything to be sent
+    pub fn new(broker: SocketAddr, client_id: &str, topic_prefix: &str) -> (Self, EventLoop) {
+        let topic_prefix = topic_prefix.trim_end_matches('/').to_string();
+        let mut options = MqttOptions::new(client_id, broker.ip().to_string(), broker.port());
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_last_will(LastWill::new(
+            format!("{}/status", topic_prefix),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        let (client, eventloop) = AsyncClient::new(options, QUEUE_SIZE);
+        let sink = Self {
+            broker,
+            client_id: client_id.to_string(),
+            topic_prefix,
+            client,
+        };
+        (sink, eventloop)
+    }
+
+    /// Connects to `broker` as `client_id` and hangs up once it accepted
+    pub async fn check(broker: SocketAddr, client_id: &str) -> Result<()> {
+        let options = MqttOptions::new(client_id, broker.ip().to_string(), broker.port());
+        let (_client, mut eventloop) = AsyncClient::new(options, 1);
+        loop {
+            if let Event::Incoming(Packet::ConnAck(_)) = eventloop.poll().await? {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn write_ping(&self, ping: &Ping) -> Result<()> {
+        self.publish(
+            &format!("ping/{}", ping.target),
+            labeled(&serde_json::to_vec(ping)?),
+        )
+    }
+
+    pub fn write_speedtest(&self, result: &SpeedtestResult) -> Result<()> {
+        self.publish("speedtest", labeled(&serde_json::to_vec(result)?))
+    }
+
+    /// Queues a message, failing instead of waiting while the broker is unreachable
+    fn publish(&self, topic: &str, payload: Vec<u8>) -> Result<()> {
+        self.client.try_publish(
+            format!("{}/{}", self.topic_prefix, topic),
+            QoS::AtLeastOnce,
+            false,
+            payload,
+        )?;
+        Ok(())
+    }
+
+    fn publish_status(&self, status: &str) -> Result<()> {
+        self.client.try_publish(
+            format!("{}/status", self.topic_prefix),
+            QoS::AtLeastOnce,
+            true,
+            status,
+        )?;
+        Ok(())
+    }
+
+    /// Keeps the connection up until shutdown, then marks con-mon offline and disconnects
+    pub async fn run(
+        self,
+        mut eventloop: EventLoop,
+        mut shutdown: broadcast::Receiver<()>,
+    ) -> Result<()> {
+        loop {
+            tokio::select! {
+                event = eventloop.poll() => match event {
+                    // The last will replaced the status if the connection was lost before
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to MQTT broker at {} as {}", self.broker, self.client_id);
+                        if let Err(err) = self.publish_status("online") {
+                            warn!("Couldn't publish MQTT status: {}", err);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!("Lost connection to MQTT broker at {}: {}", self.broker, err);
+                        sleep(RECONNECT_DELAY).await;
+                    }
+                },
+                _ = shutdown.recv() => break,
+            }
+        }
+
+        // A clean disconnect doesn't trigger the last will
+        self.publish_status("offline")?;
+        self.client.try_disconnect()?;
+        let disconnected = timeout(DISCONNECT_TIMEOUT, async {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(_) => return,
+                    Ok(_) => {}
+                }
+            }
+        })
+        .await;
+        if disconnected.is_err() {
+            debug!(
+                "Timed out disconnecting from MQTT broker at {}",
+                self.broker
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for MqttSink {
+    fn name(&self) -> &'static str {
+        "MQTT"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        MqttSink::write_ping(self, ping)
+    }
+}
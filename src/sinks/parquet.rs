@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+use tokio::sync::broadcast;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use super::{Sink, SinkFactory};
+use crate::export::write_parquet;
+use crate::labels::Labels;
+use crate::ping::{epoch_micros, Ping};
+use crate::rotate::dated_path;
+
+/// Buffers pings and writes them to a Parquet file per day, e.g. `ping-2024-01-15.parquet`
+///
+/// Parquet files can't be appended to, so a flush rewrites the day's file with the buffered
+/// pings added.
+#[derive(Clone)]
+pub struct ParquetSink {
+    pub base: PathBuf,
+    /// Pings buffered before they are written
+    pub batch_size: usize,
+    /// Maximum time a ping stays buffered
+    pub flush_interval: Duration,
+    /// Written as extra columns, replacing those of pings written before
+    pub labels: Labels,
+    sinks: &'static dyn SinkFactory,
+    buffer: Arc<Mutex<Vec<Ping>>>,
+    /// Held while a file is rewritten, so two flushes don't drop each other's pings
+    writing: Arc<Mutex<()>>,
+}
+
+/// Reads the pings of a file written by `write_parquet`
+fn read_parquet(path: &Path) -> Result<Vec<Ping>> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    reader
+        .get_row_iter(None)?
+        .map(|row| {
+            let row = row?;
+            let micros = row.get_timestamp_micros(0)?;
+            Ok(Ping {
+                timestamp: format!(
+                    "{}.{:06}",
+                    micros.div_euclid(1_000_000),
+                    micros.rem_euclid(1_000_000)
+                ),
+                target: row.get_string(1)?.clone(),
+                ms: row.get_double(2)?,
+            })
+        })
+        .collect()
+}
+
+impl ParquetSink {
+    pub fn new(base: &Path, sinks: &'static dyn SinkFactory) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            batch_size: 1000,
+            flush_interval: Duration::from_secs(300),
+            labels: Labels::default(),
+            sinks,
+            buffer: Arc::default(),
+            writing: Arc::default(),
+        }
+    }
+
+    pub async fn write_ping(&self, ping: &Ping) -> Result<()> {
+        let full = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.push(ping.clone());
+            buffer.len() >= self.batch_size
+        };
+        if full {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes all buffered pings on a blocking thread
+    pub async fn flush(&self) -> Result<()> {
+        let pings = std::mem::take(&mut *self.buffer.lock().unwrap());
+        if pings.is_empty() {
+            return Ok(());
+        }
+        debug!("Writing {} pings to Parquet", pings.len());
+        let sink = self.clone();
+        tokio::task::spawn_blocking(move || sink.write(pings)).await?
+    }
+
+    /// Adds `pings` to the files of the local days they were sent on
+    fn write(&self, pings: Vec<Ping>) -> Result<()> {
+        let mut days: BTreeMap<NaiveDate, Vec<Ping>> = BTreeMap::new();
+        for ping in pings {
+            let sent = DateTime::from_timestamp_micros(epoch_micros(&ping.timestamp)?)
+                .ok_or(anyhow!("Timestamp {} out of range", ping.timestamp))?;
+            days.entry(sent.with_timezone(&Local).date_naive())
+                .or_default()
+                .push(ping);
+        }
+
+        let _writing = self.writing.lock().unwrap();
+        for (date, pings) in days {
+            let path = dated_path(&self.base, date);
+            let mut all = if path.exists() {
+                read_parquet(&path)?
+            } else {
+                Vec::new()
+            };
+            all.extend(pings);
+            let mut contents = Vec::new();
+            write_parquet(&all, &self.labels, &mut contents)?;
+            self.sinks.replace(&path, &contents)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes every `flush_interval`, and a last time on shutdown
+    pub async fn flush_periodically(self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let mut iv = interval(self.flush_interval);
+        loop {
+            tokio::select! {
+                _ = iv.tick() => {
+                    if let Err(err) = self.flush().await {
+                        warn!("Couldn't write to Parquet: {}", err);
+                    }
+                }
+                _ = shutdown.recv() => return self.flush().await,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for ParquetSink {
+    fn name(&self) -> &'static str {
+        "Parquet"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        ParquetSink::write_ping(self, ping).await
+    }
+}
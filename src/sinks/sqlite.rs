@@ -0,0 +1,74 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OpenFlags};
+
+use super::Sink;
+use crate::ping::Ping;
+
+/// Writes pings into the `pings` table of an SQLite database
+pub struct SqliteSink {
+    conn: Connection,
+}
+
+impl SqliteSink {
+    /// Opens the database at `path`, creating it and the table if missing
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        // Every pinger has its own connection to the same file
+        conn.busy_timeout(Duration::from_secs(5))?;
+        Self::init(conn)
+    }
+
+    /// Opens the existing database at `path` for reading the stored pings
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("No such database {}", path.display()))?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        Ok(Self { conn })
+    }
+
+    /// Opens a database that only lives as long as the sink
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS pings (
+                id INTEGER PRIMARY KEY,
+                timestamp TEXT NOT NULL,
+                target TEXT NOT NULL,
+                latency_ms REAL NOT NULL
+            )",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// The underlying connection, for reading the stored pings
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn insert_ping(&self, ping: &Ping) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO pings (timestamp, target, latency_ms) VALUES (?1, ?2, ?3)",
+            params![ping.timestamp, ping.target, ping.ms],
+        )?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for SqliteSink {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    async fn write_ping(&mut self, ping: &Ping) -> Result<()> {
+        self.insert_ping(ping)
+    }
+}
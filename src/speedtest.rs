@@ -0,0 +1,290 @@
+use std::io::Write;
+use std::time::Duration;
+
+use chrono::{Local, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use serde_json::Value;
+use tokio::{process::Command, time};
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+use tracing::{debug, warn};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::{Config, SpeedtestBackend};
+use crate::Shared;
+
+/// Result of a single speedtest, speeds are in bits per second
+///
+/// Follows the JSON schema of `speedtest-cli`, which the other backends fill as far as they can
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpeedtestResult {
+    pub download: f64,
+    pub upload: f64,
+    pub ping: f64,
+    pub timestamp: String,
+    pub server: ServerInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<ClientInfo>,
+    #[serde(default)]
+    pub bytes_sent: u64,
+    #[serde(default)]
+    pub bytes_received: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geolocation: Option<crate::geolocation::Geolocation>,
+    /// Everything else the backend reported
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
+}
+
+/// Server a speedtest ran against
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sponsor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Distance in kilometers
+    #[serde(rename = "d", skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
+    /// Latency in milliseconds measured while picking the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<f64>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
+}
+
+/// Connection a speedtest ran from, as seen by the server
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ClientInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
+}
+
+/// Command installing `speedtest-cli` on this system
+pub fn speedtest_cli_install_hint() -> &'static str {
+    if cfg!(target_os = "macos") {
+        return "brew install speedtest-cli";
+    }
+    let os_release = std::fs::read_to_string("/etc/os-release").unwrap_or_default();
+    let ids: Vec<_> = os_release
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("ID=")
+                .or_else(|| line.strip_prefix("ID_LIKE="))
+        })
+        .flat_map(|ids| ids.trim_matches('"').split_whitespace())
+        .collect();
+    let is = |distro: &str| ids.contains(&distro);
+    if is("debian") || is("ubuntu") {
+        "sudo apt install speedtest-cli"
+    } else if is("fedora") || is("rhel") {
+        "sudo dnf install speedtest-cli"
+    } else if is("arch") {
+        "sudo pacman -S speedtest-cli"
+    } else {
+        "pip install speedtest-cli"
+    }
+}
+
+/// Whether `speedtest-cli` is in the `PATH`
+pub async fn speedtest_cli_installed() -> bool {
+    let version = Command::new("speedtest-cli")
+        .arg("--version")
+        .output()
+        .await;
+    !matches!(version, Err(err) if err.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Runs `speedtest-cli` and parses its JSON output
+async fn cli_speedtest(server_id: Option<u32>) -> Result<SpeedtestResult> {
+    let mut command = Command::new("speedtest-cli");
+    if let Some(id) = server_id {
+        command.arg("--server").arg(id.to_string());
+    }
+    let output = match command.arg("--json").kill_on_drop(true).output().await {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(anyhow!(
+                "speedtest-cli is not installed, install it with `{}`",
+                speedtest_cli_install_hint()
+            ))
+        }
+        output => output?,
+    };
+    if !output.status.success() {
+        return Err(anyhow!(
+            "speedtest-cli failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let output = String::from_utf8(output.stdout)?;
+    debug!("Speed: {}", &output);
+    serde_json::from_str(&output).context("speedtest-cli output doesn't match its known schema")
+}
+
+/// Servers `--speedtest-list-servers` prints
+const LISTED_SERVERS: usize = 20;
+
+/// Prints the closest servers `speedtest-cli --list` knows of as a table
+pub async fn list_speedtest_servers() -> Result<()> {
+    lazy_static! {
+        /// `  1234) Sponsor (City, Country) [12.34 km]`
+        static ref SERVER: Regex =
+            Regex::new(r"^\s*(\d+)\) (.+?) \((.+)\) \[(.+)\]$").unwrap();
+    }
+    let output = Command::new("speedtest-cli")
+        .arg("--list")
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "speedtest-cli failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    println!(
+        "{:>6}  {:<30}  {:<30}  DISTANCE",
+        "ID", "SPONSOR", "LOCATION"
+    );
+    for cap in output
+        .lines()
+        .filter_map(|line| SERVER.captures(line))
+        .take(LISTED_SERVERS)
+    {
+        println!(
+            "{:>6}  {:<30}  {:<30}  {}",
+            &cap[1], &cap[2], &cap[3], &cap[4]
+        );
+    }
+    Ok(())
+}
+
+/// Runs a speedtest, retrying failed attempts up to `speedtest_retries` times
+pub async fn speed_tester(config: &Config) -> Result<SpeedtestResult> {
+    let attempts = config.speedtest_retries + 1;
+    let mut attempt = 1;
+    let mut result = loop {
+        match speedtest_attempt(config).await {
+            Err(err) if attempt < attempts => {
+                warn!(
+                    attempt,
+                    attempts,
+                    "Speedtest failed, retrying in {} seconds: {}",
+                    config.speedtest_retry_delay,
+                    err
+                );
+                time::sleep(Duration::from_secs(config.speedtest_retry_delay)).await;
+                attempt += 1;
+            }
+            result => break result?,
+        }
+    };
+    if config.geolocation {
+        match crate::geolocation::lookup(config.ipinfo_token.as_deref()).await {
+            Ok(geolocation) => result.geolocation = Some(geolocation),
+            Err(err) => warn!("Couldn't look up the geolocation: {}", err),
+        }
+    }
+    Ok(result)
+}
+
+async fn speedtest_attempt(config: &Config) -> Result<SpeedtestResult> {
+    debug!("Speedtest started");
+    match &config.speedtest_backend {
+        SpeedtestBackend::SpeedtestNet => {}
+        SpeedtestBackend::Iperf3 { host, port } => {
+            return crate::iperf::iperf3_speedtest(host, *port).await
+        }
+        SpeedtestBackend::FastCom => return crate::native_speedtest::fast_com_speedtest().await,
+    }
+    if !config.native_speedtest {
+        return cli_speedtest(config.speedtest_server_id).await;
+    }
+    match crate::native_speedtest::native_speedtest().await {
+        Ok(result) => Ok(result),
+        Err(err) if config.native_speedtest_fallback => {
+            warn!(
+                "Native speedtest failed, falling back to speedtest-cli: {}",
+                err
+            );
+            cli_speedtest(config.speedtest_server_id).await
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Time until the next of the given local times of day
+pub fn next_speedtest(times: &[NaiveTime]) -> Duration {
+    let now = Local::now();
+    times
+        .iter()
+        .flat_map(|time| {
+            let today = now.date_naive();
+            [today, today + chrono::Days::new(1)].map(|date| date.and_time(*time))
+        })
+        // Times skipped by a DST change don't occur that day
+        .filter_map(|time| Local.from_local_datetime(&time).earliest())
+        .filter(|time| *time > now)
+        .map(|time| (time - now).to_std().unwrap_or_default())
+        .min()
+        .unwrap_or_default()
+}
+
+/// Publishes a speedtest result and appends it to the speedtest log
+pub async fn record_speedtest(
+    config: &Config,
+    shared: &Shared,
+    result: &SpeedtestResult,
+) -> Result<()> {
+    {
+        let mut metrics = shared.metrics.lock().unwrap();
+        metrics.download_bps = Some(result.download);
+        metrics.upload_bps = Some(result.upload);
+    }
+
+    let mut outfile = config.sinks().append(&config.speedtest_log)?;
+    serde_json::to_writer(&mut outfile, result)?;
+    outfile.write_all(b"\n")?;
+    outfile.flush()?;
+
+    if let Err(err) = crate::bandwidth::record(config, result) {
+        warn!("Couldn't record speedtest data usage: {}", err);
+    }
+
+    if let Some(influx) = &shared.influx {
+        if let Err(err) = influx.write_speedtest(result).await {
+            warn!("Couldn't write speedtest to InfluxDB: {}", err);
+        }
+    }
+    if let Some(mqtt) = &shared.mqtt {
+        if let Err(err) = mqtt.write_speedtest(result) {
+            warn!("Couldn't publish speedtest to MQTT: {}", err);
+        }
+    }
+    if let Some(csv) = &shared.csv {
+        if let Err(err) = csv.write_speedtest(result) {
+            warn!("Couldn't write speedtest to CSV: {}", err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use crate::sinks::Writer;
+
+/// The last `capacity` values pushed, with statistics over them
+#[derive(Debug, Clone, Default)]
+pub struct StatsWindow<T> {
+    values: VecDeque<T>,
+    capacity: usize,
+}
+
+/// Value at the `p` quantile of already sorted values, the nearest rank, none without values
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let rank = (p * sorted.len().saturating_sub(1) as f64).round() as usize;
+    sorted.get(rank).copied()
+}
+
+impl<T: Copy + Into<f64>> StatsWindow<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            values: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds `value`, dropping the oldest one once the window is full
+    pub fn push(&mut self, value: T) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        if self.capacity > 0 {
+            self.values.push_back(value);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Values from the oldest to the newest
+    pub fn iter(&self) -> impl Iterator<Item = f64> + '_ {
+        self.values.iter().map(|&value| value.into())
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.iter().sum()
+    }
+
+    /// 0 while the window is empty
+    pub fn mean(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.sum() / self.len() as f64
+    }
+
+    /// Population standard deviation, 0 while the window is empty
+    pub fn std_dev(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let variance =
+            self.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / self.len() as f64;
+        variance.sqrt()
+    }
+
+    /// Value at the `p` quantile, between 0 and 1, or 0 while the window is empty
+    pub fn percentile(&self, p: f64) -> f64 {
+        let mut sorted: Vec<_> = self.iter().collect();
+        sorted.sort_by(f64::total_cmp);
+        percentile(&sorted, p.clamp(0.0, 1.0)).unwrap_or(0.0)
+    }
+}
+
+/// Time between two summaries
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Percentile tracker shared between the pingers and the summary writer
+pub type SharedPercentiles = Arc<Mutex<PercentileTracker>>;
+
+/// Latency percentiles of a host over the last summary interval
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub host: String,
+    pub timestamp: String,
+    pub count: u64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub p999: f64,
+}
+
+/// Histogram of the ping latencies per host
+#[derive(Debug, Default)]
+pub struct PercentileTracker {
+    /// Latencies in microseconds
+    hosts: BTreeMap<String, Histogram<u64>>,
+}
+
+impl PercentileTracker {
+    pub fn record(&mut self, host: &str, latency_ms: f64) {
+        let histogram = self
+            .hosts
+            .entry(host.to_string())
+            // Three significant digits, growing to whatever latencies show up
+            .or_insert_with(|| Histogram::new(3).unwrap());
+        histogram.saturating_record((latency_ms * 1000.0).max(1.0) as u64);
+    }
+
+    /// Summarizes the latencies of every host and starts over
+    pub fn take_summaries(&mut self) -> Vec<Summary> {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let ms = |histogram: &Histogram<u64>, quantile| {
+            histogram.value_at_quantile(quantile) as f64 / 1000.0
+        };
+        std::mem::take(&mut self.hosts)
+            .into_iter()
+            .map(|(host, histogram)| Summary {
+                timestamp: timestamp.clone(),
+                count: histogram.len(),
+                p50: ms(&histogram, 0.5),
+                p95: ms(&histogram, 0.95),
+                p99: ms(&histogram, 0.99),
+                p999: ms(&histogram, 0.999),
+                host,
+            })
+            .collect()
+    }
+}
+
+/// Appends the percentiles of every host to `outfile` once an hour
+pub async fn write_hourly(tracker: SharedPercentiles, mut outfile: Writer) -> Result<()> {
+    let mut iv = interval(SUMMARY_INTERVAL);
+    // The first tick completes immediately
+    iv.tick().await;
+    loop {
+        iv.tick().await;
+        let summaries = tracker.lock().unwrap().take_summaries();
+        debug!("Writing latency percentiles of {} hosts", summaries.len());
+        for summary in summaries {
+            serde_json::to_writer(&mut outfile, &summary)?;
+            outfile.write_all(b"\n")?;
+        }
+        if let Err(err) = outfile.flush() {
+            warn!("Couldn't write latency percentiles: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(capacity: usize, values: &[f64]) -> StatsWindow<f64> {
+        let mut window = StatsWindow::new(capacity);
+        for &value in values {
+            window.push(value);
+        }
+        window
+    }
+
+    #[test]
+    fn empty_window_is_zero() {
+        let window = StatsWindow::<f64>::new(5);
+        assert!(window.is_empty());
+        assert_eq!(window.len(), 0);
+        assert_eq!(window.mean(), 0.0);
+        assert_eq!(window.std_dev(), 0.0);
+        assert_eq!(window.percentile(0.5), 0.0);
+    }
+
+    #[test]
+    fn drops_oldest_values_once_full() {
+        let window = window(3, &[1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(window.len(), 3);
+        assert_eq!(window.iter().collect::<Vec<_>>(), [3.0, 4.0, 5.0]);
+        assert_eq!(window.sum(), 12.0);
+        assert_eq!(window.mean(), 4.0);
+    }
+
+    #[test]
+    fn zero_capacity_keeps_nothing() {
+        let window = window(0, &[1.0, 2.0]);
+        assert!(window.is_empty());
+        assert_eq!(window.mean(), 0.0);
+    }
+
+    #[test]
+    fn std_dev_is_population_std_dev() {
+        let window = window(8, &[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(window.mean(), 5.0);
+        assert_eq!(window.std_dev(), 2.0);
+        assert_eq!(self::window(3, &[7.0]).std_dev(), 0.0);
+        assert_eq!(self::window(3, &[3.0, 3.0, 3.0]).std_dev(), 0.0);
+    }
+
+    #[test]
+    fn percentile_is_nearest_rank() {
+        // Pushed out of order, the window sorts a copy
+        let window = window(5, &[50.0, 10.0, 40.0, 20.0, 30.0]);
+        assert_eq!(window.percentile(0.0), 10.0);
+        assert_eq!(window.percentile(0.5), 30.0);
+        assert_eq!(window.percentile(0.95), 50.0);
+        assert_eq!(window.percentile(1.0), 50.0);
+        assert_eq!(window.percentile(2.0), 50.0);
+        assert_eq!(window.percentile(-1.0), 10.0);
+        assert_eq!(window.iter().next(), Some(50.0));
+
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0], 0.5), Some(3.0));
+        assert_eq!(percentile(&[1.0], 0.99), Some(1.0));
+        assert_eq!(percentile(&[], 0.5), None);
+    }
+
+    #[test]
+    fn accepts_anything_convertible_to_f64() {
+        let mut lost = StatsWindow::new(4);
+        for value in [true, false, false, true, true] {
+            lost.push(value);
+        }
+        assert_eq!(lost.sum(), 2.0);
+        assert_eq!(lost.mean(), 0.5);
+
+        let mut counts = StatsWindow::<u32>::new(3);
+        counts.push(1);
+        counts.push(2);
+        assert_eq!(counts.mean(), 1.5);
+        assert_eq!(counts.percentile(1.0), 2.0);
+    }
+}
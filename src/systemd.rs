@@ -0,0 +1,30 @@
+use anyhow::Result;
+use libsystemd::daemon::{self, NotifyState};
+use tokio::sync::mpsc;
+use tokio::time::interval;
+use tracing::{debug, info};
+
+/// Tells systemd we are ready once the `states` pinged targets replied, then keeps its watchdog happy
+pub async fn notify_ready(
+    mut first_replies: mpsc::UnboundedReceiver<()>,
+    states: usize,
+) -> Result<()> {
+    if std::env::var_os("NOTIFY_SOCKET").is_none() {
+        debug!("Not started by systemd, skipping notifications");
+        return Ok(());
+    }
+    for _ in 0..states {
+        first_replies.recv().await;
+    }
+    daemon::notify(false, &[NotifyState::Ready])?;
+    info!("Notified systemd that con-mon is ready");
+
+    let Some(timeout) = daemon::watchdog_enabled(false) else {
+        return Ok(());
+    };
+    let mut iv = interval(timeout / 2);
+    loop {
+        iv.tick().await;
+        daemon::notify(false, &[NotifyState::Watchdog])?;
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use clap::Args;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use crate::config::Config;
+use crate::ping::Ping;
+use crate::rotate::dated_path;
+use crate::session::is_session_start;
+
+/// How often the ping log is checked for new lines
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Prints the latest pings and follows new ones
+#[derive(Debug, Clone, Args)]
+pub struct TailArgs {
+    /// Pings printed before following
+    #[arg(long, default_value_t = 10)]
+    lines: usize,
+}
+
+/// Colors a line by its latency: green below 20 ms, yellow up to 100 ms, red above, and white
+/// for lines that aren't a reply
+fn colorize(line: &str) -> String {
+    let ping = if line.starts_with('{') {
+        serde_json::from_str::<Ping>(line).ok()
+    } else {
+        line.parse::<Ping>().ok()
+    };
+    let color = match ping.map(|ping| ping.ms) {
+        Some(ms) if ms < 20.0 => 32,
+        Some(ms) if ms <= 100.0 => 33,
+        Some(_) => 31,
+        None => 37,
+    };
+    format!("\x1b[{}m{}\x1b[0m", color, line)
+}
+
+/// Prints a line, colored if `color` is set
+fn print_line(line: &str, color: bool) {
+    if line.trim().is_empty() || is_session_start(line) {
+        return;
+    }
+    if color {
+        println!("{}", colorize(line));
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Streams from the ring buffer of the running monitor, `false` if none is listening on `socket`
+async fn follow_socket(socket: &Path, lines: usize, color: bool) -> Result<bool> {
+    let Ok(mut stream) = UnixStream::connect(socket).await else {
+        return Ok(false);
+    };
+    stream
+        .write_all(format!("tail {}\n", lines).as_bytes())
+        .await?;
+    let mut reader = tokio::io::BufReader::new(stream).lines();
+    while let Some(line) = reader.next_line().await? {
+        print_line(&line, color);
+    }
+    Ok(true)
+}
+
+/// Prints the last `lines` lines of today's ping log and then the ones appended to it,
+/// moving on to the next day's file after midnight
+async fn follow_log(base: &Path, lines: usize, color: bool) -> Result<()> {
+    let mut date = Local::now().date_naive();
+    let mut reader = BufReader::new(File::open(dated_path(base, date))?);
+    let mut last = VecDeque::with_capacity(lines + 1);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        last.push_back(std::mem::take(&mut line));
+        if last.len() > lines {
+            last.pop_front();
+        }
+    }
+    for line in last {
+        print_line(line.trim_end(), color);
+    }
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let today = Local::now().date_naive();
+        if today != date {
+            if let Ok(file) = File::open(dated_path(base, today)) {
+                date = today;
+                reader = BufReader::new(file);
+            }
+        }
+        // Trimming replaces the file with one starting with the lines already printed
+        let path = dated_path(base, date);
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            if metadata.ino() != reader.get_ref().metadata()?.ino() {
+                reader = BufReader::new(File::open(&path)?);
+                reader.seek(SeekFrom::End(0))?;
+            }
+        }
+        // A line still being written is completed on the next poll
+        loop {
+            let read = reader.read_line(&mut line)?;
+            if read == 0 || !line.ends_with('\n') {
+                if read > 0 {
+                    reader.seek_relative(-(read as i64))?;
+                }
+                line.clear();
+                break;
+            }
+            print_line(line.trim_end(), color);
+            line.clear();
+        }
+    }
+}
+
+pub async fn run(config: &Config, args: &TailArgs) -> Result<()> {
+    let color = config.color();
+    if let Some(socket) = &config.control_socket {
+        if follow_socket(socket, args.lines, color).await? {
+            return Ok(());
+        }
+    }
+    follow_log(&config.ping_log, args.lines, color).await
+}
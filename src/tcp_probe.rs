@@ -0,0 +1,91 @@
+use std::io::Write;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::json;
+use socket2::{Domain, Socket, Type};
+use tokio::net::TcpSocket;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use crate::interface::bind_to_interface;
+use crate::metrics::SharedMetrics;
+use crate::ping::unix_timestamp;
+use crate::sinks::Writer;
+
+/// Outcome of a single TCP connection attempt
+#[derive(Debug, Serialize)]
+pub struct TcpProbeResult {
+    pub target: String,
+    pub port: u16,
+    pub timestamp: String,
+    pub latency_ms: f64,
+}
+
+/// Connects to `addr`, through `interface` if given, and measures how long the handshake takes
+pub async fn tcp_probe(
+    addr: SocketAddr,
+    timeout: Duration,
+    interface: Option<&str>,
+) -> Result<TcpProbeResult> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if let Some(interface) = interface {
+        bind_to_interface(&socket, interface, addr.ip())?;
+    }
+    socket.set_nonblocking(true)?;
+    let socket = TcpSocket::from_std_stream(std::net::TcpStream::from(socket));
+
+    let timestamp = unix_timestamp();
+    let start = Instant::now();
+    tokio::time::timeout(timeout, socket.connect(addr))
+        .await
+        .map_err(|_| anyhow!("Connecting took longer than {:?}", timeout))??;
+    Ok(TcpProbeResult {
+        target: addr.ip().to_string(),
+        port: addr.port(),
+        timestamp,
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+/// Probes all `addrs` every `period` and appends the results to `outfile` as JSON lines
+pub async fn tcp_prober(
+    addrs: Vec<SocketAddr>,
+    period: Duration,
+    timeout: Duration,
+    interface: Option<String>,
+    mut outfile: Writer,
+    metrics: SharedMetrics,
+) -> Result<()> {
+    let mut iv = interval(period);
+    loop {
+        iv.tick().await;
+        for addr in &addrs {
+            let record = match tcp_probe(*addr, timeout, interface.as_deref()).await {
+                Ok(result) => {
+                    debug!("TCP probe: {:?}", result);
+                    metrics
+                        .lock()
+                        .unwrap()
+                        .tcp_probe_latency_ms
+                        .insert(*addr, result.latency_ms);
+                    serde_json::to_value(&result)?
+                }
+                Err(err) => {
+                    warn!("TCP probe of {} failed: {}", addr, err);
+                    metrics.lock().unwrap().tcp_probe_latency_ms.remove(addr);
+                    json!({
+                        "target": addr.ip().to_string(),
+                        "port": addr.port(),
+                        "timestamp": unix_timestamp(),
+                        "error": err.to_string(),
+                    })
+                }
+            };
+            outfile.write_all(format!("{}\n", record).as_bytes())?;
+            outfile.flush()?;
+        }
+    }
+}
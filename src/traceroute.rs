@@ -0,0 +1,92 @@
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use anyhow::{anyhow, Result};
+use chrono::{SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::sinks::SinkFactory;
+
+/// A router on the path to a target
+#[derive(Debug, Serialize)]
+pub struct HopResult {
+    pub hop: u8,
+    /// Unset if the hop didn't answer
+    pub ip: Option<IpAddr>,
+    pub rtt_ms: Option<f64>,
+}
+
+/// The path to a target when it became degraded or offline
+#[derive(Debug, Serialize)]
+struct Traceroute {
+    target: String,
+    timestamp: String,
+    hops: Vec<HopResult>,
+}
+
+/// Parses a hop line of `traceroute -n -q 1` like ` 3  10.0.0.1  4.512 ms`
+fn parse_hop(line: &str) -> Option<HopResult> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"^\s*(\d+)\s+(?:(\S+)\s+(\d+(?:\.\d+)?) ms|\*)").unwrap();
+    }
+    let cap = RE.captures(line)?;
+    Some(HopResult {
+        hop: cap.get(1)?.as_str().parse().ok()?,
+        ip: cap.get(2).and_then(|ip| ip.as_str().parse().ok()),
+        rtt_ms: cap.get(3).and_then(|ms| ms.as_str().parse().ok()),
+    })
+}
+
+/// Lists the hops to `target` by running `traceroute`
+pub async fn traceroute(target: IpAddr, max_hops: u8) -> Result<Vec<HopResult>> {
+    let mut command = Command::new("traceroute");
+    if target.is_ipv6() {
+        command.arg("-6");
+    }
+    let output = command
+        .args(["-n", "-q", "1", "-w", "1", "-m"])
+        .arg(max_hops.to_string())
+        .arg(target.to_string())
+        .stderr(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!("traceroute failed with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_hop)
+        .collect())
+}
+
+/// Traces the route to `target` and appends it to `log`
+pub async fn trace_to_log(target: String, max_hops: u8, sinks: &dyn SinkFactory, log: PathBuf) {
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let hops = match crate::ping::resolve(&target).await {
+        Ok(addr) => traceroute(addr, max_hops).await,
+        Err(err) => Err(err),
+    };
+    let result = hops.and_then(|hops| {
+        debug!("{} hops to {}", hops.len(), target);
+        let mut outfile = sinks.append(&log)?;
+        let record = Traceroute {
+            target: target.clone(),
+            timestamp,
+            hops,
+        };
+        serde_json::to_writer(&mut outfile, &record)?;
+        outfile.write_all(b"\n")?;
+        outfile.flush()?;
+        Ok(())
+    });
+    if let Err(err) = result {
+        warn!(%target, "Couldn't trace route: {}", err);
+    }
+}
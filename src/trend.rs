@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::speedtest::SpeedtestResult;
+use crate::stats::StatsWindow;
+
+/// Latest speedtest compared to the ones before it
+#[derive(Debug, Serialize)]
+struct TrendSummary<'a> {
+    timestamp: &'a str,
+    /// Number of earlier speedtests averaged
+    window: usize,
+    download_bps: f64,
+    upload_bps: f64,
+    download_avg_bps: f64,
+    upload_avg_bps: f64,
+    /// Difference of the latest speedtest to the average, negative if slower
+    download_change_pct: f64,
+    upload_change_pct: f64,
+}
+
+/// Download and upload speeds of the recent speedtests
+pub struct TrendTracker {
+    downloads: StatsWindow<f64>,
+    uploads: StatsWindow<f64>,
+}
+
+fn change_pct(latest: f64, avg: f64) -> f64 {
+    if avg > 0.0 {
+        100.0 * (latest - avg) / avg
+    } else {
+        0.0
+    }
+}
+
+impl TrendTracker {
+    /// Starts from the last `size` speedtests in the speedtest log
+    pub fn load(log: &Path, size: usize) -> Self {
+        let mut tracker = Self {
+            downloads: StatsWindow::new(size),
+            uploads: StatsWindow::new(size),
+        };
+        if let Ok(file) = File::open(log) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Ok(result) = serde_json::from_str::<SpeedtestResult>(&line) {
+                    tracker.push(result.download, result.upload);
+                }
+            }
+        }
+        tracker
+    }
+
+    fn push(&mut self, download: f64, upload: f64) {
+        self.downloads.push(download);
+        self.uploads.push(upload);
+    }
+
+    /// Compares `result` to the recent speedtests, warns if it is much slower and
+    /// writes the comparison to `speed_trend`
+    pub fn record(&mut self, config: &Config, result: &SpeedtestResult) -> Result<()> {
+        let window = self.downloads.len();
+        if window > 0 {
+            let download_avg = self.downloads.mean();
+            let upload_avg = self.uploads.mean();
+            let summary = TrendSummary {
+                timestamp: &result.timestamp,
+                window,
+                download_bps: result.download,
+                upload_bps: result.upload,
+                download_avg_bps: download_avg,
+                upload_avg_bps: upload_avg,
+                download_change_pct: change_pct(result.download, download_avg),
+                upload_change_pct: change_pct(result.upload, upload_avg),
+            };
+            for (direction, change) in [
+                ("Download", summary.download_change_pct),
+                ("Upload", summary.upload_change_pct),
+            ] {
+                if -change > config.trend_threshold {
+                    warn!(
+                        change_pct = change,
+                        window,
+                        "{} speed {:.0} % below the average of the last speedtests",
+                        direction,
+                        -change
+                    );
+                }
+            }
+            config
+                .sinks()
+                .replace(&config.speed_trend, &serde_json::to_vec_pretty(&summary)?)?;
+        } else {
+            info!("First speedtest, no trend yet");
+        }
+        self.push(result.download, result.upload);
+        Ok(())
+    }
+}
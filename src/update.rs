@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use semver::Version;
+use serde::Deserialize;
+
+/// Latest published release
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/Schaback/con-mon/releases/latest";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+}
+
+/// Prints whether a newer release than the running version is available
+pub async fn check_update() -> Result<()> {
+    let release: Release = reqwest::Client::new()
+        .get(LATEST_RELEASE_URL)
+        // Required by the GitHub API
+        .header("User-Agent", concat!("con-mon/", env!("CARGO_PKG_VERSION")))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let latest = Version::parse(release.tag_name.trim_start_matches('v'))
+        .with_context(|| format!("Release tag {} is no version", release.tag_name))?;
+    let running = Version::parse(env!("CARGO_PKG_VERSION"))?;
+    if latest > running {
+        println!(
+            "con-mon {} is available, running {}. Download it from {}",
+            latest, running, release.html_url
+        );
+    } else {
+        println!("con-mon {} is the latest version", running);
+    }
+    Ok(())
+}
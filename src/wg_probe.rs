@@ -0,0 +1,150 @@
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::json;
+use tokio::process::Command;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use crate::ping::unix_timestamp;
+use crate::sinks::Writer;
+
+lazy_static! {
+    /// One unit of `latest handshake: 1 day, 2 hours, 3 minutes, 4 seconds ago`
+    static ref AGE_PART: Regex =
+        Regex::new(r"(\d+) (second|minute|hour|day|week|year)s?").unwrap();
+}
+
+/// A peer of a WireGuard interface
+#[derive(Debug, Serialize)]
+pub struct WgPeer {
+    pub public_key: String,
+    pub endpoint: Option<String>,
+    /// Seconds since the latest handshake, `None` if there never was one
+    pub handshake_age_s: Option<u64>,
+}
+
+/// Handshake ages of the peers of a WireGuard interface
+#[derive(Debug, Serialize)]
+pub struct WgProbeResult {
+    pub interface: String,
+    pub timestamp: String,
+    pub peers: Vec<WgPeer>,
+}
+
+/// Parses the age of `latest handshake`, `Now` being 0
+fn parse_age(age: &str) -> Option<u64> {
+    if age.eq_ignore_ascii_case("now") {
+        return Some(0);
+    }
+    let mut seconds = None;
+    for part in AGE_PART.captures_iter(age) {
+        let count: u64 = part[1].parse().ok()?;
+        let unit = match &part[2] {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            _ => 365 * 24 * 60 * 60,
+        };
+        seconds = Some(seconds.unwrap_or(0) + count * unit);
+    }
+    seconds
+}
+
+/// Parses the peers out of the output of `wg show`
+fn parse_peers(output: &str) -> Vec<WgPeer> {
+    let mut peers = Vec::new();
+    for line in output.lines() {
+        let Some((key, value)) = line.trim().split_once(": ") else {
+            continue;
+        };
+        match (key, peers.last_mut()) {
+            ("peer", _) => peers.push(WgPeer {
+                public_key: value.to_string(),
+                endpoint: None,
+                handshake_age_s: None,
+            }),
+            ("endpoint", Some(peer)) => peer.endpoint = Some(value.to_string()),
+            ("latest handshake", Some(peer)) => {
+                peer.handshake_age_s = parse_age(value.trim_end_matches(" ago"))
+            }
+            _ => {}
+        }
+    }
+    peers
+}
+
+/// Reads the handshake ages of `interface` with `wg show`
+///
+/// Needs no root on Linux if the user is in the `wireguard` group.
+pub async fn wg_probe(interface: &str) -> Result<WgProbeResult> {
+    let timestamp = unix_timestamp();
+    let output = Command::new("wg")
+        .args(["show", interface])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "wg show failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(WgProbeResult {
+        interface: interface.to_string(),
+        timestamp,
+        peers: parse_peers(&String::from_utf8_lossy(&output.stdout)),
+    })
+}
+
+/// Probes every interface each `period` and appends the results to `outfile`
+pub async fn wg_prober(
+    interfaces: Vec<String>,
+    period: Duration,
+    max_age: Duration,
+    mut outfile: Writer,
+) -> Result<()> {
+    let mut iv = interval(period);
+    loop {
+        iv.tick().await;
+        for interface in &interfaces {
+            let record = match wg_probe(interface).await {
+                Ok(result) => {
+                    debug!("WireGuard probe: {:?}", result);
+                    for peer in &result.peers {
+                        match peer.handshake_age_s {
+                            Some(age) if age <= max_age.as_secs() => {}
+                            Some(age) => warn!(
+                                "Latest WireGuard handshake with {} on {} was {} s ago, above {} s",
+                                peer.public_key,
+                                interface,
+                                age,
+                                max_age.as_secs()
+                            ),
+                            None => warn!(
+                                "No WireGuard handshake with {} on {} yet",
+                                peer.public_key, interface
+                            ),
+                        }
+                    }
+                    serde_json::to_value(&result)?
+                }
+                Err(err) => {
+                    warn!("Probing WireGuard interface {} failed: {}", interface, err);
+                    json!({
+                        "interface": interface,
+                        "timestamp": unix_timestamp(),
+                        "error": err.to_string(),
+                    })
+                }
+            };
+            outfile.write_all(format!("{}\n", record).as_bytes())?;
+            outfile.flush()?;
+        }
+    }
+}